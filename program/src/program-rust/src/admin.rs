@@ -0,0 +1,475 @@
+//! Multisig admin with timelocked proposals
+//!
+//! A treasury's authority can hand its admin rights to an M-of-N multisig
+//! PDA `["admin", treasury]`, which then becomes the treasury's authority.
+//! From then on fee, Kelly fraction, pause, lockup, deposit limit, keeper
+//! fee, randomness source, allowlist and authority changes are proposed
+//! into a proposal PDA `["proposal", multisig, index]`, approved by the
+//! multisig's signers, and executed by anyone once enough signers approved
+//! and the multisig's delay has passed since the proposal. Every executed
+//! proposal is logged as one line:
+//!
+//! ```text
+//! EXECUTED treasury=<address> proposal=<index> action=<tag>
+//! ```
+
+use crate::{
+    crank::MAX_KEEPER_FEE_BPS,
+    deposit_limits::set_allowed,
+    error::RollError,
+    instruction::{AdminAction, TreasuryKind},
+    odds::BPS,
+    pause::PAUSE_ALL,
+    randomness::set_randomness,
+    state::{FeeVault, Multisig, Pool, Proposal, Treasury, ADMIN_SEED, MAX_SIGNERS, PROPOSAL_SEED},
+    token_pool::create_pda_account,
+    treasury::load_treasury,
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar, assert_writable},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, clock::Clock, rent, rent::Rent, Sysvar},
+};
+
+/// Fails if `action` could never be applied
+pub fn validate_action(action: &AdminAction) -> ProgramResult {
+    let valid = match action {
+        AdminAction::SetFees {
+            protocol_fee_bps,
+            referrer_fee_bps,
+        } => *protocol_fee_bps as u64 + *referrer_fee_bps as u64 <= BPS,
+        AdminAction::SetKellyFraction { kelly_fraction_bps } => *kelly_fraction_bps as u64 <= BPS,
+        AdminAction::SetPaused { paused } => paused & !PAUSE_ALL == 0,
+        AdminAction::SetAuthority { .. } => true,
+        AdminAction::SetLockup { early_exit_fee_bps, .. } => *early_exit_fee_bps as u64 <= BPS,
+        AdminAction::SetDepositLimits { .. } => true,
+        AdminAction::SetAllowed { .. } => true,
+        AdminAction::SetRandomness { .. } => true,
+        AdminAction::SetKeeperFee { keeper_fee_bps } => *keeper_fee_bps <= MAX_KEEPER_FEE_BPS,
+    };
+    if !valid {
+        info!("SolanaRoll invalid admin action");
+        return Err(RollError::InvalidAmount.into());
+    }
+    Ok(())
+}
+
+/// Load an initialized multisig
+fn load_multisig(program_id: &Pubkey, multisig_account: &AccountInfo) -> Result<Multisig, ProgramError> {
+    assert_program_account(multisig_account, program_id)?;
+    let multisig = Multisig::unpack(&multisig_account.data.borrow())?;
+    if !multisig.is_initialized {
+        info!("SolanaRoll multisig is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(multisig)
+}
+
+/// Load an initialized proposal of `multisig_account`
+fn load_proposal(
+    program_id: &Pubkey,
+    multisig_account: &AccountInfo,
+    proposal_account: &AccountInfo,
+) -> Result<Proposal, ProgramError> {
+    assert_program_account(proposal_account, program_id)?;
+    let proposal = Proposal::unpack(&proposal_account.data.borrow())?;
+    if !proposal.is_initialized {
+        info!("SolanaRoll proposal is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if proposal.multisig != *multisig_account.key {
+        info!("SolanaRoll proposal belongs to another multisig");
+        return Err(RollError::IncorrectAddress.into());
+    }
+    if proposal.executed {
+        return Err(RollError::ProposalExecuted.into());
+    }
+    Ok(proposal)
+}
+
+/// Load the fee vault of `multisig`'s treasury, a fee change's target
+fn load_fee_vault(program_id: &Pubkey, multisig: &Multisig, target_account: &AccountInfo) -> Result<FeeVault, ProgramError> {
+    assert_program_account(target_account, program_id)?;
+    let (fee_vault_address, _) = FeeVault::find_address(program_id, &multisig.treasury);
+    assert_key(target_account, &fee_vault_address)?;
+    let fee_vault = FeeVault::unpack(&target_account.data.borrow())?;
+    if !fee_vault.is_initialized {
+        info!("SolanaRoll fee vault is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(fee_vault)
+}
+
+/// Apply a change to `multisig`'s treasury in `target_account`: the
+/// treasury state of a lamport treasury, updated by `update_treasury`, or
+/// the token pool, updated by `update_pool`
+fn update_target(
+    program_id: &Pubkey,
+    multisig: &Multisig,
+    target_account: &AccountInfo,
+    update_treasury: impl FnOnce(&mut Treasury) -> ProgramResult,
+    update_pool: impl FnOnce(&mut Pool) -> ProgramResult,
+) -> ProgramResult {
+    assert_program_account(target_account, program_id)?;
+    if multisig.kind == TreasuryKind::Lamports as u8 {
+        let (state_address, _) = Treasury::find_address(program_id, &multisig.treasury);
+        assert_key(target_account, &state_address)?;
+        let mut treasury = Treasury::unpack(&target_account.data.borrow())?;
+        if !treasury.is_initialized {
+            info!("SolanaRoll treasury state is not initialized");
+            return Err(ProgramError::UninitializedAccount);
+        }
+        update_treasury(&mut treasury)?;
+        treasury.pack(&mut target_account.data.borrow_mut())
+    } else {
+        assert_key(target_account, &multisig.treasury)?;
+        let mut pool = Pool::unpack_initialized(&target_account.data.borrow())?;
+        update_pool(&mut pool)?;
+        pool.pack(&mut target_account.data.borrow_mut())
+    }
+}
+
+/// Approval bit of a signing `signer_account`
+fn signer_bit(multisig: &Multisig, signer_account: &AccountInfo) -> Result<u8, ProgramError> {
+    assert_signer(signer_account)?;
+    multisig
+        .signer_index(signer_account.key)
+        .map(|index| 1 << index)
+        .ok_or_else(|| RollError::NotAdminSigner.into())
+}
+
+/// 20 - hand a treasury's admin rights to a new multisig
+///
+/// The multisig becomes the treasury's authority. Data: [1] treasury kind,
+/// 0 lamports or 1 token pool, [2] approvals needed, [3..11] delay in slots
+/// between proposal and execution. Accounts: payer (signer), authority
+/// (signer), treasury or pool, multisig, rent sysvar, system program, then
+/// the treasury state for lamport treasuries, then up to 8 signers, each
+/// signing so no key is listed without its holder
+pub fn process_init_admin(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 11 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let threshold = data[2];
+    let delay_slots = LittleEndian::read_u64(&data[3..11]);
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let multisig_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_signer(authority_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let (multisig_address, bump_seed) = Multisig::find_address(program_id, treasury_account.key);
+    assert_key(multisig_account, &multisig_address)?;
+
+    // Hand over the treasury first, the remaining accounts are the signers
+    match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            let mut treasury = load_treasury(program_id, treasury_account, state_account)?;
+            assert_key(authority_account, &treasury.authority)?;
+            treasury.authority = multisig_address;
+            treasury.pack(&mut state_account.data.borrow_mut())?;
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            let mut pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(authority_account, &pool.authority)?;
+            pool.authority = multisig_address;
+            pool.pack(&mut treasury_account.data.borrow_mut())?;
+        }
+    }
+
+    let mut signers = [Pubkey::default(); MAX_SIGNERS];
+    let mut signer_count = 0;
+    for signer_account in accounts_iter {
+        assert_signer(signer_account)?;
+        if signer_count == MAX_SIGNERS || signers[..signer_count].contains(signer_account.key) {
+            info!("SolanaRoll too many or repeated multisig signers");
+            return Err(ProgramError::InvalidArgument);
+        }
+        signers[signer_count] = *signer_account.key;
+        signer_count += 1;
+    }
+    if threshold == 0 || threshold as usize > signer_count {
+        info!("SolanaRoll multisig threshold must be between 1 and the signer count");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    info!("Creating multisig admin");
+    create_pda_account(
+        payer_account,
+        multisig_account,
+        system_program_account,
+        program_id,
+        Multisig::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[ADMIN_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let multisig = Multisig {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury_account.key,
+        kind: kind as u8,
+        threshold,
+        signer_count: signer_count as u8,
+        signers,
+        delay_slots,
+        proposal_count: 0,
+    };
+    multisig.pack(&mut multisig_account.data.borrow_mut())
+}
+
+/// 21 - propose an admin action, approved by its proposer
+///
+/// Data: [1..35] the action, see `AdminAction`. Accounts: payer (signer),
+/// proposer (signer), multisig, proposal for the multisig's next index,
+/// clock sysvar, rent sysvar, system program
+pub fn process_propose(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let action = AdminAction::unpack(data.get(1..).unwrap_or_default())?;
+    validate_action(&action)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let proposer_account = next_account_info(accounts_iter)?;
+    let multisig_account = next_account_info(accounts_iter)?;
+    let proposal_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_writable(multisig_account)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let mut multisig = load_multisig(program_id, multisig_account)?;
+    let approvals = signer_bit(&multisig, proposer_account)?;
+    let index = multisig.proposal_count;
+    let (proposal_address, bump_seed) = Proposal::find_address(program_id, multisig_account.key, index);
+    assert_key(proposal_account, &proposal_address)?;
+
+    info!("Creating proposal");
+    create_pda_account(
+        payer_account,
+        proposal_account,
+        system_program_account,
+        program_id,
+        Proposal::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[PROPOSAL_SEED, multisig_account.key.as_ref(), &index.to_le_bytes(), &[bump_seed]],
+    )?;
+
+    let slot = Clock::from_account_info(clock_account)?.slot;
+    let proposal = Proposal {
+        is_initialized: true,
+        bump_seed,
+        multisig: *multisig_account.key,
+        index,
+        action: action.pack(),
+        eta_slot: slot.saturating_add(multisig.delay_slots),
+        approvals,
+        executed: false,
+    };
+    proposal.pack(&mut proposal_account.data.borrow_mut())?;
+
+    multisig.proposal_count += 1;
+    multisig.pack(&mut multisig_account.data.borrow_mut())
+}
+
+/// 22 - approve a pending proposal
+///
+/// Accounts: signer (signer), multisig, proposal
+pub fn process_approve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let signer_account = next_account_info(accounts_iter)?;
+    let multisig_account = next_account_info(accounts_iter)?;
+    let proposal_account = next_account_info(accounts_iter)?;
+
+    let multisig = load_multisig(program_id, multisig_account)?;
+    let mut proposal = load_proposal(program_id, multisig_account, proposal_account)?;
+    proposal.approvals |= signer_bit(&multisig, signer_account)?;
+    proposal.pack(&mut proposal_account.data.borrow_mut())
+}
+
+/// 23 - execute an approved proposal once its delay has passed
+///
+/// Anyone may execute. Accounts: multisig, proposal, clock sysvar, target:
+/// the fee vault for fee changes, the depositor's allowlist entry for
+/// allowlist changes, otherwise the treasury state of a lamport treasury or
+/// the token pool, then for allowlist changes the payer (signer), rent
+/// sysvar and system program, and for randomness changes the pool's slot
+/// hashes sysvar, house chain or VRF queue
+pub fn process_execute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let multisig_account = next_account_info(accounts_iter)?;
+    let proposal_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let target_account = next_account_info(accounts_iter)?;
+
+    assert_sysvar(clock_account, &clock::id())?;
+    let multisig = load_multisig(program_id, multisig_account)?;
+    let mut proposal = load_proposal(program_id, multisig_account, proposal_account)?;
+
+    let slot = Clock::from_account_info(clock_account)?.slot;
+    if proposal.approval_count() < multisig.threshold as u32 || slot < proposal.eta_slot {
+        return Err(RollError::ProposalNotReady.into());
+    }
+
+    let action = AdminAction::unpack(&proposal.action)?;
+    match action {
+        AdminAction::SetFees {
+            protocol_fee_bps,
+            referrer_fee_bps,
+        } => {
+            let mut fee_vault = load_fee_vault(program_id, &multisig, target_account)?;
+            fee_vault.protocol_fee_bps = protocol_fee_bps;
+            fee_vault.referrer_fee_bps = referrer_fee_bps;
+            fee_vault.pack(&mut target_account.data.borrow_mut())?;
+        }
+        AdminAction::SetKellyFraction { kelly_fraction_bps } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.kelly_fraction_bps = kelly_fraction_bps;
+                Ok(())
+            },
+            |pool| {
+                pool.kelly_fraction_bps = kelly_fraction_bps;
+                Ok(())
+            },
+        )?,
+        AdminAction::SetPaused { paused } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.paused = paused;
+                Ok(())
+            },
+            |pool| {
+                pool.paused = paused;
+                Ok(())
+            },
+        )?,
+        AdminAction::SetAuthority { authority } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.authority = authority;
+                Ok(())
+            },
+            |pool| {
+                pool.authority = authority;
+                Ok(())
+            },
+        )?,
+        AdminAction::SetLockup {
+            lockup_slots,
+            early_exit_fee_bps,
+        } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.lockup_slots = lockup_slots;
+                treasury.early_exit_fee_bps = early_exit_fee_bps;
+                Ok(())
+            },
+            |pool| {
+                pool.lockup_slots = lockup_slots;
+                pool.early_exit_fee_bps = early_exit_fee_bps;
+                Ok(())
+            },
+        )?,
+        AdminAction::SetDepositLimits {
+            max_liquidity,
+            allowlist_only,
+        } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.max_liquidity = max_liquidity;
+                treasury.allowlist_only = allowlist_only;
+                Ok(())
+            },
+            |pool| {
+                pool.max_liquidity = max_liquidity;
+                pool.allowlist_only = allowlist_only;
+                Ok(())
+            },
+        )?,
+        AdminAction::SetAllowed { depositor, allowed } => {
+            let payer_account = next_account_info(accounts_iter)?;
+            let rent_account = next_account_info(accounts_iter)?;
+            let system_program_account = next_account_info(accounts_iter)?;
+            assert_signer(payer_account)?;
+            assert_sysvar(rent_account, &rent::id())?;
+            assert_key(system_program_account, &system_program::id())?;
+            set_allowed(
+                program_id,
+                &multisig.treasury,
+                &depositor,
+                allowed,
+                payer_account,
+                target_account,
+                system_program_account,
+                &Rent::from_account_info(rent_account)?,
+            )?
+        }
+        AdminAction::SetRandomness { randomness } => {
+            let source_account = next_account_info(accounts_iter)?;
+            update_target(
+                program_id,
+                &multisig,
+                target_account,
+                |_| {
+                    info!("SolanaRoll lamport treasuries roll on slot hashes");
+                    Err(ProgramError::InvalidArgument)
+                },
+                |pool| set_randomness(program_id, &multisig.treasury, pool, randomness, source_account),
+            )?
+        }
+        AdminAction::SetKeeperFee { keeper_fee_bps } => update_target(
+            program_id,
+            &multisig,
+            target_account,
+            |treasury| {
+                treasury.keeper_fee_bps = keeper_fee_bps;
+                Ok(())
+            },
+            |pool| {
+                pool.keeper_fee_bps = keeper_fee_bps;
+                Ok(())
+            },
+        )?,
+    }
+
+    proposal.executed = true;
+    proposal.pack(&mut proposal_account.data.borrow_mut())?;
+
+    info!(&format!(
+        "EXECUTED treasury={} proposal={} action={}",
+        multisig.treasury, proposal.index, proposal.action[0]
+    ));
+    Ok(())
+}
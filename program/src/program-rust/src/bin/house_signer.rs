@@ -0,0 +1,77 @@
+//! Reference house signer for house-seeded token pools
+//!
+//! Derives a pool's seed chain from a secret file and signs each game's seed
+//! for the Ed25519 instruction placed before its `PostHouseSeed` or settle.
+//!
+//!     house-signer commit <secret-file> <length>
+//!     house-signer sign <keypair-file> <secret-file> <length> <number> <game>
+//!
+//! `commit` prints the chain head for `InitHouseChain`. `sign` prints seed
+//! `number`, counted from 1 in commit order, the house's signature and the
+//! Ed25519 program instruction data, all hex encoded.
+
+use solana_bpf_helloworld::house_seed::{chain_seed, ed25519_instruction_data, message};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Signer},
+};
+use std::{env, fs, process, str::FromStr};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn usage() -> ! {
+    eprintln!("usage: house-signer commit <secret-file> <length>");
+    eprintln!("       house-signer sign <keypair-file> <secret-file> <length> <number> <game>");
+    process::exit(1);
+}
+
+fn read_secret(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("cannot read {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn parse<T: FromStr>(arg: &str) -> T {
+    arg.parse().unwrap_or_else(|_| {
+        eprintln!("invalid argument {}", arg);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.get(0).map(String::as_str) {
+        Some("commit") if args.len() == 3 => {
+            let secret = read_secret(&args[1]);
+            let length: u64 = parse(&args[2]);
+            println!("head {}", hex(&chain_seed(&secret, length, 0)));
+        }
+        Some("sign") if args.len() == 6 => {
+            let house = read_keypair_file(&args[1]).unwrap_or_else(|err| {
+                eprintln!("cannot read keypair {}: {}", args[1], err);
+                process::exit(1);
+            });
+            let secret = read_secret(&args[2]);
+            let length: u64 = parse(&args[3]);
+            let number: u64 = parse(&args[4]);
+            let game: Pubkey = parse(&args[5]);
+            if number == 0 || number > length {
+                eprintln!("seed number must be between 1 and {}", length);
+                process::exit(1);
+            }
+
+            let seed = chain_seed(&secret, length, number);
+            let signed = message(&game, &seed);
+            let signature = house.sign_message(&signed);
+            let data = ed25519_instruction_data(&house.pubkey(), signature.as_ref(), &signed);
+            println!("house {}", house.pubkey());
+            println!("seed {}", hex(&seed));
+            println!("signature {}", hex(signature.as_ref()));
+            println!("ed25519 {}", hex(&data));
+        }
+        _ => usage(),
+    }
+}
@@ -0,0 +1,180 @@
+//! Reference keeper cranking abandoned games
+//!
+//! Polls the program's accounts and cranks every committed game past its
+//! crank delay, earning the keeper fee of its treasury or pool.
+//!
+//!     keeper <keypair-file> <program-id> <treasury> [rpc-url] [keeper-token-account...]
+//!
+//! Lamport games are cranked against `<treasury>`, up to `MAX_BATCH_GAMES`
+//! a transaction with `SettleBatch`. Token games are cranked one at a time
+//! for the mints the keeper passed a token account of, house chain games
+//! without a seed, rolling on the seed the house posted or paying the player
+//! if it never did. The RPC URL defaults to a local validator.
+
+use solana_bpf_helloworld::{
+    cpi,
+    crank::{is_crankable, MAX_BATCH_GAMES},
+    state::{Game, GameStatus, Pool},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, env, process, str::FromStr, thread, time::Duration};
+
+const DEFAULT_RPC_URL: &str = "http://localhost:8899";
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+fn usage() -> ! {
+    eprintln!("usage: keeper <keypair-file> <program-id> <treasury> [rpc-url] [keeper-token-account...]");
+    process::exit(1);
+}
+
+fn parse_pubkey(arg: &str) -> Pubkey {
+    Pubkey::from_str(arg).unwrap_or_else(|_| {
+        eprintln!("invalid address {}", arg);
+        process::exit(1);
+    })
+}
+
+/// Committed games of the program
+fn scan(accounts: &[(Pubkey, Account)]) -> Vec<(Pubkey, Game)> {
+    accounts
+        .iter()
+        .filter_map(|(key, account)| match Game::unpack(&account.data) {
+            Ok(game) if game.status == GameStatus::Committed => Some((*key, game)),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Keeper {
+    client: RpcClient,
+    keypair: Keypair,
+    program_id: Pubkey,
+    treasury: Pubkey,
+    /// Keeper token account of each mint
+    token_accounts: HashMap<Pubkey, Pubkey>,
+}
+
+impl Keeper {
+    /// Crank instruction of the token game `game`, if the keeper can crank it
+    fn token_instruction(&self, game_key: &Pubkey, game: &Game) -> Option<Instruction> {
+        let pool = Pool::unpack(&self.client.get_account(&game.pool).ok()?.data).ok()?;
+        let keeper_tokens = self.token_accounts.get(&pool.mint)?;
+        let referrer_tokens = if game.referrer == Pubkey::default() {
+            None
+        } else {
+            Some(&game.referrer)
+        };
+        Some(cpi::token_crank(
+            &self.program_id,
+            &self.keypair.pubkey(),
+            keeper_tokens,
+            game_key,
+            &pool.mint,
+            &game.player,
+            pool.randomness,
+            None,
+            referrer_tokens,
+        ))
+    }
+
+    fn crank(&self, instruction: Instruction) -> Result<(), String> {
+        let (blockhash, _) = self.client.get_recent_blockhash().map_err(|err| err.to_string())?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.keypair.pubkey()),
+            &[&self.keypair],
+            blockhash,
+        );
+        let signature = self
+            .client
+            .send_and_confirm_transaction(&transaction)
+            .map_err(|err| err.to_string())?;
+        println!("cranked {}", signature);
+        Ok(())
+    }
+
+    fn poll(&self) -> Result<(), String> {
+        let slot = self.client.get_slot().map_err(|err| err.to_string())?;
+        let accounts = self
+            .client
+            .get_program_accounts(&self.program_id)
+            .map_err(|err| err.to_string())?;
+        let games = scan(&accounts);
+
+        let mut batch = vec![];
+        for (game_key, game) in games.iter().filter(|(_, game)| is_crankable(game, slot)) {
+            if game.pool == Pubkey::default() {
+                if game.treasury == self.treasury {
+                    batch.push((*game_key, game.player, game.fund));
+                }
+            } else if let Some(instruction) = self.token_instruction(game_key, game) {
+                println!("cranking game {}", game_key);
+                if let Err(err) = self.crank(instruction) {
+                    eprintln!("crank of game {} failed: {}", game_key, err);
+                }
+            }
+        }
+        for games in batch.chunks(MAX_BATCH_GAMES) {
+            println!("cranking {} lamport games", games.len());
+            let instruction = cpi::settle_batch(&self.program_id, &self.keypair.pubkey(), &self.treasury, games);
+            if let Err(err) = self.crank(instruction) {
+                eprintln!("batch crank failed: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 3 {
+        usage();
+    }
+    let keypair = read_keypair_file(&args[0]).unwrap_or_else(|err| {
+        eprintln!("cannot read keypair {}: {}", args[0], err);
+        process::exit(1);
+    });
+    let program_id = parse_pubkey(&args[1]);
+    let treasury = parse_pubkey(&args[2]);
+    let url = args.get(3).map(String::as_str).unwrap_or(DEFAULT_RPC_URL);
+    let client = RpcClient::new(url.to_string());
+
+    let mut token_accounts = HashMap::new();
+    for arg in args.iter().skip(4) {
+        let key = parse_pubkey(arg);
+        let mint = client
+            .get_account(&key)
+            .ok()
+            .and_then(|account| TokenAccount::unpack(&account.data).ok())
+            .map(|account| account.mint)
+            .unwrap_or_else(|| {
+                eprintln!("{} is not a token account", key);
+                process::exit(1);
+            });
+        token_accounts.insert(mint, key);
+    }
+
+    let keeper = Keeper {
+        client,
+        keypair,
+        program_id,
+        treasury,
+        token_accounts,
+    };
+    println!("keeper {} watching program {}", keeper.keypair.pubkey(), program_id);
+    loop {
+        if let Err(err) = keeper.poll() {
+            eprintln!("poll failed: {}", err);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,509 @@
+//! Interface for programs placing and settling bets by CPI
+//!
+//! Tournament, bonus and other programs can play against a treasury through
+//! the four game instructions, and keepers crank abandoned games. The
+//! builders here lay out their command bytes, data and accounts, deriving
+//! the PDAs the program reads, for clients. The `*Accounts` helpers invoke them from another program with
+//! its signer seeds, so a PDA of the calling program can be the player.
+//! Programs depending on this crate enable its `no-entrypoint` feature.
+//!
+//! Account order is part of the interface: new accounts are only ever
+//! appended after the ones listed here. Token pools rolling on a house chain
+//! or VRF queue take it in place of the slot hashes sysvar, a house chain
+//! followed on settle by the instructions sysvar.
+
+use crate::{
+    instruction::{Commit, TreasuryKind},
+    state::{
+        FeeVault, HouseChain, PlayerStats, Pool, PoolOracle, Randomness, Referral, ReferrerStats,
+        RollHistory, Treasury, VrfQueue, FEE_TOKENS_SEED, VAULT_SEED,
+    },
+};
+use solana_sdk::{
+    account_info::AccountInfo,
+    entrypoint_deprecated::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    pubkey::Pubkey,
+    sysvar::{clock, instructions, slot_hashes},
+};
+
+/// 0 - commit a lamport bet by `player`, who must sign
+///
+/// `game` and `fund` are program owned accounts the player created, the
+/// fund blank so the commit can link it to the game; a bet without a roll
+/// count wagers whatever `fund` holds at settle.
+pub fn commit(
+    program_id: &Pubkey,
+    player: &Pubkey,
+    game: &Pubkey,
+    fund: &Pubkey,
+    treasury: &Pubkey,
+    bet: &Commit,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*player, true),
+            AccountMeta::new(*game, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new_readonly(slot_hashes::id(), false),
+            AccountMeta::new(*fund, false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new(Treasury::find_address(program_id, treasury).0, false),
+            AccountMeta::new_readonly(Referral::find_address(program_id, player).0, false),
+        ],
+        data: bet.pack(0),
+    }
+}
+
+/// 1 - settle a lamport game committed by `player`, paying `recipient`
+///
+/// Only the player may settle and must sign. `referrer` is the referrer
+/// recorded at commit, if any.
+#[allow(clippy::too_many_arguments)]
+pub fn settle(
+    program_id: &Pubkey,
+    player: &Pubkey,
+    game: &Pubkey,
+    fund: &Pubkey,
+    treasury: &Pubkey,
+    recipient: &Pubkey,
+    reveal_number: u8,
+    referrer: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*player, true),
+        AccountMeta::new(*game, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(slot_hashes::id(), false),
+        AccountMeta::new(*fund, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new(Treasury::find_address(program_id, treasury).0, false),
+        AccountMeta::new(PlayerStats::find_address(program_id, player).0, false),
+        AccountMeta::new(RollHistory::find_address(program_id, treasury).0, false),
+        AccountMeta::new(PoolOracle::find_address(program_id, treasury).0, false),
+        AccountMeta::new(FeeVault::find_address(program_id, treasury).0, false),
+    ];
+    if let Some(referrer) = referrer {
+        accounts.push(AccountMeta::new(ReferrerStats::find_address(program_id, referrer).0, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: vec![1, reveal_number],
+    }
+}
+
+/// 5 - commit a bet against the token pool of `mint`, escrowing the wager
+/// from `player_tokens`, which `player` owns and settles pay. `randomness`
+/// is the pool's randomness source.
+#[allow(clippy::too_many_arguments)]
+pub fn token_commit(
+    program_id: &Pubkey,
+    player: &Pubkey,
+    game: &Pubkey,
+    mint: &Pubkey,
+    player_tokens: &Pubkey,
+    referrer_tokens: Option<&Pubkey>,
+    randomness: Randomness,
+    bet: &Commit,
+) -> Instruction {
+    let (pool, _) = Pool::find_address(program_id, mint);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*player, true),
+        AccountMeta::new(*game, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        randomness_account(program_id, &pool, randomness),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(vault_address(program_id, mint), false),
+        AccountMeta::new(*player_tokens, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    if let Some(referrer_tokens) = referrer_tokens {
+        accounts.push(AccountMeta::new_readonly(*referrer_tokens, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: bet.pack(5),
+    }
+}
+
+/// 6 - settle a token game against the pool of `mint`, paying the
+/// `player_tokens` it was committed with. Only `player`, the owner of
+/// `player_tokens`, may settle and must sign, except for games of a
+/// house-seeded pool, which any signer may settle. `randomness` is the
+/// pool's randomness source. Games of a house-seeded pool pass the game's
+/// house seed as `seed`, and the transaction verifies the house's signature
+/// of it right before this instruction, or pass none once the house posted
+/// the seed. Games of a VRF pool pass the player's secret committed with
+/// `Commit::secret_hash`.
+#[allow(clippy::too_many_arguments)]
+pub fn token_settle(
+    program_id: &Pubkey,
+    player: &Pubkey,
+    game: &Pubkey,
+    mint: &Pubkey,
+    player_tokens: &Pubkey,
+    reveal_number: u8,
+    randomness: Randomness,
+    seed: Option<&[u8; 32]>,
+    referrer_tokens: Option<&Pubkey>,
+) -> Instruction {
+    let (pool, _) = Pool::find_address(program_id, mint);
+    let (fee_tokens, _) =
+        Pubkey::find_program_address(&[FEE_TOKENS_SEED, pool.as_ref()], program_id);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*player, true),
+        AccountMeta::new(*game, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        randomness_account(program_id, &pool, randomness),
+    ];
+    if randomness == Randomness::HouseChain {
+        accounts.push(AccountMeta::new_readonly(instructions::id(), false));
+    }
+    let mut data = vec![6, reveal_number];
+    if let Some(seed) = seed {
+        data.extend_from_slice(seed);
+    }
+    accounts.extend(vec![
+        AccountMeta::new(pool, false),
+        AccountMeta::new(vault_address(program_id, mint), false),
+        AccountMeta::new(*player_tokens, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(PlayerStats::find_address(program_id, player_tokens).0, false),
+        AccountMeta::new(RollHistory::find_address(program_id, &pool).0, false),
+        AccountMeta::new(PoolOracle::find_address(program_id, &pool).0, false),
+        AccountMeta::new(FeeVault::find_address(program_id, &pool).0, false),
+        AccountMeta::new(fee_tokens, false),
+    ]);
+    if let Some(referrer_tokens) = referrer_tokens {
+        accounts.push(AccountMeta::new(*referrer_tokens, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    }
+}
+
+/// 34 - crank the lamport game of `player`, forfeiting the stake in the
+/// `fund` it was committed with and paying the `keeper` the treasury's
+/// keeper fee
+pub fn crank(
+    program_id: &Pubkey,
+    keeper: &Pubkey,
+    game: &Pubkey,
+    fund: &Pubkey,
+    treasury: &Pubkey,
+    player: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*keeper, true),
+            AccountMeta::new(*game, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new(*fund, false),
+            AccountMeta::new_readonly(*treasury, false),
+            AccountMeta::new(*player, false),
+            AccountMeta::new(Treasury::find_address(program_id, treasury).0, false),
+        ],
+        data: vec![34, TreasuryKind::Lamports as u8],
+    }
+}
+
+/// 34 - crank a token game against the pool of `mint`, paying the pool's
+/// keeper fee to `keeper_tokens`. The other arguments are those of
+/// `token_settle`.
+#[allow(clippy::too_many_arguments)]
+pub fn token_crank(
+    program_id: &Pubkey,
+    keeper: &Pubkey,
+    keeper_tokens: &Pubkey,
+    game: &Pubkey,
+    mint: &Pubkey,
+    player_tokens: &Pubkey,
+    randomness: Randomness,
+    seed: Option<&[u8; 32]>,
+    referrer_tokens: Option<&Pubkey>,
+) -> Instruction {
+    let mut instruction = token_settle(
+        program_id,
+        keeper,
+        game,
+        mint,
+        player_tokens,
+        0,
+        randomness,
+        seed,
+        referrer_tokens,
+    );
+    // The keeper signs in the player's place, followed by its token account
+    instruction.accounts.insert(1, AccountMeta::new(*keeper_tokens, false));
+    instruction.data[0] = 34;
+    instruction.data[1] = TreasuryKind::TokenPool as u8;
+    instruction
+}
+
+/// 36 - crank the lamport games of `treasury` in `games`, each a (game,
+/// player, fund) triple, paying the `keeper` the treasury's keeper fee.
+/// Transactions fit `crank::MAX_BATCH_GAMES` games with distinct accounts.
+pub fn settle_batch(
+    program_id: &Pubkey,
+    keeper: &Pubkey,
+    treasury: &Pubkey,
+    games: &[(Pubkey, Pubkey, Pubkey)],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*keeper, true),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new(Treasury::find_address(program_id, treasury).0, false),
+    ];
+    for (game, player, fund) in games {
+        accounts.push(AccountMeta::new(*game, false));
+        accounts.push(AccountMeta::new(*player, false));
+        accounts.push(AccountMeta::new(*fund, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: vec![36],
+    }
+}
+
+fn vault_address(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[VAULT_SEED, mint.as_ref()], program_id).0
+}
+
+/// Account a token game of `pool` takes its randomness from
+fn randomness_account(program_id: &Pubkey, pool: &Pubkey, randomness: Randomness) -> AccountMeta {
+    match randomness {
+        Randomness::SlotHashes => AccountMeta::new_readonly(slot_hashes::id(), false),
+        Randomness::HouseChain => AccountMeta::new(HouseChain::find_address(program_id, pool).0, false),
+        Randomness::Vrf => AccountMeta::new(VrfQueue::find_address(program_id, pool).0, false),
+    }
+}
+
+/// Invoke `program` with `data` over `accounts` in order, `signer` signing
+/// with the caller's `signer_seeds` and every account passed as writable as
+/// the caller holds it
+fn invoke_with<'a>(
+    program: &AccountInfo<'a>,
+    data: Vec<u8>,
+    accounts: &[Option<&AccountInfo<'a>>],
+    signer: Option<&AccountInfo<'a>>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let accounts: Vec<&AccountInfo<'a>> = accounts.iter().flatten().copied().collect();
+    let signer = signer.map(|signer| signer.key);
+    let instr = Instruction {
+        program_id: *program.key,
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer || Some(account.key) == signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut account_infos: Vec<AccountInfo<'a>> = accounts.into_iter().cloned().collect();
+    account_infos.push(program.clone());
+    invoke_signed(&instr, &account_infos, signer_seeds)
+}
+
+/// Accounts of a lamport commit made by CPI, in instruction order
+pub struct LamportCommitAccounts<'a, 'b> {
+    /// The SolanaRoll program
+    pub program: &'b AccountInfo<'a>,
+    pub player: &'b AccountInfo<'a>,
+    pub game: &'b AccountInfo<'a>,
+    pub clock: &'b AccountInfo<'a>,
+    pub slot_hashes: &'b AccountInfo<'a>,
+    pub fund: &'b AccountInfo<'a>,
+    pub treasury: &'b AccountInfo<'a>,
+    pub treasury_state: &'b AccountInfo<'a>,
+    /// The player's referral account, to record their referrer
+    pub referral: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> LamportCommitAccounts<'a, 'b> {
+    /// Commit `bet`, the player signing with `signer_seeds`
+    pub fn invoke_signed(&self, bet: &Commit, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_with(
+            self.program,
+            bet.pack(0),
+            &[
+                Some(self.player),
+                Some(self.game),
+                Some(self.clock),
+                Some(self.slot_hashes),
+                Some(self.fund),
+                Some(self.treasury),
+                Some(self.treasury_state),
+                self.referral,
+            ],
+            Some(self.player),
+            signer_seeds,
+        )
+    }
+}
+
+/// Accounts of a lamport settle made by CPI, in instruction order
+pub struct LamportSettleAccounts<'a, 'b> {
+    /// The SolanaRoll program
+    pub program: &'b AccountInfo<'a>,
+    /// Signs the settle and picks the recipient
+    pub player: &'b AccountInfo<'a>,
+    pub game: &'b AccountInfo<'a>,
+    pub clock: &'b AccountInfo<'a>,
+    pub slot_hashes: &'b AccountInfo<'a>,
+    pub fund: &'b AccountInfo<'a>,
+    pub treasury: &'b AccountInfo<'a>,
+    pub recipient: &'b AccountInfo<'a>,
+    pub treasury_state: &'b AccountInfo<'a>,
+    pub player_stats: &'b AccountInfo<'a>,
+    pub roll_history: &'b AccountInfo<'a>,
+    pub oracle: &'b AccountInfo<'a>,
+    pub fee_vault: &'b AccountInfo<'a>,
+    /// Referrer stats, only for games with a referrer
+    pub referrer: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> LamportSettleAccounts<'a, 'b> {
+    /// Settle with `reveal_number`, the player signing with `signer_seeds`
+    pub fn invoke_signed(&self, reveal_number: u8, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_with(
+            self.program,
+            vec![1, reveal_number],
+            &[
+                Some(self.player),
+                Some(self.game),
+                Some(self.clock),
+                Some(self.slot_hashes),
+                Some(self.fund),
+                Some(self.treasury),
+                Some(self.recipient),
+                Some(self.treasury_state),
+                Some(self.player_stats),
+                Some(self.roll_history),
+                Some(self.oracle),
+                Some(self.fee_vault),
+                self.referrer,
+            ],
+            Some(self.player),
+            signer_seeds,
+        )
+    }
+}
+
+/// Accounts of a token pool commit made by CPI, in instruction order
+pub struct TokenCommitAccounts<'a, 'b> {
+    /// The SolanaRoll program
+    pub program: &'b AccountInfo<'a>,
+    /// Owner of the player token account
+    pub player: &'b AccountInfo<'a>,
+    pub game: &'b AccountInfo<'a>,
+    pub clock: &'b AccountInfo<'a>,
+    /// Slot hashes sysvar, or the pool's house chain or VRF queue
+    pub randomness: &'b AccountInfo<'a>,
+    pub pool: &'b AccountInfo<'a>,
+    pub vault: &'b AccountInfo<'a>,
+    /// Pays the wager and is paid on settle
+    pub player_tokens: &'b AccountInfo<'a>,
+    pub token_program: &'b AccountInfo<'a>,
+    pub referrer: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> TokenCommitAccounts<'a, 'b> {
+    /// Commit `bet`, the player signing with `signer_seeds`
+    pub fn invoke_signed(&self, bet: &Commit, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_with(
+            self.program,
+            bet.pack(5),
+            &[
+                Some(self.player),
+                Some(self.game),
+                Some(self.clock),
+                Some(self.randomness),
+                Some(self.pool),
+                Some(self.vault),
+                Some(self.player_tokens),
+                Some(self.token_program),
+                self.referrer,
+            ],
+            Some(self.player),
+            signer_seeds,
+        )
+    }
+}
+
+/// Accounts of a token pool settle made by CPI, in instruction order
+pub struct TokenSettleAccounts<'a, 'b> {
+    /// The SolanaRoll program
+    pub program: &'b AccountInfo<'a>,
+    /// Owner of the player token account
+    pub player: &'b AccountInfo<'a>,
+    pub game: &'b AccountInfo<'a>,
+    pub clock: &'b AccountInfo<'a>,
+    /// Slot hashes sysvar, or the pool's house chain or VRF queue
+    pub randomness: &'b AccountInfo<'a>,
+    /// Instructions sysvar, only for house-seeded pools
+    pub instructions: Option<&'b AccountInfo<'a>>,
+    pub pool: &'b AccountInfo<'a>,
+    pub vault: &'b AccountInfo<'a>,
+    pub player_tokens: &'b AccountInfo<'a>,
+    pub token_program: &'b AccountInfo<'a>,
+    pub player_stats: &'b AccountInfo<'a>,
+    pub roll_history: &'b AccountInfo<'a>,
+    pub oracle: &'b AccountInfo<'a>,
+    pub fee_vault: &'b AccountInfo<'a>,
+    pub fee_tokens: &'b AccountInfo<'a>,
+    /// Referrer token account, only for games with a referrer
+    pub referrer: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> TokenSettleAccounts<'a, 'b> {
+    /// Settle with `reveal_number`, and the game's house seed or the
+    /// player's secret as `seed` for house-seeded or VRF pools, the player
+    /// signing with `signer_seeds`
+    pub fn invoke_signed(
+        &self,
+        reveal_number: u8,
+        seed: Option<&[u8; 32]>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut data = vec![6, reveal_number];
+        if let Some(seed) = seed {
+            data.extend_from_slice(seed);
+        }
+        invoke_with(
+            self.program,
+            data,
+            &[
+                Some(self.player),
+                Some(self.game),
+                Some(self.clock),
+                Some(self.randomness),
+                self.instructions,
+                Some(self.pool),
+                Some(self.vault),
+                Some(self.player_tokens),
+                Some(self.token_program),
+                Some(self.player_stats),
+                Some(self.roll_history),
+                Some(self.oracle),
+                Some(self.fee_vault),
+                Some(self.fee_tokens),
+                self.referrer,
+            ],
+            Some(self.player),
+            signer_seeds,
+        )
+    }
+}
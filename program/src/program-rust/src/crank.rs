@@ -0,0 +1,640 @@
+//! Permissionless settling of abandoned games
+//!
+//! A player who never sends the roll leaves their wager in the fund account
+//! or pool vault and its exposure reserved. Once a game's commit slot has
+//! left the slot hashes, anyone may `Crank` it. The player saw the slot hash
+//! and held back the reveal, so lamport games and slot hash token games are
+//! forfeited to the treasury or pool. Games of a house chain are settled on
+//! the seed the house posted or the crank brings, or without one if the
+//! house never posted it, and VRF games are forfeited too, or refunded if
+//! the worker never wrote their output. The keeper earns its treasury's
+//! keeper fee, a share of the stake taken out of the payout or the forfeited
+//! or lost wager. While a treasury's settles are paused, cranks refund games
+//! instead, paying no keeper fee.
+//!
+//! Commits link the blank, program owned fund of a lamport game to it by
+//! writing the game key at its start, and settles and cranks only take the
+//! linked fund. `SettleBatch` cranks many lamport games of one treasury at
+//! once, skipping any game that fails its checks.
+
+use crate::{
+    error::RollError,
+    fees::FeeAccounts,
+    instruction::TreasuryKind,
+    odds::BPS,
+    oracle,
+    pause::{self, PAUSE_SETTLES},
+    randomness::RandomnessAccounts,
+    settle,
+    state::{Game, GameStatus, Pool},
+    token_pool::{liquidity, load_token_account, settle_game, transfer_from_vault},
+    treasury::load_treasury,
+    withdraw_queue,
+    validation::{
+        assert_distinct, assert_key, assert_program_account, assert_signer, assert_sysvar,
+        assert_writable,
+    },
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    slot_hashes::MAX_ENTRIES,
+    sysvar::{clock, clock::Clock, Sysvar},
+};
+
+/// Slots after its commit slot a game can be cranked, when the commit
+/// slot's hash is no longer in the slot hashes
+pub const CRANK_DELAY_SLOTS: u64 = MAX_ENTRIES as u64;
+
+/// Highest keeper fee a treasury may set, in bps of the stake
+pub const MAX_KEEPER_FEE_BPS: u16 = 100;
+
+/// Most games with distinct accounts one `SettleBatch` transaction fits,
+/// as three account keys per game fill the packet well before the compute
+/// budget runs out
+pub const MAX_BATCH_GAMES: usize = 9;
+
+/// Whether the crank delay of `game` has passed at `current_slot`
+pub fn is_expired(game: &Game, current_slot: u64) -> bool {
+    current_slot > game.commit_slot.saturating_add(CRANK_DELAY_SLOTS)
+}
+
+/// Whether `game` can be cranked at `current_slot`
+pub fn is_crankable(game: &Game, current_slot: u64) -> bool {
+    game.status == GameStatus::Committed && is_expired(game, current_slot)
+}
+
+/// Keeper's share of a cranked game's `stake`
+pub fn keeper_fee(stake: u64, keeper_fee_bps: u16) -> u64 {
+    (stake as u128 * keeper_fee_bps as u128 / BPS as u128) as u64
+}
+
+/// Link a lamport game's fund account, owned by the program, to `game`.
+/// Fails unless the fund is writable and blank, so every committed game has
+/// a linked fund to settle and crank.
+pub fn link_fund(fund_account: &AccountInfo, game: &Pubkey) -> ProgramResult {
+    assert_writable(fund_account)?;
+    let mut data = fund_account.try_borrow_mut_data()?;
+    if data.len() < 32 || data.iter().any(|byte| *byte != 0) {
+        info!("SolanaRoll fund account must be blank with room for the game");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    data[..32].copy_from_slice(game.as_ref());
+    Ok(())
+}
+
+/// Game a fund account's data is linked to, if any
+pub fn linked_game(data: &[u8]) -> Option<Pubkey> {
+    match data.get(..32) {
+        Some(game) if game.iter().any(|byte| *byte != 0) => Some(Pubkey::new(game)),
+        _ => None,
+    }
+}
+
+fn load_crankable(game_account: &AccountInfo, current_slot: u64) -> Result<Game, ProgramError> {
+    let game = Game::unpack(&game_account.data.borrow())?;
+    if game.status != GameStatus::Committed {
+        info!("Game is not committed");
+        return Err(RollError::GameNotCommitted.into());
+    }
+    if !is_crankable(&game, current_slot) {
+        info!("SolanaRoll game can still be settled by its player");
+        return Err(RollError::GameNotExpired.into());
+    }
+    Ok(game)
+}
+
+/// Amounts a cranked lamport game moved
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cranked {
+    /// Exposure the treasury reserved for the game
+    pub reserved: u64,
+    /// Keeper's share of the stake
+    pub fee: u64,
+    /// Rest of the stake, forfeited to the treasury
+    pub forfeited: u64,
+    /// Anything funded beyond the stake, returned to the player
+    pub payout: u64,
+}
+
+/// Forfeit the abandoned lamport game in `game_account` to `treasury_account`
+/// from its linked `fund_account`, returning anything funded beyond the
+/// stake to `player_account` and keeping the keeper's fee of
+/// `keeper_fee_bps` in the fund for the caller to pay out. While settles are
+/// `paused` the whole fund is refunded to the player instead. Every check
+/// runs before any account is written.
+#[allow(clippy::too_many_arguments)]
+pub fn crank_lamport<'a>(
+    program_id: &Pubkey,
+    game_account: &AccountInfo<'a>,
+    player_account: &AccountInfo<'a>,
+    fund_account: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    current_slot: u64,
+    keeper_fee_bps: u16,
+    paused: bool,
+) -> Result<Cranked, ProgramError> {
+    assert_program_account(game_account, program_id)?;
+    assert_program_account(fund_account, program_id)?;
+    assert_writable(game_account)?;
+    assert_writable(fund_account)?;
+    assert_writable(player_account)?;
+    assert_writable(treasury_account)?;
+    assert_distinct(&[game_account, player_account, fund_account, treasury_account])?;
+
+    let mut game = load_crankable(game_account, current_slot)?;
+    if game.pool != Pubkey::default() {
+        info!("Game is not a lamport game");
+        return Err(RollError::GameNotCommitted.into());
+    }
+    assert_key(player_account, &game.player)?;
+    assert_key(fund_account, &game.fund)?;
+    if &game.treasury != treasury_account.key {
+        info!("SolanaRoll game was committed against another treasury");
+        return Err(RollError::IncorrectAddress.into());
+    }
+    if linked_game(&fund_account.data.borrow()) != Some(*game_account.key) {
+        info!("SolanaRoll fund account is not linked to the game");
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    // Games committed without a roll count wager the whole fund
+    let balance = fund_account.lamports();
+    let stake = settle::stake(&game, &game_account.data.borrow())
+        .unwrap_or(balance)
+        .min(balance);
+    game.status = GameStatus::Settled;
+    game.pack(&mut game_account.data.borrow_mut())?;
+
+    if paused {
+        info!("Settles are paused, returning funds");
+        **fund_account.lamports.borrow_mut() -= balance;
+        **player_account.lamports.borrow_mut() += balance;
+        return Ok(Cranked {
+            reserved: game.reserved,
+            payout: balance,
+            ..Cranked::default()
+        });
+    }
+
+    // The stake goes to the treasury, less the keeper's fee
+    let fee = keeper_fee(stake, keeper_fee_bps);
+    let forfeited = stake - fee;
+    let payout = balance - stake;
+    **fund_account.lamports.borrow_mut() -= forfeited + payout;
+    **treasury_account.lamports.borrow_mut() += forfeited;
+    **player_account.lamports.borrow_mut() += payout;
+    Ok(Cranked {
+        reserved: game.reserved,
+        fee,
+        forfeited,
+        payout,
+    })
+}
+
+/// 34 - settle or forfeit an abandoned game, paying the keeper's fee
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2..34] house seed
+/// (house-seeded pools only). Lamport accounts: keeper (signer), game,
+/// clock sysvar, fund, treasury, player, treasury state. Token pool
+/// accounts: keeper (signer), keeper's token account of the pool mint, then
+/// the accounts of a token settle from the game on
+pub fn process_crank(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let keeper_account = next_account_info(accounts_iter)?;
+    assert_signer(keeper_account)?;
+
+    match kind {
+        TreasuryKind::Lamports => crank_lamport_game(program_id, keeper_account, accounts_iter),
+        TreasuryKind::TokenPool => crank_token_game(program_id, keeper_account, accounts_iter, data.get(2..34)),
+    }
+}
+
+fn crank_lamport_game<'a, 'b, I: Iterator<Item = &'b AccountInfo<'a>>>(
+    program_id: &Pubkey,
+    keeper_account: &'b AccountInfo<'a>,
+    accounts_iter: &mut I,
+) -> ProgramResult {
+    let game_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let fund_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let player_account = next_account_info(accounts_iter)?;
+    let treasury_state_account = next_account_info(accounts_iter)?;
+
+    assert_sysvar(clock_account, &clock::id())?;
+    assert_writable(keeper_account)?;
+    assert_distinct(&[game_account, fund_account, treasury_account, player_account, treasury_state_account])?;
+    let mut treasury = load_treasury(program_id, treasury_account, treasury_state_account)?;
+    withdraw_queue::open_treasury_epoch(&mut treasury, treasury_account.lamports(), None, clock_account)?;
+    let current_slot = Clock::from_account_info(clock_account)?.slot;
+
+    let cranked = crank_lamport(
+        program_id,
+        game_account,
+        player_account,
+        fund_account,
+        treasury_account,
+        current_slot,
+        treasury.keeper_fee_bps,
+        pause::is_paused(treasury.paused, PAUSE_SETTLES),
+    )?;
+    treasury.reserved = treasury.reserved.saturating_sub(cranked.reserved);
+    treasury.pack(&mut treasury_state_account.data.borrow_mut())?;
+    **fund_account.lamports.borrow_mut() -= cranked.fee;
+    **keeper_account.lamports.borrow_mut() += cranked.fee;
+
+    info!(&format!(
+        "CRANK game={} keeper={} fee={} forfeited={} payout={}",
+        game_account.key, keeper_account.key, cranked.fee, cranked.forfeited, cranked.payout
+    ));
+    Ok(())
+}
+
+fn crank_token_game<'a, 'b, I: Iterator<Item = &'b AccountInfo<'a>>>(
+    program_id: &Pubkey,
+    keeper_account: &'b AccountInfo<'a>,
+    accounts_iter: &mut I,
+    seed_data: Option<&[u8]>,
+) -> ProgramResult {
+    let keeper_token_account = next_account_info(accounts_iter)?;
+    let game_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let randomness = RandomnessAccounts::next(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let player_token_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let player_stats_account = next_account_info(accounts_iter)?;
+    let roll_history_account = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+    let fee_accounts = FeeAccounts::next(accounts_iter, TreasuryKind::TokenPool)?;
+
+    assert_key(token_program, &spl_token::ID)?;
+    assert_program_account(game_account, program_id)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    assert_writable(keeper_token_account)?;
+    assert_distinct(&[keeper_token_account, vault_account, player_token_account])?;
+    let current_slot = Clock::from_account_info(clock_account)?.slot;
+    let game = load_crankable(game_account, current_slot)?;
+    let stake = settle::stake(&game, &game_account.data.borrow())?;
+
+    let (pool, payout): (Pool, u64) = settle_game(
+        program_id,
+        None,
+        seed_data,
+        game_account,
+        clock_account,
+        &randomness,
+        pool_account,
+        vault_account,
+        player_token_account,
+        player_stats_account,
+        roll_history_account,
+        &fee_accounts,
+        token_program,
+    )?;
+
+    let keeper_tokens = load_token_account(keeper_token_account, keeper_account.key)?;
+    if keeper_tokens.mint != pool.mint {
+        info!("Keeper token account is not for the pool mint");
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    // The fee comes out of the payout, or out of the wager the pool won or
+    // was forfeited. Refunds of paused settles pay none.
+    let fee = if pause::is_paused(pool.paused, PAUSE_SETTLES) {
+        0
+    } else {
+        keeper_fee(stake, pool.keeper_fee_bps)
+    };
+    let payout = payout.saturating_sub(fee);
+    transfer_from_vault(&pool, pool_account, vault_account, keeper_token_account, token_program, fee)?;
+    transfer_from_vault(&pool, pool_account, vault_account, player_token_account, token_program, payout)?;
+
+    info!(&format!(
+        "CRANK game={} keeper={} fee={} payout={}",
+        game_account.key, keeper_token_account.key, fee, payout
+    ));
+    let treasury_liquidity = liquidity(&pool, vault_account)?;
+    oracle::update(program_id, pool_account.key, treasury_liquidity, None, clock_account, oracle_account)
+}
+
+/// 35 - set the keeper fee of a treasury or pool
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2..4] keeper fee,
+/// bps of a cranked game's stake. Accounts: authority (signer), treasury or
+/// pool, treasury state (lamports only)
+pub fn process_set_keeper_fee(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 4 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let keeper_fee_bps = LittleEndian::read_u16(&data[2..4]);
+    if keeper_fee_bps > MAX_KEEPER_FEE_BPS {
+        info!("Keeper fee is too high");
+        return Err(RollError::InvalidAmount.into());
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    assert_signer(authority_account)?;
+
+    match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            let mut treasury = load_treasury(program_id, treasury_account, state_account)?;
+            assert_key(authority_account, &treasury.authority)?;
+            treasury.keeper_fee_bps = keeper_fee_bps;
+            treasury.pack(&mut state_account.data.borrow_mut())?;
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            let mut pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(authority_account, &pool.authority)?;
+            pool.keeper_fee_bps = keeper_fee_bps;
+            pool.pack(&mut treasury_account.data.borrow_mut())?;
+        }
+    }
+
+    info!(&format!(
+        "KEEPER_FEE treasury={} keeper_fee_bps={}",
+        treasury_account.key, keeper_fee_bps
+    ));
+    Ok(())
+}
+
+/// 36 - crank many abandoned lamport games of one treasury
+///
+/// Each game is checked and forfeited like a lamport `Crank` on its own: a
+/// game that fails its checks is left untouched and the rest still settle.
+/// The batch is logged as one line:
+///
+/// ```text
+/// SETTLE_BATCH treasury=<address> keeper=<address> settled=<count> skipped=<count> fee=<lamports> forfeited=<lamports> payout=<lamports>
+/// ```
+///
+/// Accounts: keeper (signer), clock sysvar, treasury, treasury state, then a
+/// (game, player, fund) triple per game
+pub fn process_settle_batch(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let keeper_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let treasury_state_account = next_account_info(accounts_iter)?;
+    let games = accounts_iter.as_slice();
+    if games.is_empty() || !games.len().is_multiple_of(3) {
+        info!("SolanaRoll batch takes a game, player and fund per game");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    assert_signer(keeper_account)?;
+    assert_writable(keeper_account)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    let mut treasury = load_treasury(program_id, treasury_account, treasury_state_account)?;
+    withdraw_queue::open_treasury_epoch(&mut treasury, treasury_account.lamports(), None, clock_account)?;
+    let current_slot = Clock::from_account_info(clock_account)?.slot;
+
+    let mut total = Cranked::default();
+    let mut settled = 0;
+    for triple in games.chunks(3) {
+        let (game_account, player_account, fund_account) = (&triple[0], &triple[1], &triple[2]);
+        // The treasury's own accounts are never a game's
+        let cranked = assert_distinct(&[game_account, player_account, fund_account, treasury_account, treasury_state_account])
+            .and_then(|_| {
+                crank_lamport(
+                    program_id,
+                    game_account,
+                    player_account,
+                    fund_account,
+                    treasury_account,
+                    current_slot,
+                    treasury.keeper_fee_bps,
+                    pause::is_paused(treasury.paused, PAUSE_SETTLES),
+                )
+            });
+        match cranked {
+            Ok(cranked) => {
+                **fund_account.lamports.borrow_mut() -= cranked.fee;
+                total.reserved = total.reserved.saturating_add(cranked.reserved);
+                total.fee += cranked.fee;
+                total.forfeited += cranked.forfeited;
+                total.payout += cranked.payout;
+                settled += 1;
+            }
+            Err(_) => info!(&format!("SolanaRoll skipping game {}", game_account.key)),
+        }
+    }
+
+    treasury.reserved = treasury.reserved.saturating_sub(total.reserved);
+    treasury.pack(&mut treasury_state_account.data.borrow_mut())?;
+    **keeper_account.lamports.borrow_mut() += total.fee;
+
+    info!(&format!(
+        "SETTLE_BATCH treasury={} keeper={} settled={} skipped={} fee={} forfeited={} payout={}",
+        treasury_account.key,
+        keeper_account.key,
+        settled,
+        games.len() / 3 - settled,
+        total.fee,
+        total.forfeited,
+        total.payout
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::clock::Epoch;
+
+    const TREASURY: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    fn committed_game(player: Pubkey, fund: Pubkey, commit_slot: u64, reserved: u64) -> Game {
+        let mut game = Game::unpack(&[0u8; Game::LEN]).unwrap();
+        game.status = GameStatus::Committed;
+        game.player = player;
+        game.fund = fund;
+        game.treasury = TREASURY;
+        game.commit_slot = commit_slot;
+        game.reserved = reserved;
+        game
+    }
+
+    #[test]
+    fn test_crank_delay() {
+        let game = committed_game(Pubkey::default(), Pubkey::default(), 1_000, 0);
+        assert!(!is_crankable(&game, 1_000 + CRANK_DELAY_SLOTS));
+        assert!(is_crankable(&game, 1_001 + CRANK_DELAY_SLOTS));
+        let settled = Game {
+            status: GameStatus::Settled,
+            ..game
+        };
+        assert!(!is_crankable(&settled, 1_001 + CRANK_DELAY_SLOTS));
+    }
+
+    #[test]
+    fn test_keeper_fee() {
+        assert_eq!(keeper_fee(1_000_000, 50), 5_000);
+        assert_eq!(keeper_fee(1_000_000, 0), 0);
+        assert_eq!(keeper_fee(u64::MAX, MAX_KEEPER_FEE_BPS), u64::MAX / 100);
+    }
+
+    #[test]
+    fn test_linked_game() {
+        let game = Pubkey::new_unique();
+        let mut data = vec![0u8; 162];
+        assert_eq!(linked_game(&data), None);
+        data[..32].copy_from_slice(game.as_ref());
+        assert_eq!(linked_game(&data), Some(game));
+        assert_eq!(linked_game(&[]), None);
+    }
+
+    #[test]
+    fn test_crank_lamport() {
+        let program_id = Pubkey::new_unique();
+        let system = Pubkey::default();
+        let (game_key, player_key, fund_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let other_key = Pubkey::new_unique();
+        let mut game_data = vec![0u8; Game::LEN];
+        let mut committed = committed_game(player_key, fund_key, 0, 500);
+        committed.wager = 4_000;
+        committed.roll_count = 1;
+        committed.pack(&mut game_data).unwrap();
+        let mut fund_data = vec![0u8; Game::LEN];
+        fund_data[..32].copy_from_slice(game_key.as_ref());
+        let (mut player_data, mut treasury_data, mut other_data) = (vec![], vec![], vec![]);
+        let (mut game_lamports, mut player_lamports, mut fund_lamports) = (1, 0, 10_000);
+        let (mut treasury_lamports, mut other_lamports) = (0, 0);
+        let game = AccountInfo::new(&game_key, false, true, &mut game_lamports, &mut game_data, &program_id, false, Epoch::default());
+        let player = AccountInfo::new(&player_key, false, true, &mut player_lamports, &mut player_data, &system, false, Epoch::default());
+        let fund = AccountInfo::new(&fund_key, false, true, &mut fund_lamports, &mut fund_data, &program_id, false, Epoch::default());
+        let treasury = AccountInfo::new(&TREASURY, false, true, &mut treasury_lamports, &mut treasury_data, &program_id, false, Epoch::default());
+        let other = AccountInfo::new(&other_key, false, true, &mut other_lamports, &mut other_data, &program_id, false, Epoch::default());
+
+        // Too early, nothing moves
+        assert_eq!(
+            crank_lamport(&program_id, &game, &player, &fund, &treasury, CRANK_DELAY_SLOTS, 50, false),
+            Err(RollError::GameNotExpired.into())
+        );
+        assert_eq!(fund.lamports(), 10_000);
+
+        // Only against the treasury the game was committed to
+        assert_eq!(
+            crank_lamport(&program_id, &game, &player, &fund, &other, CRANK_DELAY_SLOTS + 1, 50, false),
+            Err(RollError::IncorrectAddress.into())
+        );
+
+        // The stake is forfeited less the fee, the rest of the fund returned
+        let cranked = crank_lamport(&program_id, &game, &player, &fund, &treasury, CRANK_DELAY_SLOTS + 1, 50, false).unwrap();
+        assert_eq!(
+            cranked,
+            Cranked {
+                reserved: 500,
+                fee: 20,
+                forfeited: 3_980,
+                payout: 6_000
+            }
+        );
+        assert_eq!(treasury.lamports(), 3_980);
+        assert_eq!(player.lamports(), 6_000);
+        assert_eq!(fund.lamports(), 20);
+        assert_eq!(Game::unpack(&game.data.borrow()).unwrap().status, GameStatus::Settled);
+
+        // Settled games cannot be cranked again
+        assert_eq!(
+            crank_lamport(&program_id, &game, &player, &fund, &treasury, CRANK_DELAY_SLOTS + 1, 50, false),
+            Err(RollError::GameNotCommitted.into())
+        );
+    }
+
+    #[test]
+    fn test_link_fund() {
+        let program_id = Pubkey::new_unique();
+        let (fund_key, game_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut fund_data = vec![0u8; 16];
+        let mut fund_lamports = 0;
+        let fund = AccountInfo::new(&fund_key, false, true, &mut fund_lamports, &mut fund_data, &program_id, false, Epoch::default());
+
+        // Too small to hold the game key
+        assert_eq!(link_fund(&fund, &game_key), Err(ProgramError::InvalidAccountData));
+
+        let mut fund_data = vec![0u8; Game::LEN];
+        let mut fund_lamports = 0;
+        let fund = AccountInfo::new(&fund_key, false, true, &mut fund_lamports, &mut fund_data, &program_id, false, Epoch::default());
+        link_fund(&fund, &game_key).unwrap();
+        assert_eq!(linked_game(&fund.data.borrow()), Some(game_key));
+
+        // A linked fund cannot be linked again
+        assert_eq!(link_fund(&fund, &Pubkey::new_unique()), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn test_crank_lamport_unlinked_fund() {
+        let program_id = Pubkey::new_unique();
+        let system = Pubkey::default();
+        let (game_key, player_key, fund_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut game_data = vec![0u8; Game::LEN];
+        committed_game(player_key, fund_key, 0, 0).pack(&mut game_data).unwrap();
+        let mut fund_data = vec![0u8; Game::LEN];
+        let (mut player_data, mut treasury_data) = (vec![], vec![]);
+        let (mut game_lamports, mut player_lamports, mut fund_lamports, mut treasury_lamports) = (1, 0, 10_000, 0);
+        let game = AccountInfo::new(&game_key, false, true, &mut game_lamports, &mut game_data, &program_id, false, Epoch::default());
+        let player = AccountInfo::new(&player_key, false, true, &mut player_lamports, &mut player_data, &system, false, Epoch::default());
+        let fund = AccountInfo::new(&fund_key, false, true, &mut fund_lamports, &mut fund_data, &program_id, false, Epoch::default());
+        let treasury = AccountInfo::new(&TREASURY, false, true, &mut treasury_lamports, &mut treasury_data, &program_id, false, Epoch::default());
+
+        assert_eq!(
+            crank_lamport(&program_id, &game, &player, &fund, &treasury, CRANK_DELAY_SLOTS + 1, 0, false),
+            Err(RollError::IncorrectAddress.into())
+        );
+        assert_eq!(Game::unpack(&game.data.borrow()).unwrap().status, GameStatus::Committed);
+        assert_eq!(fund.lamports(), 10_000);
+    }
+
+    #[test]
+    fn test_crank_lamport_paused() {
+        let program_id = Pubkey::new_unique();
+        let system = Pubkey::default();
+        let (game_key, player_key, fund_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut game_data = vec![0u8; Game::LEN];
+        let mut committed = committed_game(player_key, fund_key, 0, 500);
+        committed.wager = 4_000;
+        committed.roll_count = 1;
+        committed.pack(&mut game_data).unwrap();
+        let mut fund_data = vec![0u8; Game::LEN];
+        fund_data[..32].copy_from_slice(game_key.as_ref());
+        let (mut player_data, mut treasury_data) = (vec![], vec![]);
+        let (mut game_lamports, mut player_lamports, mut fund_lamports, mut treasury_lamports) = (1, 0, 10_000, 0);
+        let game = AccountInfo::new(&game_key, false, true, &mut game_lamports, &mut game_data, &program_id, false, Epoch::default());
+        let player = AccountInfo::new(&player_key, false, true, &mut player_lamports, &mut player_data, &system, false, Epoch::default());
+        let fund = AccountInfo::new(&fund_key, false, true, &mut fund_lamports, &mut fund_data, &program_id, false, Epoch::default());
+        let treasury = AccountInfo::new(&TREASURY, false, true, &mut treasury_lamports, &mut treasury_data, &program_id, false, Epoch::default());
+
+        // Paused settles refund the whole fund and pay no keeper fee
+        let cranked = crank_lamport(&program_id, &game, &player, &fund, &treasury, CRANK_DELAY_SLOTS + 1, 50, true).unwrap();
+        assert_eq!(
+            cranked,
+            Cranked {
+                reserved: 500,
+                fee: 0,
+                forfeited: 0,
+                payout: 10_000
+            }
+        );
+        assert_eq!(treasury.lamports(), 0);
+        assert_eq!(player.lamports(), 10_000);
+        assert_eq!(fund.lamports(), 0);
+        assert_eq!(Game::unpack(&game.data.borrow()).unwrap().status, GameStatus::Settled);
+    }
+}
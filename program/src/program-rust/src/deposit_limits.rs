@@ -0,0 +1,212 @@
+//! Liquidity cap and depositor allowlist
+//!
+//! New treasuries can launch conservatively: deposits may not take a
+//! treasury past its liquidity cap, and an allowlisted treasury only takes
+//! deposits from depositors with an allowlist entry PDA
+//! `["allowlist", treasury, depositor]` set by its authority. Token pool
+//! deposits over the cap are partially filled up to it. Lamport deposits
+//! arrive in a fund account beforehand, so they are rejected instead.
+
+use crate::{
+    error::RollError,
+    instruction::TreasuryKind,
+    state::{AllowlistEntry, Pool, ALLOWLIST_SEED},
+    token_pool::create_pda_account,
+    treasury::load_treasury,
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+
+/// Part of a deposit of `amount` that fits under `max_liquidity` on top of
+/// `liquidity`. A zero cap takes everything.
+pub fn capped_amount(amount: u64, liquidity: u64, max_liquidity: u64) -> u64 {
+    if max_liquidity == 0 {
+        return amount;
+    }
+    amount.min(max_liquidity.saturating_sub(liquidity))
+}
+
+/// Fails unless `depositor` may deposit into `treasury`, from the allowlist
+/// entry passed when the treasury is allowlisted
+pub fn assert_allowed(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    allowlist_only: bool,
+    depositor: &Pubkey,
+    entry_account: Option<&AccountInfo>,
+) -> ProgramResult {
+    if !allowlist_only {
+        return Ok(());
+    }
+    let entry_account = entry_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let (address, _) = AllowlistEntry::find_address(program_id, treasury, depositor);
+    assert_key(entry_account, &address)?;
+    if entry_account.owner == program_id {
+        let entry = AllowlistEntry::unpack(&entry_account.data.borrow())?;
+        if entry.is_initialized && entry.allowed {
+            return Ok(());
+        }
+    }
+    info!("SolanaRoll depositor is not allowlisted");
+    Err(RollError::NotAllowlisted.into())
+}
+
+/// 27 - set the liquidity cap and allowlist mode of a treasury or pool
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2..10] liquidity
+/// cap, 0 for none, [10] 1 for allowlisted deposits only. Accounts:
+/// authority (signer), treasury or pool, treasury state (lamports only)
+pub fn process_set_deposit_limits(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 11 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let max_liquidity = LittleEndian::read_u64(&data[2..10]);
+    let allowlist_only = data[10] != 0;
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    assert_signer(authority_account)?;
+
+    match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            let mut treasury = load_treasury(program_id, treasury_account, state_account)?;
+            assert_key(authority_account, &treasury.authority)?;
+            treasury.max_liquidity = max_liquidity;
+            treasury.allowlist_only = allowlist_only;
+            treasury.pack(&mut state_account.data.borrow_mut())?;
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            let mut pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(authority_account, &pool.authority)?;
+            pool.max_liquidity = max_liquidity;
+            pool.allowlist_only = allowlist_only;
+            pool.pack(&mut treasury_account.data.borrow_mut())?;
+        }
+    }
+
+    info!(&format!(
+        "DEPOSIT_LIMITS treasury={} max_liquidity={} allowlist_only={}",
+        treasury_account.key, max_liquidity, allowlist_only
+    ));
+    Ok(())
+}
+
+/// 28 - add a depositor to, or remove them from, a treasury's allowlist
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2] 1 to allow, 0
+/// to remove. Accounts: payer (signer), authority (signer), treasury or
+/// pool, depositor, allowlist entry, rent sysvar, system program, treasury
+/// state (lamports only)
+pub fn process_set_allowed(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 3 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let allowed = data[2] != 0;
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let depositor_account = next_account_info(accounts_iter)?;
+    let entry_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_signer(authority_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let authority = match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            load_treasury(program_id, treasury_account, state_account)?.authority
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            Pool::unpack_initialized(&treasury_account.data.borrow())?.authority
+        }
+    };
+    assert_key(authority_account, &authority)?;
+
+    set_allowed(
+        program_id,
+        treasury_account.key,
+        depositor_account.key,
+        allowed,
+        payer_account,
+        entry_account,
+        system_program_account,
+        &Rent::from_account_info(rent_account)?,
+    )
+}
+
+/// Allow `depositor` into `treasury`'s allowlist, or remove them, creating
+/// their entry on first use
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn set_allowed<'a>(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    depositor: &Pubkey,
+    allowed: bool,
+    payer_account: &AccountInfo<'a>,
+    entry_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    rent: &Rent,
+) -> ProgramResult {
+    let (entry_address, bump_seed) = AllowlistEntry::find_address(program_id, treasury, depositor);
+    assert_key(entry_account, &entry_address)?;
+    if entry_account.owner != program_id {
+        create_pda_account(
+            payer_account,
+            entry_account,
+            system_program_account,
+            program_id,
+            AllowlistEntry::LEN,
+            rent,
+            &[ALLOWLIST_SEED, treasury.as_ref(), depositor.as_ref(), &[bump_seed]],
+        )?;
+    }
+    let entry = AllowlistEntry {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury,
+        depositor: *depositor,
+        allowed,
+    };
+    entry.pack(&mut entry_account.data.borrow_mut())?;
+
+    info!(&format!(
+        "ALLOWLIST treasury={} depositor={} allowed={}",
+        treasury, depositor, allowed
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capped_amount() {
+        assert_eq!(capped_amount(500, 1_000, 0), 500);
+        assert_eq!(capped_amount(500, 1_000, 2_000), 500);
+        assert_eq!(capped_amount(500, 1_800, 2_000), 200);
+        assert_eq!(capped_amount(500, 2_000, 2_000), 0);
+        assert_eq!(capped_amount(500, 3_000, 2_000), 0);
+    }
+}
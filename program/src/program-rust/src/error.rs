@@ -0,0 +1,111 @@
+//! Error types
+
+use num_derive::FromPrimitive;
+use solana_sdk::{decode_error::DecodeError, program_error::ProgramError};
+use thiserror::Error;
+
+/// Errors that may be returned by the SolanaRoll program.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum RollError {
+    /// The same account was passed in two positions that must differ.
+    /// Codes start at 100 so they never collide with `SwapError`.
+    #[error("Account passed more than once")]
+    DuplicateAccount = 100,
+    /// The account is not owned by the expected program.
+    #[error("Account has an unexpected owner")]
+    IncorrectOwner,
+    /// The account was expected to sign the transaction.
+    #[error("Account is missing a required signature")]
+    MissingSigner,
+    /// The account was expected to be writable.
+    #[error("Account is not writable")]
+    NotWritable,
+    /// The account is not the sysvar the instruction expects.
+    #[error("Account is not the expected sysvar")]
+    InvalidSysvar,
+    /// The account address does not match the expected address.
+    #[error("Account address is incorrect")]
+    IncorrectAddress,
+    /// The game kind is not one the program knows how to settle.
+    #[error("Invalid game kind")]
+    InvalidGameKind,
+    /// The bet target gives odds outside the allowed range.
+    #[error("Bet target is outside the allowed odds")]
+    InvalidTarget,
+    /// A roulette chip placement is not a valid spot on the layout.
+    #[error("Invalid roulette placement")]
+    InvalidPlacement,
+    /// The roll count or wager per roll of a multi-roll bet is invalid.
+    #[error("Invalid roll count or wager per roll")]
+    InvalidRollCount,
+    /// The game is committed and waiting for its reveal.
+    #[error("Game is already committed")]
+    GameAlreadyCommitted,
+    /// The game has no pending commit to settle.
+    #[error("Game is not committed")]
+    GameNotCommitted,
+    /// The amount is zero or out of range.
+    #[error("Invalid amount")]
+    InvalidAmount,
+    /// The swap returned less than the minimum amount out.
+    #[error("Swap output below the minimum amount out")]
+    SlippageExceeded,
+    /// The wager is above the treasury's max wager for the bet's odds.
+    #[error("Wager exceeds max wager")]
+    MaxWagerExceeded,
+    /// The treasury's unreserved liquidity cannot cover the amount.
+    #[error("Insufficient unreserved liquidity")]
+    InsufficientLiquidity,
+    /// The operation is paused by the treasury's authority.
+    #[error("Operation is paused")]
+    Paused,
+    /// The signer is not one of the multisig's signers.
+    #[error("Not a multisig signer")]
+    NotAdminSigner,
+    /// The proposal lacks approvals or its timelock has not passed.
+    #[error("Proposal is not ready to execute")]
+    ProposalNotReady,
+    /// The proposal was already executed.
+    #[error("Proposal already executed")]
+    ProposalExecuted,
+    /// The withdrawal waits for the price snapshot of a later epoch.
+    #[error("Withdrawal is not due yet")]
+    WithdrawNotDue,
+    /// The deposit would take the treasury past its liquidity cap.
+    #[error("Deposit exceeds the treasury cap")]
+    DepositCapExceeded,
+    /// The treasury only takes deposits from allowlisted depositors.
+    #[error("Depositor is not allowlisted")]
+    NotAllowlisted,
+    /// The house seed or its signature does not verify against the chain.
+    #[error("Invalid house seed")]
+    InvalidHouseSeed,
+    /// The house chain already holds the most games waiting for a seed.
+    #[error("Too many games waiting for a house seed")]
+    HouseQueueFull,
+    /// The pool has committed bets that are not settled yet.
+    #[error("Pool has open bets")]
+    OpenBets,
+    /// The VRF queue already holds the most games waiting for an output.
+    #[error("Too many games waiting for a VRF output")]
+    VrfQueueFull,
+    /// The game's VRF output has not been written yet.
+    #[error("VRF output not fulfilled")]
+    VrfNotFulfilled,
+    /// The game can still be settled by its player and cannot be cranked yet.
+    #[error("Game cannot be cranked yet")]
+    GameNotExpired,
+    /// The reveal number does not hash to the one committed.
+    #[error("Reveal number does not match the commit")]
+    InvalidReveal,
+}
+impl From<RollError> for ProgramError {
+    fn from(e: RollError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+impl<T> DecodeError<T> for RollError {
+    fn type_of() -> &'static str {
+        "Roll Error"
+    }
+}
@@ -0,0 +1,436 @@
+//! Protocol and referrer fees
+//!
+//! Every rolled bet pays a fee on the house edge it carries, `wager * edge`,
+//! whatever the result. A treasury's fee vault PDA `["fee_vault", treasury]`
+//! sets the split: the protocol share accrues in the fee vault until its fee
+//! authority claims it, the referrer share goes to the referrer recorded at
+//! commit, and the rest stays with the liquidity providers. Token referrers
+//! are paid straight away, lamport referrers through `referral`.
+//! Refunded bets pay no fees, and neither do treasuries without a fee vault.
+//!
+//! Lamport fees are held by the fee vault account itself. Token fees are
+//! held by a fee token account PDA `["fee_tokens", pool]` owned by the pool.
+
+use crate::{
+    error::RollError,
+    instruction::TreasuryKind,
+    odds::BPS,
+    referral, risk,
+    state::{FeeVault, Game, Pool, FEE_TOKENS_SEED, FEE_VAULT_SEED},
+    token_pool::{create_pda_account, transfer_from_vault},
+    validation::{
+        assert_distinct, assert_key, assert_owned_by, assert_program_account, assert_signer,
+        assert_sysvar, assert_writable,
+    },
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+use spl_token::state::Account as TokenAccount;
+
+/// Fees taken from one settled bet
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeeSplit {
+    pub protocol: u64,
+    pub referrer: u64,
+}
+
+impl FeeSplit {
+    pub fn total(&self) -> u64 {
+        self.protocol.saturating_add(self.referrer)
+    }
+}
+
+/// House edge revenue of a `wager` placed with `edge_bps` edge
+pub fn revenue(wager: u64, edge_bps: u64) -> u64 {
+    (wager as u128 * edge_bps as u128 / BPS as u128) as u64
+}
+
+/// Split `revenue` by the fee vault's rates. Without a referrer its share
+/// stays with the liquidity providers.
+pub fn split(revenue: u64, fee_vault: &FeeVault, has_referrer: bool) -> FeeSplit {
+    let share = |bps: u16| (revenue as u128 * bps as u128 / BPS as u128) as u64;
+    FeeSplit {
+        protocol: share(fee_vault.protocol_fee_bps),
+        referrer: if has_referrer {
+            share(fee_vault.referrer_fee_bps)
+        } else {
+            0
+        },
+    }
+}
+
+/// Fee split of a rolled `game` with `stake` at risk
+pub fn game_fees(game: &Game, stake: u64, fee_vault: &FeeVault) -> FeeSplit {
+    let revenue = revenue(stake, risk::edge_bps(game.kind));
+    split(revenue, fee_vault, game.referrer != Pubkey::default())
+}
+
+/// Fee accounts passed after the other accounts of a settle
+pub struct FeeAccounts<'a, 'b> {
+    pub fee_vault: &'b AccountInfo<'a>,
+    /// Fee token account, token pools only
+    pub fee_tokens: Option<&'b AccountInfo<'a>>,
+    /// Referrer token account recorded at commit, or for lamport games the
+    /// referrer's stats. Only passed when the game has a referrer.
+    pub referrer: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> FeeAccounts<'a, 'b> {
+    pub fn next<I: Iterator<Item = &'b AccountInfo<'a>>>(
+        iter: &mut I,
+        kind: TreasuryKind,
+    ) -> Result<Self, ProgramError> {
+        let fee_vault = next_account_info(iter)?;
+        let fee_tokens = match kind {
+            TreasuryKind::Lamports => None,
+            TreasuryKind::TokenPool => Some(next_account_info(iter)?),
+        };
+        Ok(FeeAccounts {
+            fee_vault,
+            fee_tokens,
+            referrer: next_account_info(iter).ok(),
+        })
+    }
+
+    /// Fee vault of `treasury`, `None` when it has not set one up yet
+    pub fn load(&self, program_id: &Pubkey, treasury: &Pubkey) -> Result<Option<FeeVault>, ProgramError> {
+        let (address, _) = FeeVault::find_address(program_id, treasury);
+        assert_key(self.fee_vault, &address)?;
+        if self.fee_vault.owner != program_id {
+            return Ok(None);
+        }
+        let fee_vault = FeeVault::unpack(&self.fee_vault.data.borrow())?;
+        if !fee_vault.is_initialized {
+            return Ok(None);
+        }
+        assert_writable(self.fee_vault)?;
+        Ok(Some(fee_vault))
+    }
+
+    /// The referrer token account a token game was committed with, if any
+    pub fn referrer(&self, game: &Game) -> Result<Option<&'b AccountInfo<'a>>, ProgramError> {
+        if game.referrer == Pubkey::default() {
+            return Ok(None);
+        }
+        let referrer = self.referrer.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        assert_key(referrer, &game.referrer)?;
+        assert_writable(referrer)?;
+        Ok(Some(referrer))
+    }
+}
+
+/// Referrer account of a commit, the optional account after the others.
+/// Players cannot refer themselves.
+pub fn commit_referrer(
+    referrer: Option<&AccountInfo>,
+    player: &Pubkey,
+    payout: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    match referrer {
+        None => Ok(Pubkey::default()),
+        Some(referrer) if referrer.key == player || referrer.key == payout => {
+            info!("Players cannot refer themselves");
+            Err(RollError::IncorrectAddress.into())
+        }
+        Some(referrer) => Ok(*referrer.key),
+    }
+}
+
+/// Take the fees of a rolled lamport game out of the treasury, crediting
+/// the referrer share and the referred volume to the referrer's stats
+pub fn take_lamport_fees(
+    program_id: &Pubkey,
+    game: &Game,
+    stake: u64,
+    treasury_account: &AccountInfo,
+    accounts: &FeeAccounts,
+) -> ProgramResult {
+    let referrer = if game.referrer == Pubkey::default() {
+        None
+    } else {
+        let stats_account = accounts.referrer.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let stats = referral::load_stats(program_id, &game.referrer, stats_account)?;
+        Some((stats_account, stats))
+    };
+    let fee_vault = accounts.load(program_id, treasury_account.key)?;
+
+    let mut fees = match &fee_vault {
+        Some(fee_vault) => game_fees(game, stake, fee_vault),
+        None => FeeSplit::default(),
+    };
+    if fees.total() > treasury_account.lamports() {
+        info!("Treasury cannot cover fees, skipping");
+        fees = FeeSplit::default();
+    }
+    if fees.total() > 0 {
+        info!("Protocol fee, referrer fee:");
+        info!(&fees.protocol.to_string());
+        info!(&fees.referrer.to_string());
+        **treasury_account.lamports.borrow_mut() -= fees.total();
+    }
+
+    if let Some(mut fee_vault) = fee_vault {
+        **accounts.fee_vault.lamports.borrow_mut() += fees.protocol;
+        fee_vault.accrued = fee_vault.accrued.saturating_add(fees.protocol);
+        fee_vault.pack(&mut accounts.fee_vault.data.borrow_mut())?;
+    }
+    if let Some((stats_account, mut stats)) = referrer {
+        **stats_account.lamports.borrow_mut() += fees.referrer;
+        referral::credit(&mut stats, stake, fees.referrer);
+        stats.pack(&mut stats_account.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Take the fees of a rolled token game out of the pool vault
+#[allow(clippy::too_many_arguments)]
+pub fn take_token_fees<'a>(
+    program_id: &Pubkey,
+    game: &Game,
+    stake: u64,
+    pool: &Pool,
+    pool_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    accounts: &FeeAccounts<'a, '_>,
+) -> ProgramResult {
+    let mut fee_vault = match accounts.load(program_id, pool_account.key)? {
+        Some(fee_vault) => fee_vault,
+        None => return Ok(()),
+    };
+    let fee_tokens = accounts.fee_tokens.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    assert_key(fee_tokens, &fee_vault.fee_tokens)?;
+    let referrer = accounts.referrer(game)?;
+    let fees = game_fees(game, stake, &fee_vault);
+
+    info!("Protocol fee, referrer fee:");
+    info!(&fees.protocol.to_string());
+    info!(&fees.referrer.to_string());
+    transfer_from_vault(pool, pool_account, vault_account, fee_tokens, token_program, fees.protocol)?;
+    if let Some(referrer) = referrer {
+        transfer_from_vault(pool, pool_account, vault_account, referrer, token_program, fees.referrer)?;
+    }
+
+    fee_vault.accrued = fee_vault.accrued.saturating_add(fees.protocol);
+    fee_vault.pack(&mut accounts.fee_vault.data.borrow_mut())
+}
+
+/// 13 - create a treasury's fee vault and set its fee split
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2..4] protocol fee
+/// and [4..6] referrer fee, in bps of the house edge. Only the lamport
+/// treasury account itself or a pool's authority may set up its fee vault.
+/// Accounts: payer (signer), owner (signer), fee authority, treasury or
+/// pool, fee vault, rent sysvar, system program. Token pools add the fee
+/// token account, pool mint and token program.
+pub fn process_init_fee_vault(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 6 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let protocol_fee_bps = LittleEndian::read_u16(&data[2..4]);
+    let referrer_fee_bps = LittleEndian::read_u16(&data[4..6]);
+    if protocol_fee_bps as u64 + referrer_fee_bps as u64 > BPS {
+        info!("Fee split exceeds the house edge");
+        return Err(RollError::InvalidAmount.into());
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let owner_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let fee_vault_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_signer(owner_account)?;
+    assert_owned_by(treasury_account, program_id)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let (fee_vault_address, bump_seed) = FeeVault::find_address(program_id, treasury_account.key);
+    assert_key(fee_vault_account, &fee_vault_address)?;
+    let rent = Rent::from_account_info(rent_account)?;
+
+    info!("Creating fee vault");
+    create_pda_account(
+        payer_account,
+        fee_vault_account,
+        system_program_account,
+        program_id,
+        FeeVault::LEN,
+        &rent,
+        &[FEE_VAULT_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let fee_tokens = match kind {
+        TreasuryKind::Lamports => {
+            assert_key(owner_account, treasury_account.key)?;
+            Pubkey::default()
+        }
+        TreasuryKind::TokenPool => {
+            let fee_tokens_account = next_account_info(accounts_iter)?;
+            let mint_account = next_account_info(accounts_iter)?;
+            let token_program = next_account_info(accounts_iter)?;
+            assert_key(token_program, &spl_token::ID)?;
+
+            let pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(owner_account, &pool.authority)?;
+            assert_key(mint_account, &pool.mint)?;
+            let (fee_tokens_address, fee_tokens_bump) = Pubkey::find_program_address(
+                &[FEE_TOKENS_SEED, treasury_account.key.as_ref()],
+                program_id,
+            );
+            assert_key(fee_tokens_account, &fee_tokens_address)?;
+
+            create_pda_account(
+                payer_account,
+                fee_tokens_account,
+                system_program_account,
+                &spl_token::ID,
+                TokenAccount::LEN,
+                &rent,
+                &[FEE_TOKENS_SEED, treasury_account.key.as_ref(), &[fee_tokens_bump]],
+            )?;
+            invoke(
+                &spl_token::instruction::initialize_account(
+                    &spl_token::ID,
+                    fee_tokens_account.key,
+                    mint_account.key,
+                    treasury_account.key,
+                )?,
+                &[
+                    fee_tokens_account.clone(),
+                    mint_account.clone(),
+                    treasury_account.clone(),
+                    rent_account.clone(),
+                    token_program.clone(),
+                ],
+            )?;
+            fee_tokens_address
+        }
+    };
+
+    let fee_vault = FeeVault {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury_account.key,
+        authority: *authority_account.key,
+        protocol_fee_bps,
+        referrer_fee_bps,
+        accrued: 0,
+        fee_tokens,
+    };
+    fee_vault.pack(&mut fee_vault_account.data.borrow_mut())?;
+
+    info!("Fee vault created");
+    Ok(())
+}
+
+/// 14 - claim the protocol fees accrued in a fee vault
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool. Accounts: fee
+/// authority (signer), treasury or pool, fee vault, destination. Token pools
+/// pay to a destination token account and add the fee token account and
+/// token program.
+pub fn process_claim_fees(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let fee_vault_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+
+    assert_signer(authority_account)?;
+    assert_owned_by(treasury_account, program_id)?;
+    assert_program_account(fee_vault_account, program_id)?;
+    assert_writable(destination_account)?;
+    assert_distinct(&[treasury_account, fee_vault_account, destination_account])?;
+
+    let mut fee_vault = FeeVault::unpack(&fee_vault_account.data.borrow())?;
+    if !fee_vault.is_initialized {
+        info!("SolanaRoll fee vault is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    assert_key(treasury_account, &fee_vault.treasury)?;
+    assert_key(authority_account, &fee_vault.authority)?;
+
+    let amount = fee_vault.accrued;
+    if amount == 0 {
+        info!("No fees to claim");
+        return Err(RollError::InvalidAmount.into());
+    }
+    info!("Claiming fees:");
+    info!(&amount.to_string());
+
+    match kind {
+        TreasuryKind::Lamports => {
+            if fee_vault.fee_tokens != Pubkey::default() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            **fee_vault_account.lamports.borrow_mut() -= amount;
+            **destination_account.lamports.borrow_mut() += amount;
+        }
+        TreasuryKind::TokenPool => {
+            let fee_tokens_account = next_account_info(accounts_iter)?;
+            let token_program = next_account_info(accounts_iter)?;
+            assert_key(token_program, &spl_token::ID)?;
+            assert_key(fee_tokens_account, &fee_vault.fee_tokens)?;
+            let pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            transfer_from_vault(
+                &pool,
+                treasury_account,
+                fee_tokens_account,
+                destination_account,
+                token_program,
+                amount,
+            )?;
+        }
+    }
+
+    fee_vault.accrued = 0;
+    fee_vault.pack(&mut fee_vault_account.data.borrow_mut())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::odds::HOUSE_EDGE_BPS;
+
+    #[test]
+    fn test_split() {
+        let fee_vault = FeeVault {
+            protocol_fee_bps: 2_000,
+            referrer_fee_bps: 1_000,
+            ..FeeVault::default()
+        };
+        // 1% of a 1_000_000 wager is 10_000 of house edge
+        let revenue = revenue(1_000_000, HOUSE_EDGE_BPS);
+        assert_eq!(revenue, 10_000);
+        assert_eq!(
+            split(revenue, &fee_vault, true),
+            FeeSplit {
+                protocol: 2_000,
+                referrer: 1_000
+            }
+        );
+        // Without a referrer its share stays with the liquidity providers
+        assert_eq!(split(revenue, &fee_vault, false).total(), 2_000);
+    }
+}
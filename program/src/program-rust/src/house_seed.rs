@@ -0,0 +1,519 @@
+//! House-seeded randomness for token pools
+//!
+//! Slot hashes can be biased by the validator producing the slot, and the
+//! player's reveal by the player. A pool can instead take its randomness
+//! from a hash chain the house commits to up front: the house derives seeds
+//! `s_1..s_n` with `hash(s_i) == s_(i-1)` and stores `s_0` as the head of
+//! the pool's chain PDA `["house_chain", pool]`. Each commit takes the next
+//! seed number, so the house cannot pick which game gets which seed, and
+//! each settle must bring that seed with the house's Ed25519 signature over
+//! the game and seed, verified by an Ed25519 program instruction placed
+//! right before the settle. The seed is mixed with the player's hashed
+//! reveal like a slot hash.
+//!
+//! The house knows every seed and could hold back those losing it the
+//! game, while the player, handed the seed off chain, could hold back those
+//! losing them theirs. So the house posts each seed on chain with
+//! `PostHouseSeed`, which records the slot it was posted, and anyone, the
+//! house or whoever holds the signed seed, may settle the game with it. A
+//! game past the crank delay is settled on its posted seed, and only if the
+//! house never posted one is it settled without, paying the player the
+//! stake and the worst case profit reserved at commit, its seed skipped so
+//! later games are not held up behind it.
+
+use crate::{
+    crank,
+    error::RollError,
+    randomness::{self, RandomnessSource, Roll},
+    state::{Game, HouseChain, Pool, PostedSeed, Randomness, HOUSE_CHAIN_SEED, MAX_PENDING_SEEDS},
+    token_pool::create_pda_account,
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    hash::hash,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, clock::Clock, instructions, rent, rent::Rent, Sysvar},
+};
+
+/// The Ed25519 signature verification program
+pub mod ed25519_program {
+    solana_sdk::declare_id!("Ed25519SigVerify111111111111111111111111111");
+}
+
+/// Offset of the public key in Ed25519 program data holding one signature,
+/// after the count, padding and offsets
+const PUBKEY_OFFSET: usize = 16;
+const SIGNATURE_OFFSET: usize = PUBKEY_OFFSET + 32;
+const MESSAGE_OFFSET: usize = SIGNATURE_OFFSET + 64;
+/// Instruction index the Ed25519 program reads as its own data
+const THIS_INSTRUCTION: u16 = u16::MAX;
+
+/// Seed number `number` of a chain of `length` seeds derived from the
+/// house's `secret`, number 0 being the commitment stored on chain
+pub fn chain_seed(secret: &[u8], length: u64, number: u64) -> [u8; 32] {
+    let mut seed = hash(secret).to_bytes();
+    for _ in number..length {
+        seed = hash(&seed).to_bytes();
+    }
+    seed
+}
+
+/// Whether `seed` is the chain seed after `head` once `skipped` seeds are
+/// skipped, i.e. hashes to `head` `skipped + 1` times
+pub fn follows(seed: &[u8; 32], skipped: u64, head: &[u8; 32]) -> bool {
+    let mut hashed = hash(seed).to_bytes();
+    for _ in 0..skipped {
+        hashed = hash(&hashed).to_bytes();
+    }
+    &hashed == head
+}
+
+/// Message the house signs to reveal `seed` for `game`
+pub fn message(game: &Pubkey, seed: &[u8; 32]) -> [u8; 64] {
+    let mut message = [0u8; 64];
+    message[..32].copy_from_slice(game.as_ref());
+    message[32..].copy_from_slice(seed);
+    message
+}
+
+/// Ed25519 program data verifying one `signature` of `message` by `house`,
+/// all held in the instruction itself
+pub fn ed25519_instruction_data(house: &Pubkey, signature: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut data = vec![0u8; MESSAGE_OFFSET + message.len()];
+    data[0] = 1;
+    let offsets = [
+        SIGNATURE_OFFSET as u16,
+        THIS_INSTRUCTION,
+        PUBKEY_OFFSET as u16,
+        THIS_INSTRUCTION,
+        MESSAGE_OFFSET as u16,
+        message.len() as u16,
+        THIS_INSTRUCTION,
+    ];
+    for (i, offset) in offsets.iter().enumerate() {
+        LittleEndian::write_u16(&mut data[2 + i * 2..4 + i * 2], *offset);
+    }
+    data[PUBKEY_OFFSET..SIGNATURE_OFFSET].copy_from_slice(house.as_ref());
+    data[SIGNATURE_OFFSET..MESSAGE_OFFSET].copy_from_slice(signature);
+    data[MESSAGE_OFFSET..].copy_from_slice(message);
+    data
+}
+
+/// Whether Ed25519 program `data` checks a single signature of `message` by
+/// `house`, read from the instruction itself rather than another one
+pub fn verifies(data: &[u8], house: &Pubkey, message: &[u8]) -> bool {
+    if data.len() < PUBKEY_OFFSET || data[0] != 1 {
+        return false;
+    }
+    let offset = |i: usize| LittleEndian::read_u16(&data[2 + i * 2..4 + i * 2]);
+    if offset(1) != THIS_INSTRUCTION || offset(3) != THIS_INSTRUCTION || offset(6) != THIS_INSTRUCTION {
+        return false;
+    }
+    let pubkey_offset = offset(2) as usize;
+    let message_offset = offset(4) as usize;
+    let message_size = offset(5) as usize;
+    match (
+        data.get(pubkey_offset..pubkey_offset + 32),
+        data.get(message_offset..message_offset + message_size),
+    ) {
+        (Some(pubkey), Some(signed)) => pubkey == house.as_ref() && signed == message,
+        _ => false,
+    }
+}
+
+/// Check the instruction before the current one in `instructions_account`
+/// verifies the house's signature of `seed` for `game`
+fn assert_house_signed(
+    instructions_account: Option<&AccountInfo>,
+    house: &Pubkey,
+    game: &Pubkey,
+    seed: &[u8; 32],
+) -> ProgramResult {
+    let instructions_account = instructions_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    assert_sysvar(instructions_account, &instructions::id())?;
+    let data = instructions_account.try_borrow_data()?;
+    let current = instructions::load_current_index(&data) as usize;
+    let signed = current > 0
+        && match instructions::load_instruction_at(current - 1, &data) {
+            Ok(instr) => instr.program_id == ed25519_program::id() && verifies(&instr.data, house, &message(game, seed)),
+            Err(_) => false,
+        };
+    if !signed {
+        info!("SolanaRoll house seed is not signed by the house");
+        return Err(RollError::InvalidHouseSeed.into());
+    }
+    Ok(())
+}
+
+fn read_seed(data: &[u8]) -> Result<[u8; 32], ProgramError> {
+    if data.len() != 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(data);
+    Ok(seed)
+}
+
+pub(crate) fn load_chain(program_id: &Pubkey, pool: &Pubkey, chain_account: &AccountInfo) -> Result<HouseChain, ProgramError> {
+    let (address, _) = HouseChain::find_address(program_id, pool);
+    assert_key(chain_account, &address)?;
+    assert_program_account(chain_account, program_id)?;
+    let chain = HouseChain::unpack(&chain_account.data.borrow())?;
+    if !chain.is_initialized {
+        info!("SolanaRoll house chain is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(chain)
+}
+
+/// A house-seeded pool's chain; settles also need the instructions sysvar
+pub struct HouseChainSource<'a, 'b> {
+    pub program_id: &'b Pubkey,
+    pub pool: &'b Pubkey,
+    pub clock: &'b AccountInfo<'a>,
+    pub chain: &'b AccountInfo<'a>,
+    pub instructions: Option<&'b AccountInfo<'a>>,
+}
+
+impl RandomnessSource for HouseChainSource<'_, '_> {
+    /// Give `game` the chain's next seed number
+    fn commit(&self, game: &Pubkey, _secret_hash: Option<&[u8; 32]>) -> ProgramResult {
+        let mut chain = load_chain(self.program_id, self.pool, self.chain)?;
+        if chain.assigned - chain.revealed >= MAX_PENDING_SEEDS as u64 {
+            info!("SolanaRoll house chain queue is full");
+            return Err(RollError::HouseQueueFull.into());
+        }
+        let index = chain.assigned as usize % MAX_PENDING_SEEDS;
+        chain.pending[index] = *game;
+        chain.posted[index] = PostedSeed::default();
+        chain.assigned += 1;
+        chain.pack(&mut self.chain.data.borrow_mut())
+    }
+
+    /// Check and consume the house seed in `seed_data`, which must be next in
+    /// the chain and signed by the house in the instruction before this one,
+    /// or else the seed the house posted for the game. A game past the crank
+    /// delay whose seed the house never posted is settled without one.
+    fn roll_seed(
+        &self,
+        game_key: &Pubkey,
+        game: &Game,
+        _reveal_number: Option<u32>,
+        seed_data: Option<&[u8]>,
+    ) -> Result<Roll, ProgramError> {
+        let mut chain = load_chain(self.program_id, self.pool, self.chain)?;
+        if chain.next_pending() != Some(game_key) {
+            info!("SolanaRoll game is not next in the house chain");
+            return Err(RollError::InvalidHouseSeed.into());
+        }
+        let index = chain.revealed as usize % MAX_PENDING_SEEDS;
+        let posted = chain.posted[index];
+        chain.posted[index] = PostedSeed::default();
+        let seed = match seed_data {
+            Some(seed_data) => {
+                let seed = read_seed(seed_data)?;
+                if !follows(&seed, chain.skipped, &chain.head) {
+                    info!("SolanaRoll house seed is not the game's chain seed");
+                    return Err(RollError::InvalidHouseSeed.into());
+                }
+                assert_house_signed(self.instructions, &chain.house, game_key, &seed)?;
+                seed
+            }
+            // Checked when it was posted
+            None if posted.posted => posted.seed,
+            None => {
+                let current_slot = Clock::from_account_info(self.clock)?.slot;
+                if !crank::is_expired(game, current_slot) {
+                    info!("SolanaRoll house seed can still be posted");
+                    return Err(RollError::GameNotExpired.into());
+                }
+                info!("SolanaRoll house never posted the seed, skipping it");
+                chain.revealed += 1;
+                chain.skipped += 1;
+                chain.pack(&mut self.chain.data.borrow_mut())?;
+                return Ok(Roll::Withheld);
+            }
+        };
+
+        chain.head = seed;
+        chain.revealed += 1;
+        chain.skipped = 0;
+        chain.pack(&mut self.chain.data.borrow_mut())?;
+        Ok(Roll::Seed(randomness::mix(game, &seed)))
+    }
+}
+
+/// 30 - commit a token pool's randomness to a house seed chain
+///
+/// Also starts a new chain once one runs out. The pool must have no open
+/// bets. Data: [1..33] house signer, [33..65] chain commitment. Accounts:
+/// payer (signer), pool authority (signer), pool, house chain, rent sysvar,
+/// system program
+pub fn process_init_house_chain(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 65 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let house = Pubkey::new(&data[1..33]);
+    let mut head = [0u8; 32];
+    head.copy_from_slice(&data[33..65]);
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let chain_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_signer(authority_account)?;
+    assert_program_account(pool_account, program_id)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let mut pool = Pool::unpack_initialized(&pool_account.data.borrow())?;
+    assert_key(authority_account, &pool.authority)?;
+    // Open bets were committed without a seed number
+    if pool.escrowed != 0 {
+        info!("SolanaRoll pool has open bets");
+        return Err(RollError::OpenBets.into());
+    }
+
+    let (chain_address, bump_seed) = HouseChain::find_address(program_id, pool_account.key);
+    assert_key(chain_account, &chain_address)?;
+    if chain_account.owner != program_id {
+        info!("Creating house chain");
+        create_pda_account(
+            payer_account,
+            chain_account,
+            system_program_account,
+            program_id,
+            HouseChain::LEN,
+            &Rent::from_account_info(rent_account)?,
+            &[HOUSE_CHAIN_SEED, pool_account.key.as_ref(), &[bump_seed]],
+        )?;
+    }
+    let chain = HouseChain {
+        is_initialized: true,
+        bump_seed,
+        pool: *pool_account.key,
+        house,
+        head,
+        ..HouseChain::default()
+    };
+    chain.pack(&mut chain_account.data.borrow_mut())?;
+
+    pool.randomness = Randomness::HouseChain;
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+
+    info!(&format!("HOUSE_CHAIN pool={} house={}", pool_account.key, house));
+    Ok(())
+}
+
+/// 37 - post the house seed of a committed game on chain
+///
+/// The seed must be the game's in the chain and signed by the house in the
+/// instruction before this one, so anyone holding the signed seed may post
+/// it. Its slot is recorded, and the game is then settled on it even
+/// without a seed, before or after the crank delay. Data: [1..33] game,
+/// [33..65] seed. Accounts: pool, house chain, clock sysvar, instructions
+/// sysvar
+pub fn process_post_house_seed(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 65 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let game = Pubkey::new(&data[1..33]);
+    let seed = read_seed(&data[33..65])?;
+
+    let accounts_iter = &mut accounts.iter();
+    let pool_account = next_account_info(accounts_iter)?;
+    let chain_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let instructions_account = next_account_info(accounts_iter)?;
+
+    assert_sysvar(clock_account, &clock::id())?;
+    let mut chain = load_chain(program_id, pool_account.key, chain_account)?;
+    let position = match chain.position(&game) {
+        Some(position) => position,
+        None => {
+            info!("SolanaRoll game is not waiting for a house seed");
+            return Err(RollError::InvalidHouseSeed.into());
+        }
+    };
+    // Seeds of the games before it are revealed or skipped first
+    if !follows(&seed, chain.skipped + position, &chain.head) {
+        info!("SolanaRoll house seed is not the game's chain seed");
+        return Err(RollError::InvalidHouseSeed.into());
+    }
+    assert_house_signed(Some(instructions_account), &chain.house, &game, &seed)?;
+
+    let slot = Clock::from_account_info(clock_account)?.slot;
+    chain.posted[(chain.revealed + position) as usize % MAX_PENDING_SEEDS] = PostedSeed {
+        posted: true,
+        slot,
+        seed,
+    };
+    chain.pack(&mut chain_account.data.borrow_mut())?;
+
+    info!(&format!("HOUSE_SEED pool={} game={} slot={}", pool_account.key, game, slot));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{crank::CRANK_DELAY_SLOTS, state::GameStatus};
+    use solana_sdk::{clock::Epoch, instruction::Instruction, message::Message};
+
+    #[test]
+    fn test_chain_seeds() {
+        let secret = b"house secret";
+        let head = chain_seed(secret, 10, 0);
+        let first = chain_seed(secret, 10, 1);
+        let second = chain_seed(secret, 10, 2);
+        assert_eq!(hash(&first).to_bytes(), head);
+        assert_eq!(hash(&second).to_bytes(), first);
+        assert_eq!(chain_seed(secret, 10, 10), hash(secret).to_bytes());
+    }
+
+    #[test]
+    fn test_follows_skipped_seeds() {
+        let secret = b"house secret";
+        let head = chain_seed(secret, 10, 0);
+        assert!(follows(&chain_seed(secret, 10, 1), 0, &head));
+        assert!(!follows(&chain_seed(secret, 10, 2), 0, &head));
+        // Seeds 1 and 2 were withheld and skipped
+        assert!(follows(&chain_seed(secret, 10, 3), 2, &head));
+        assert!(!follows(&chain_seed(secret, 10, 3), 1, &head));
+    }
+
+    #[test]
+    fn test_ed25519_data() {
+        let house = Pubkey::new_unique();
+        let signed = message(&Pubkey::new_unique(), &[7u8; 32]);
+        let data = ed25519_instruction_data(&house, &[1u8; 64], &signed);
+        assert!(verifies(&data, &house, &signed));
+        assert!(!verifies(&data, &Pubkey::new_unique(), &signed));
+        assert!(!verifies(&data, &house, &[0u8; 64]));
+
+        // Signatures read from another instruction are not accepted
+        let mut elsewhere = data.clone();
+        LittleEndian::write_u16(&mut elsewhere[4..6], 0);
+        assert!(!verifies(&elsewhere, &house, &signed));
+    }
+
+    #[test]
+    fn test_posted_seeds() {
+        let program_id = Pubkey::new_unique();
+        let (pool, house) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (chain_key, _) = HouseChain::find_address(&program_id, &pool);
+        let (clock_key, instructions_key) = (clock::id(), instructions::id());
+        let secret = b"house secret";
+        let mut game = Game::unpack(&[0u8; Game::LEN]).unwrap();
+        game.status = GameStatus::Committed;
+
+        let mut chain_data = vec![0u8; HouseChain::LEN];
+        HouseChain {
+            is_initialized: true,
+            pool,
+            house,
+            head: chain_seed(secret, 10, 0),
+            ..HouseChain::default()
+        }
+        .pack(&mut chain_data)
+        .unwrap();
+        let mut clock_data = vec![0u8; Clock::size_of()];
+        let (mut pool_lamports, mut pool_data) = (0, vec![]);
+        let (mut chain_lamports, mut clock_lamports, mut instructions_lamports) = (0, 0, 0);
+        let pool_account = AccountInfo::new(&pool, false, false, &mut pool_lamports, &mut pool_data, &program_id, false, Epoch::default());
+        let chain = AccountInfo::new(&chain_key, false, true, &mut chain_lamports, &mut chain_data, &program_id, false, Epoch::default());
+        let mut clock_account = AccountInfo::new(&clock_key, false, false, &mut clock_lamports, &mut clock_data, &clock_key, false, Epoch::default());
+        Clock {
+            slot: 7,
+            ..Clock::default()
+        }
+        .to_account_info(&mut clock_account)
+        .unwrap();
+
+        // The house signs the second game's seed in the instruction before
+        // the post
+        let seed = chain_seed(secret, 10, 2);
+        let mut post = vec![37u8];
+        post.extend_from_slice(second.as_ref());
+        post.extend_from_slice(&seed);
+        let signed = Instruction {
+            program_id: ed25519_program::id(),
+            accounts: vec![],
+            data: ed25519_instruction_data(&house, &[1u8; 64], &message(&second, &seed)),
+        };
+        let posting = Instruction {
+            program_id,
+            accounts: vec![],
+            data: post.clone(),
+        };
+        let mut instructions_data = Message::new(&[signed, posting], None).serialize_instructions();
+        instructions_data.extend_from_slice(&[0, 0]);
+        instructions::store_current_index(&mut instructions_data, 1);
+        let instructions_account = AccountInfo::new(&instructions_key, false, false, &mut instructions_lamports, &mut instructions_data, &instructions_key, false, Epoch::default());
+        let accounts = [pool_account, chain.clone(), clock_account.clone(), instructions_account.clone()];
+
+        let source = HouseChainSource {
+            program_id: &program_id,
+            pool: &pool,
+            clock: &clock_account,
+            chain: &chain,
+            instructions: Some(&instructions_account),
+        };
+        assert_eq!(
+            process_post_house_seed(&program_id, &accounts, &post),
+            Err(RollError::InvalidHouseSeed.into())
+        );
+        source.commit(&first, None).unwrap();
+        source.commit(&second, None).unwrap();
+
+        // Only the seed the house signed for the game can be posted
+        let mut wrong = post.clone();
+        wrong[33..65].copy_from_slice(&chain_seed(secret, 10, 1));
+        assert_eq!(
+            process_post_house_seed(&program_id, &accounts, &wrong),
+            Err(RollError::InvalidHouseSeed.into())
+        );
+        process_post_house_seed(&program_id, &accounts, &post).unwrap();
+        let posted = load_chain(&program_id, &pool, &chain).unwrap().posted[1];
+        assert!(posted.posted);
+        assert_eq!(posted.slot, 7);
+        assert_eq!(posted.seed, seed);
+
+        // The first game's seed was never posted, so it cannot be settled
+        // without one until the crank delay passes
+        assert_eq!(
+            source.roll_seed(&first, &game, None, None),
+            Err(RollError::GameNotExpired.into())
+        );
+        Clock {
+            slot: CRANK_DELAY_SLOTS + 1,
+            ..Clock::default()
+        }
+        .to_account_info(&mut clock_account.clone())
+        .unwrap();
+        assert_eq!(source.roll_seed(&first, &game, None, None), Ok(Roll::Withheld));
+
+        // The second game rolls on its posted seed, even past the delay
+        assert_eq!(
+            source.roll_seed(&second, &game, None, None),
+            Ok(Roll::Seed(randomness::mix(&game, &seed)))
+        );
+        let chain = load_chain(&program_id, &pool, &chain).unwrap();
+        assert_eq!(chain.head, seed);
+        assert_eq!(chain.skipped, 0);
+        assert_eq!(chain.revealed, 2);
+        assert!(!chain.posted[1].posted);
+    }
+}
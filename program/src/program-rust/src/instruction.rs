@@ -0,0 +1,437 @@
+//! Instruction data parsing
+
+use crate::{
+    error::RollError,
+    odds::{self, GameKind},
+    risk::Odds,
+    roulette::{Placement, RouletteBet},
+    state::{Game, GameStatus, Randomness, MAX_ROLLS, WIN_BITMAP_LEN},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Bet placed by a commit instruction
+///
+/// | bytes | field                                              |
+/// |-------|----------------------------------------------------|
+/// | 1     | reveal number                                      |
+/// | 2     | game kind                                          |
+/// | 3..5  | target                                             |
+/// | 5     | roll count, optional                               |
+/// | 6..14 | wager per roll, optional                           |
+/// | 14..  | roulette placement count, then 11 byte chips       |
+/// | then  | hash of the player's secret, VRF pools only        |
+///
+/// Without a roll count the whole fund account balance is wagered on a
+/// single roll. The secret's hash takes the 32 bytes after the roulette
+/// layout, or after byte 14 for other games.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commit {
+    pub reveal_number: u32,
+    pub kind: GameKind,
+    pub target: u16,
+    pub roll_count: u8,
+    pub wager: u64,
+    pub roulette: Option<RouletteBet>,
+    pub secret_hash: Option<[u8; 32]>,
+}
+
+impl Commit {
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < 5 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let reveal_number = data[1] as u32;
+        let kind = GameKind::from_u8(data[2]).ok_or(RollError::InvalidGameKind)?;
+        let target = LittleEndian::read_u16(&data[3..5]);
+
+        if !odds::is_valid_target(kind, target) {
+            info!("Target is outside the allowed odds");
+            return Err(RollError::InvalidTarget.into());
+        }
+
+        let mut roll_count = 0;
+        let mut wager = 0;
+        if data.len() >= 14 {
+            roll_count = data[5];
+            wager = LittleEndian::read_u64(&data[6..14]);
+        }
+        if roll_count > MAX_ROLLS
+            || (roll_count > 0 && wager == 0)
+            || wager.checked_mul(roll_count as u64).is_none()
+            || (kind == GameKind::Roulette && roll_count > 1)
+        {
+            info!("Roll count or wager per roll is invalid");
+            return Err(RollError::InvalidRollCount.into());
+        }
+
+        let roulette = if kind == GameKind::Roulette {
+            if data.len() < 14 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            let bet = RouletteBet::unpack_instruction(&data[14..])?;
+            if !bet.is_valid() {
+                info!("Roulette chip layout is invalid");
+                return Err(RollError::InvalidPlacement.into());
+            }
+            Some(bet)
+        } else {
+            None
+        };
+
+        let secret_start = 14 + roulette
+            .as_ref()
+            .map_or(0, |bet| 1 + bet.placements.len() * Placement::LEN);
+        let secret_hash = data.get(secret_start..secret_start + 32).map(|hash| {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(hash);
+            bytes
+        });
+
+        Ok(Commit {
+            reveal_number,
+            kind,
+            target,
+            roll_count,
+            wager,
+            roulette,
+            secret_hash,
+        })
+    }
+
+    /// Instruction data of the commit for `command`, 0 for the lamport
+    /// treasury or 5 for a token pool. Only the low byte of the reveal
+    /// number is sent.
+    pub fn pack(&self, command: u8) -> Vec<u8> {
+        let mut data = vec![0u8; 14];
+        data[0] = command;
+        data[1] = self.reveal_number as u8;
+        data[2] = self.kind as u8;
+        LittleEndian::write_u16(&mut data[3..5], self.target);
+        data[5] = self.roll_count;
+        LittleEndian::write_u64(&mut data[6..14], self.wager);
+        if let Some(bet) = &self.roulette {
+            data.extend_from_slice(&bet.pack_instruction());
+        }
+        if let Some(secret_hash) = &self.secret_hash {
+            data.extend_from_slice(secret_hash);
+        }
+        data
+    }
+
+    /// Total amount put at stake, `None` when the fund balance is wagered
+    pub fn total_wager(&self) -> Option<u64> {
+        match &self.roulette {
+            Some(bet) => bet.total_wager(),
+            None if self.roll_count > 0 => self.wager.checked_mul(self.roll_count as u64),
+            None => None,
+        }
+    }
+
+    /// Odds of the bet, for its max wager
+    pub fn odds(&self) -> Option<Odds> {
+        match &self.roulette {
+            Some(bet) => Odds::roulette(bet),
+            None => Odds::new(self.kind, self.target),
+        }
+    }
+
+    /// Largest profit the bet can win over every result, the exposure it
+    /// reserves against the treasury. `fund_balance` is the wager of a
+    /// commit without a roll count.
+    pub fn worst_case_profit(&self, fund_balance: u64) -> Option<u64> {
+        match &self.roulette {
+            Some(bet) => bet.worst_case_payout()?.checked_sub(bet.total_wager()?),
+            None if self.roll_count > 0 => odds::profit(self.wager, self.kind, self.target)?
+                .checked_mul(self.roll_count as u64),
+            None => odds::profit(fund_balance, self.kind, self.target),
+        }
+    }
+
+    /// Fresh game state for this commit, with `reserved` exposure and an
+    /// optional `referrer`, default for none. Lamport commits set the fund
+    /// and treasury after.
+    pub fn to_game(
+        &self,
+        hashed_reveal: u64,
+        commit_slot: u64,
+        pool: Pubkey,
+        player: Pubkey,
+        reserved: u64,
+        referrer: Pubkey,
+    ) -> Game {
+        Game {
+            target: self.target,
+            kind: self.kind,
+            roll_count: self.roll_count,
+            hashed_reveal,
+            commit_slot,
+            result: 0,
+            wager: self.wager,
+            wins: [0; WIN_BITMAP_LEN],
+            payout: 0,
+            pool,
+            player,
+            status: GameStatus::Committed,
+            reserved,
+            referrer,
+            fund: Pubkey::default(),
+            treasury: Pubkey::default(),
+        }
+    }
+}
+
+/// Swap into a token pool's mint, then commit
+///
+/// | bytes  | field                                           |
+/// |--------|-------------------------------------------------|
+/// | 1..9   | amount of the player's token to swap            |
+/// | 9..17  | minimum amount out, at least the total wager    |
+/// | 17     | 1 to pay the game back in the player's token    |
+/// | 18..   | commit, laid out as `Commit` from its byte 1    |
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapAndCommit {
+    pub amount_in: u64,
+    pub minimum_amount_out: u64,
+    pub swap_back: bool,
+    pub commit: Commit,
+}
+
+impl SwapAndCommit {
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < 18 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        // `Commit` skips its command byte, which is the swap back flag here
+        let commit = Commit::unpack(&data[17..])?;
+        let minimum_amount_out = LittleEndian::read_u64(&data[9..17]);
+        match commit.total_wager() {
+            Some(total_wager) if total_wager <= minimum_amount_out => {}
+            _ => {
+                info!("Minimum swap output must cover the total wager");
+                return Err(RollError::InvalidAmount.into());
+            }
+        }
+        Ok(SwapAndCommit {
+            amount_in: LittleEndian::read_u64(&data[1..9]),
+            minimum_amount_out,
+            swap_back: data[17] != 0,
+            commit,
+        })
+    }
+}
+
+/// Kind of treasury an instruction works on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TreasuryKind {
+    /// The lamport treasury
+    Lamports = 0,
+    /// A token pool
+    TokenPool = 1,
+}
+
+impl TreasuryKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(TreasuryKind::Lamports),
+            1 => Some(TreasuryKind::TokenPool),
+            _ => None,
+        }
+    }
+}
+
+/// Read-only quote of a bet and a deposit
+///
+/// | bytes  | field                                               |
+/// |--------|-----------------------------------------------------|
+/// | 1      | source, 0 lamport treasury or 1 token pool          |
+/// | 2..10  | hypothetical deposit amount                         |
+/// | 10     | unused                                              |
+/// | 11..   | bet, laid out as `Commit` from its byte 1           |
+///
+/// The bet must have a roll count and wager or a roulette layout; its
+/// reveal number is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub source: TreasuryKind,
+    pub deposit: u64,
+    pub bet: Commit,
+}
+
+impl Quote {
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < 11 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let source = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+        // `Commit` skips its command byte, the unused byte 10 here
+        let bet = Commit::unpack(&data[10..])?;
+        if bet.total_wager().is_none() {
+            info!("Quoted bet needs a wager");
+            return Err(RollError::InvalidRollCount.into());
+        }
+        Ok(Quote {
+            source,
+            deposit: LittleEndian::read_u64(&data[2..10]),
+            bet,
+        })
+    }
+}
+
+/// Admin change a multisig proposes, applied once approved and due
+///
+/// | tag | action             | data                                 |
+/// |-----|--------------------|--------------------------------------|
+/// | 0   | set fees           | [1..3] protocol bps, [3..5] referrer |
+/// | 1   | set Kelly fraction | [1..3] bps                           |
+/// | 2   | set paused         | [1] pause flags                      |
+/// | 3   | set authority      | [1..33] new authority                |
+/// | 4   | set lockup         | [1..9] slots, [9..11] early exit bps |
+/// | 5   | set deposit limits | [1..9] liquidity cap, [9] allowlist  |
+/// | 6   | set allowed        | [1..33] depositor, [33] 1 to allow   |
+/// | 7   | set randomness     | [1] source, as `Randomness`          |
+/// | 8   | set keeper fee     | [1..3] bps                           |
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdminAction {
+    SetFees {
+        protocol_fee_bps: u16,
+        referrer_fee_bps: u16,
+    },
+    SetKellyFraction {
+        kelly_fraction_bps: u16,
+    },
+    SetPaused {
+        paused: u8,
+    },
+    SetAuthority {
+        authority: Pubkey,
+    },
+    SetLockup {
+        lockup_slots: u64,
+        early_exit_fee_bps: u16,
+    },
+    SetDepositLimits {
+        max_liquidity: u64,
+        allowlist_only: bool,
+    },
+    SetAllowed {
+        depositor: Pubkey,
+        allowed: bool,
+    },
+    /// Token pool only
+    SetRandomness {
+        randomness: Randomness,
+    },
+    SetKeeperFee {
+        keeper_fee_bps: u16,
+    },
+}
+
+impl AdminAction {
+    pub const LEN: usize = 34;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let len = match data.first() {
+            Some(0) => 5,
+            Some(1) => 3,
+            Some(2) => 2,
+            Some(3) => 33,
+            Some(4) => 11,
+            Some(5) => 10,
+            Some(6) => 34,
+            Some(7) => 2,
+            Some(8) => 3,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+        if data.len() < len {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(match data[0] {
+            0 => AdminAction::SetFees {
+                protocol_fee_bps: LittleEndian::read_u16(&data[1..3]),
+                referrer_fee_bps: LittleEndian::read_u16(&data[3..5]),
+            },
+            1 => AdminAction::SetKellyFraction {
+                kelly_fraction_bps: LittleEndian::read_u16(&data[1..3]),
+            },
+            2 => AdminAction::SetPaused { paused: data[1] },
+            3 => AdminAction::SetAuthority {
+                authority: Pubkey::new(&data[1..33]),
+            },
+            4 => AdminAction::SetLockup {
+                lockup_slots: LittleEndian::read_u64(&data[1..9]),
+                early_exit_fee_bps: LittleEndian::read_u16(&data[9..11]),
+            },
+            5 => AdminAction::SetDepositLimits {
+                max_liquidity: LittleEndian::read_u64(&data[1..9]),
+                allowlist_only: data[9] != 0,
+            },
+            6 => AdminAction::SetAllowed {
+                depositor: Pubkey::new(&data[1..33]),
+                allowed: data[33] != 0,
+            },
+            7 => AdminAction::SetRandomness {
+                randomness: Randomness::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?,
+            },
+            _ => AdminAction::SetKeeperFee {
+                keeper_fee_bps: LittleEndian::read_u16(&data[1..3]),
+            },
+        })
+    }
+
+    pub fn pack(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        match self {
+            AdminAction::SetFees {
+                protocol_fee_bps,
+                referrer_fee_bps,
+            } => {
+                data[0] = 0;
+                LittleEndian::write_u16(&mut data[1..3], *protocol_fee_bps);
+                LittleEndian::write_u16(&mut data[3..5], *referrer_fee_bps);
+            }
+            AdminAction::SetKellyFraction { kelly_fraction_bps } => {
+                data[0] = 1;
+                LittleEndian::write_u16(&mut data[1..3], *kelly_fraction_bps);
+            }
+            AdminAction::SetPaused { paused } => {
+                data[0] = 2;
+                data[1] = *paused;
+            }
+            AdminAction::SetAuthority { authority } => {
+                data[0] = 3;
+                data[1..33].copy_from_slice(authority.as_ref());
+            }
+            AdminAction::SetLockup {
+                lockup_slots,
+                early_exit_fee_bps,
+            } => {
+                data[0] = 4;
+                LittleEndian::write_u64(&mut data[1..9], *lockup_slots);
+                LittleEndian::write_u16(&mut data[9..11], *early_exit_fee_bps);
+            }
+            AdminAction::SetDepositLimits {
+                max_liquidity,
+                allowlist_only,
+            } => {
+                data[0] = 5;
+                LittleEndian::write_u64(&mut data[1..9], *max_liquidity);
+                data[9] = *allowlist_only as u8;
+            }
+            AdminAction::SetAllowed { depositor, allowed } => {
+                data[0] = 6;
+                data[1..33].copy_from_slice(depositor.as_ref());
+                data[33] = *allowed as u8;
+            }
+            AdminAction::SetRandomness { randomness } => {
+                data[0] = 7;
+                data[1] = *randomness as u8;
+            }
+            AdminAction::SetKeeperFee { keeper_fee_bps } => {
+                data[0] = 8;
+                LittleEndian::write_u16(&mut data[1..3], *keeper_fee_bps);
+            }
+        }
+        data
+    }
+}
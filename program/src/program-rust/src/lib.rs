@@ -9,15 +9,15 @@ use solana_sdk::{
     hash::{Hash, HASH_BYTES},
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program,
     sysvar::{
-        clock::Clock, slot_hashes::SlotHashes, Sysvar,
+        clock, clock::Clock, rent, rent::Rent, slot_hashes, slot_hashes::SlotHashes, Sysvar,
     },
 };
 
 use std::convert::TryInto;
 
 use solana_sdk::program::invoke_signed;
-use spl_token::{instruction};
 use solana_sdk::program_pack::Pack as TokenPack;
 use spl_token::state::{Account as TokenAccount, Mint};
 
@@ -28,6 +28,48 @@ use num_derive::FromPrimitive;
 use solana_sdk::{decode_error::DecodeError};
 use thiserror::Error;
 
+pub mod admin;
+pub mod cpi;
+pub mod crank;
+pub mod deposit_limits;
+pub mod error;
+pub mod fees;
+pub mod house_seed;
+pub mod instruction;
+pub mod lockup;
+pub mod odds;
+pub mod oracle;
+pub mod pause;
+pub mod player_stats;
+pub mod risk;
+pub mod roulette;
+pub mod quote;
+pub mod randomness;
+pub mod referral;
+pub mod roll_history;
+pub mod settle;
+pub mod shares;
+pub mod state;
+pub mod swap;
+pub mod token_pool;
+pub mod treasury;
+pub mod validation;
+pub mod vrf;
+pub mod withdraw_queue;
+
+use error::RollError;
+use fees::FeeAccounts;
+use instruction::{Commit, TreasuryKind};
+use pause::{PAUSE_COMMITS, PAUSE_DEPOSITS, PAUSE_SETTLES};
+use randomness::{RandomnessSource, Roll, SlotHashSource};
+use settle::Outcome;
+use state::{Game, GameStatus};
+
+use validation::{
+    assert_distinct, assert_key, assert_owned_by, assert_program_account, assert_signer,
+    assert_sysvar, assert_writable,
+};
+
 
 fn hash_value<T>(obj: T) -> u64
 where
@@ -128,11 +170,13 @@ fn unpack_mint(data: &[u8]) -> Result<Mint, SwapError> {
     TokenPack::unpack(data).map_err(|_| SwapError::ExpectedMint)
 }
 
-// Declare and export the program's entrypoint
+// Declare and export the program's entrypoint, left out when another
+// program links this crate for its CPI interface
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint_deprecated!(process_instruction);
 
 // Program entrypoint's implementation
-fn process_instruction(
+pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     _instruction_data: &[u8],
@@ -143,16 +187,51 @@ fn process_instruction(
     let accounts_iter = &mut accounts.iter();
 
     // Get command number
-    let command_number = _instruction_data[0];
+    let command_number = *_instruction_data
+        .first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
 
-    // 0 - commit reveal_number_hash and under_number
+    // 0 - commit reveal_number_hash, game kind and target
     // 1 - get roll result, validate, compare, move balances
     // 2 - deposit - mint and accept funds
-    // 3 - withdraw - burn and send funds
+    // 3 - request withdraw - queue treasury tokens for the next epoch's price
+    // 4 - token pool - create pool, vault and treasury token mint for a mint
+    // 5 - token commit - escrow the wager in the pool vault and commit
+    // 6 - token roll - settle a token game from the pool vault
+    // 7 - token deposit - transfer tokens in and mint treasury tokens
+    // 8 - token request withdraw - queue pool treasury tokens for the next epoch's price
+    // 9 - swap and commit - swap any token into a pool's mint and commit
+    // 10 - settle and swap - settle a token game, swapping the payout back
+    // 11 - treasury state - create the lamport treasury's exposure state
+    // 12 - quote - log payout, max wager and treasury token prices, read only
+    // 13 - fee vault - create a treasury's fee vault and set its fee split
+    // 14 - claim fees - send accrued protocol fees to the fee authority
+    // 15 - register referrer - record the player's referrer, once
+    // 16 - claim referral - send a referrer's earnings to the referrer
+    // 17 - player stats - create a player's betting statistics
+    // 18 - roll history - create a treasury's ring buffer of recent rolls
+    // 19 - set paused - pause or resume commits, settles, deposits, withdrawals
+    // 20 - admin - hand a treasury's admin rights to a multisig
+    // 21 - propose - propose a timelocked admin change
+    // 22 - approve - approve a proposed admin change
+    // 23 - execute - apply an approved admin change once due
+    // 24 - withdraw queue - create a treasury's withdrawal queue
+    // 25 - execute withdraw - burn queued treasury tokens and send funds
+    // 26 - set lockup - set the deposit lockup and early exit fee
+    // 27 - set deposit limits - set the liquidity cap and allowlist mode
+    // 28 - set allowed - add or remove a depositor on the allowlist
+    // 29 - oracle - create a treasury's price per share oracle
+    // 30 - house chain - commit a token pool's randomness to a house seed chain
+    // 31 - vrf queue - roll a token pool on its authorized worker's VRF outputs
+    // 32 - fulfill vrf - write the VRF output of a committed game
+    // 33 - set randomness - select a token pool's randomness source
+    // 34 - crank - settle or forfeit an abandoned game for a keeper fee
+    // 35 - set keeper fee - set the share of a cranked game paid to its keeper
+    // 36 - settle batch - crank many abandoned lamport games in one instruction
+    // 37 - post house seed - record the house's signed seed of a committed game
     if command_number == 0 {
         // GAME - COMMIT REVEAL NUMBER
-        let reveal_number = _instruction_data[1] as u32;
-        let under_number = _instruction_data[2] as u32;
+        let commit = Commit::unpack(_instruction_data)?;
 
         let payer_account = next_account_info(accounts_iter)?;
         let game_account = next_account_info(accounts_iter)?;
@@ -162,27 +241,95 @@ fn process_instruction(
             return Err(ProgramError::IncorrectProgramId);
         }
 
-        let mut data = game_account.try_borrow_mut_data()?;
         let sysvar_account = next_account_info(accounts_iter)?;
         let sysvar_slot_history = next_account_info(accounts_iter)?;
         let fund_account = next_account_info(accounts_iter)?;
+        let treasury_account = next_account_info(accounts_iter)?;
+        let treasury_state_account = next_account_info(accounts_iter)?;
+        // Optional, the player's referral account
+        let referrer = referral::registered_referrer(
+            program_id,
+            payer_account.key,
+            next_account_info(accounts_iter).ok(),
+        )?;
 
-        let current_slot = Clock::from_account_info(sysvar_account)?.slot;
-        let hashed_reveal = hash_value(reveal_number);
+        assert_signer(payer_account)?;
+        assert_writable(game_account)?;
+        assert_program_account(fund_account, program_id)?;
+        assert_sysvar(sysvar_account, &clock::id())?;
+        assert_sysvar(sysvar_slot_history, &slot_hashes::id())?;
+        assert_distinct(&[
+            payer_account,
+            game_account,
+            fund_account,
+            treasury_account,
+            treasury_state_account,
+        ])?;
+        let mut treasury = treasury::load_treasury(program_id, treasury_account, treasury_state_account)?;
+        pause::assert_not_paused(treasury.paused, PAUSE_COMMITS)?;
+        withdraw_queue::open_treasury_epoch(&mut treasury, treasury_account.lamports(), None, sysvar_account)?;
+
+        let mut data = game_account.try_borrow_mut_data()?;
+        if Game::unpack(&data)?.status == GameStatus::Committed {
+            info!("Game is already committed");
+            return Err(RollError::GameAlreadyCommitted.into());
+        }
+
+        // Reserve the worst case profit so open bets never promise more
+        // than the treasury holds
+        let wager = commit.total_wager().unwrap_or_else(|| fund_account.lamports());
+        // Only the program debits the fund, so a fund holding the wager now
+        // still covers it at settle, whatever the roll turns out to be
+        if fund_account.lamports() < wager {
+            info!("Fund account does not cover the wager");
+            return Err(RollError::InvalidAmount.into());
+        }
+        let odds = commit.odds().ok_or(RollError::InvalidTarget)?;
+        let exposure = commit
+            .worst_case_profit(wager)
+            .ok_or(RollError::InvalidTarget)?;
+        treasury.reserved = settle::reserve(
+            treasury.reserved,
+            treasury_account.lamports(),
+            wager,
+            odds,
+            exposure,
+            &treasury.risk_config(),
+        )?;
+        treasury.pack(&mut treasury_state_account.data.borrow_mut())?;
+
+        if let Some(bet) = &commit.roulette {
+            bet.pack(&mut data)?;
+        }
 
-        // save game data
-        BigEndian::write_u32(&mut data[0..4], under_number);
-        BigEndian::write_u64(&mut data[4..12], hashed_reveal);
-        BigEndian::write_u64(&mut data[12..20], current_slot);
+        let current_slot = Clock::from_account_info(sysvar_account)?.slot;
+        let hashed_reveal = hash_value(commit.reveal_number);
+
+        // save game data, bound to the fund and treasury it was reserved
+        // against so settles cannot swap either
+        let game = Game {
+            fund: *fund_account.key,
+            treasury: *treasury_account.key,
+            ..commit.to_game(
+                hashed_reveal,
+                current_slot,
+                Pubkey::default(),
+                *payer_account.key,
+                exposure,
+                referrer,
+            )
+        };
+        game.pack(&mut data)?;
+        // Settles only take the fund linked to the game, and keepers find
+        // and crank the game through it if the player never settles
+        crank::link_fund(fund_account, game_account.key)?;
 
     } else if command_number == 1 {
         // GAME PLAY
         let reveal_number = _instruction_data[1] as u32;
-        let under_number = _instruction_data[2] as u32;
 
         let payer_account = next_account_info(accounts_iter)?;
         let game_account = next_account_info(accounts_iter)?;
-        let mut data = game_account.try_borrow_mut_data()?;
         let sysvar_account = next_account_info(accounts_iter)?;
         let sysvar_slot_history = next_account_info(accounts_iter)?;
         let fund_account = next_account_info(accounts_iter)?;
@@ -192,6 +339,11 @@ fn process_instruction(
 
         let treasury_account = next_account_info(accounts_iter)?;
         let user_account = next_account_info(accounts_iter)?;
+        let treasury_state_account = next_account_info(accounts_iter)?;
+        let player_stats_account = next_account_info(accounts_iter)?;
+        let roll_history_account = next_account_info(accounts_iter)?;
+        let oracle_account = next_account_info(accounts_iter)?;
+        let fee_accounts = FeeAccounts::next(accounts_iter, TreasuryKind::Lamports)?;
 
         // The game_account must be owned by the program in order to modify its data
         let account_balance = game_account.lamports();
@@ -200,126 +352,167 @@ fn process_instruction(
             return Err(ProgramError::IncorrectProgramId);
         }
 
-        // confirm same reveal number
-        let hashed_reveal = hash_value(reveal_number);
-        let saved_hashed_reveal = BigEndian::read_u64(&data[4..12]);
-        if saved_hashed_reveal == hashed_reveal {
-            let current_slot = Clock::from_account_info(sysvar_account)?.slot;
-            let saved_slot = BigEndian::read_u64(&data[12..20]);
-            if saved_slot < current_slot {
-
-                // Get slot height of saved transaction
-                let slot_hashes_data = sysvar_slot_history.try_borrow_data()?;
-                let slot_hash = get_slot_hash(&slot_hashes_data, saved_slot);
-
-                // Couldn't find slot_height in recent slots, invalid
-                let mut buf = [0u8; HASH_BYTES];
-                if slot_hash == Hash::new(&buf) {
-                    **fund_account.lamports.borrow_mut() -= fund_account_balance;
-                    **user_account.lamports.borrow_mut() += fund_account_balance;
-                    info!("Block hash invalid, returning funds");
-                } else {
-
-                    info!("Block height and hash valid, obtaining result");
-
-                    let hashed_slot_hash = hash_value(slot_hash);
-                    let val = hash_value(hashed_reveal + hashed_slot_hash);
-                    let result = (val % 100) + 1;
-                    let s: String = result.to_string();
-                    let ss: &str = &s;
-
-                    // Save result
-                    BigEndian::write_u64(&mut data[20..28], result);
-
-                    let under_number_32 = BigEndian::read_u32(&data[0..4]);
-                    let under_number_64 = under_number_32 as u64;
-
-                    let un: String = under_number_64.to_string();
-                    let uns: &str = &un;
-
-                    info!("Rolling for a number under:");
-                    info!(uns);
-                    info!("You rolled a:");
-                    info!(ss);
-
-                    info!("    Fund account balance:");
-                    let fab: String = fund_account_balance.to_string();
-                    let sfab: &str = &fab;
-                    info!(sfab);
-
-                    if fund_account_balance <= 1000 {
-                        info!("Fund Account is Too Low!");
-                        return Err(ProgramError::MissingRequiredSignature);
-                    }
-
-                    // Get the treasury balance - stop if not > 0
-                    let treasury_account_balance = treasury_account.lamports();
-
-                    // TODO: confirm program owns treasury/fund accs
-
-                    let sub_under_number_64 = under_number_64 - 1;
-                    let num = 100 - sub_under_number_64;
-                    let tmp = ((num as f64 / sub_under_number_64 as f64 ) as f64 + (1 as f64)) as f64;
-                    let house = (990 as f64 / 1000 as f64) as f64;
-                    let winning_ratio = ((tmp * house) - (1 as f64)) as f64;
-                    let fund_account_balance_f = fund_account_balance as f64;
-                    let winnings = (fund_account_balance_f * winning_ratio) as u64;
-
-                    let winnings_str: String = winnings.to_string();
-                    let swinnings_str: &str = &winnings_str;
-                    info!("Potential winnings:");
-                    info!(swinnings_str);
-
-                    // TODO: max profit configurable
-                    let treasury_max_profit_f64 = treasury_account_balance as f64 * 0.01;
-                    let treasury_max_profit = treasury_max_profit_f64 as u64;
-                    let treasury_max_profit_str: String = treasury_max_profit.to_string();
-                    let streasury_max_profit_str: &str = &treasury_max_profit_str;
-                    info!("Treasury max profit:");
-                    info!(streasury_max_profit_str);
-
-                    if winnings > treasury_max_profit {
-                        **fund_account.lamports.borrow_mut() -= fund_account_balance;
-                        **user_account.lamports.borrow_mut() += fund_account_balance;
-                        info!("Potential profit exceeds max profit allowed");
-                    } else {
-                        if result >= under_number_64 {
-                            info!("You LOSE! Funds go to treasury");
-                            **fund_account.lamports.borrow_mut() -= fund_account_balance;
-                            **treasury_account.lamports.borrow_mut() += fund_account_balance;
-                            let lose: String = fund_account_balance.to_string();
-                            let slose: &str = &lose;
-                            info!(slose);
-                        } else {
-                            info!("You WIN! Funds go to user");
-                            **fund_account.lamports.borrow_mut() -= fund_account_balance;
-                            let win: String = winnings.to_string();
-                            let swin: &str = &win;
-                            info!(swin);
-
-                            if winnings < treasury_account_balance {
-                                **treasury_account.lamports.borrow_mut() -= winnings;
-                                **user_account.lamports.borrow_mut() += fund_account_balance + winnings;
-                            } else {
-                                **user_account.lamports.borrow_mut() += fund_account_balance;
-                                info!("Treasury not enough for payout, returning funds");
-                            }
-                        }
-                    }
-                }
+        // Fund and treasury balances are moved by the program, so both must be
+        // program owned and no two accounts may alias each other
+        assert_writable(game_account)?;
+        assert_program_account(fund_account, program_id)?;
+        assert_program_account(treasury_account, program_id)?;
+        assert_writable(user_account)?;
+        assert_sysvar(sysvar_account, &clock::id())?;
+        assert_sysvar(sysvar_slot_history, &slot_hashes::id())?;
+        assert_distinct(&[
+            game_account,
+            fund_account,
+            treasury_account,
+            user_account,
+            treasury_state_account,
+        ])?;
+        let mut treasury = treasury::load_treasury(program_id, treasury_account, treasury_state_account)?;
+        withdraw_queue::open_treasury_epoch(&mut treasury, treasury_account.lamports(), None, sysvar_account)?;
 
-            } else {
+        let mut data = game_account.try_borrow_mut_data()?;
+        let mut game = Game::unpack(&data)?;
+        if game.status != GameStatus::Committed || game.pool != Pubkey::default() {
+            info!("Game is not a committed lamport game");
+            return Err(RollError::GameNotCommitted.into());
+        }
+        assert_key(fund_account, &game.fund)?;
+        assert_key(treasury_account, &game.treasury)?;
+        if crank::linked_game(&fund_account.data.borrow()) != Some(*game_account.key) {
+            info!("SolanaRoll fund account is not linked to the game");
+            return Err(RollError::IncorrectAddress.into());
+        }
+        // Only the player, who may be a PDA of a calling program, reveals,
+        // so nobody else can settle with a wrong reveal; they pick the payee
+        assert_signer(payer_account)?;
+        assert_key(payer_account, &game.player)?;
+        game.status = GameStatus::Settled;
+
+        // Release the exposure reserved at commit, whatever the outcome
+        treasury.reserved = treasury.reserved.saturating_sub(game.reserved);
+        treasury.pack(&mut treasury_state_account.data.borrow_mut())?;
+
+        // Paused settles refund pending bets instead of rolling them
+        let roll = if pause::is_paused(treasury.paused, PAUSE_SETTLES) {
+            info!("Settles are paused, returning funds");
+            Roll::Refund
+        } else {
+            SlotHashSource {
+                clock: sysvar_account,
+                slot_hashes: sysvar_slot_history,
+            }
+            .roll_seed(game_account.key, &game, Some(reveal_number), None)?
+        };
+        let seed = match roll {
+            Roll::Seed(seed) => seed,
+            // Slot hashes are never withheld
+            Roll::Refund | Roll::Withheld => {
+                // TODO: fee
+                game.pack(&mut data)?;
                 **fund_account.lamports.borrow_mut() -= fund_account_balance;
                 **user_account.lamports.borrow_mut() += fund_account_balance;
-                // TODO: fee
-                info!("Block height invalid, returning funds");
+                return Ok(());
+            }
+            Roll::Forfeit => {
+                // The stake goes to the treasury, anything funded beyond it
+                // back to the user
+                let stake = settle::stake(&game, &data)
+                    .unwrap_or(fund_account_balance)
+                    .min(fund_account_balance);
+                game.pack(&mut data)?;
+                **fund_account.lamports.borrow_mut() -= fund_account_balance;
+                **treasury_account.lamports.borrow_mut() += stake;
+                **user_account.lamports.borrow_mut() += fund_account_balance - stake;
+                return Ok(());
             }
+        };
 
-        } else {
+        info!("    Fund account balance:");
+        let fab: String = fund_account_balance.to_string();
+        let sfab: &str = &fab;
+        info!(sfab);
+
+        if fund_account_balance <= 1000 {
+            info!("Fund Account is Too Low!");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // Get the treasury balance - stop if not > 0
+        let treasury_account_balance = treasury_account.lamports();
+
+        // Wager, largest possible profit and amount returned for this result
+        let Outcome { wager, winnings, payout } = settle::roll(&mut game, &data, seed, fund_account_balance)?;
+        game.pack(&mut data)?;
+
+        let winnings_str: String = winnings.to_string();
+        let swinnings_str: &str = &winnings_str;
+        info!("Potential winnings:");
+        info!(swinnings_str);
+
+        // Max profit was checked against unreserved liquidity at commit
+        let reserved_str: String = game.reserved.to_string();
+        let sreserved_str: &str = &reserved_str;
+        info!("Reserved exposure:");
+        info!(sreserved_str);
+
+        if wager > fund_account_balance {
+            **fund_account.lamports.borrow_mut() -= fund_account_balance;
+            **user_account.lamports.borrow_mut() += fund_account_balance;
+            info!("Fund account does not cover the wager, returning funds");
+        } else if winnings > game.reserved {
             **fund_account.lamports.borrow_mut() -= fund_account_balance;
             **user_account.lamports.borrow_mut() += fund_account_balance;
-            // TODO: fee
-            info!("Reveal number does not match saved reveal number, returning funds");
+            info!("Potential profit exceeds the exposure reserved at commit");
+        } else {
+            // Anything funded beyond the wager goes back to the user
+            let excess = fund_account_balance - wager;
+            if payout == 0 {
+                info!("You LOSE! Funds go to treasury");
+                **fund_account.lamports.borrow_mut() -= fund_account_balance;
+                **treasury_account.lamports.borrow_mut() += wager;
+                **user_account.lamports.borrow_mut() += excess;
+                let lose: String = wager.to_string();
+                let slose: &str = &lose;
+                info!(slose);
+            } else if payout <= wager {
+                info!("You WIN back part of the wager, rest goes to treasury");
+                **fund_account.lamports.borrow_mut() -= fund_account_balance;
+                **treasury_account.lamports.borrow_mut() += wager - payout;
+                **user_account.lamports.borrow_mut() += excess + payout;
+                let win: String = payout.to_string();
+                let swin: &str = &win;
+                info!(swin);
+            } else {
+                info!("You WIN! Funds go to user");
+                let profit = payout - wager;
+                **fund_account.lamports.borrow_mut() -= fund_account_balance;
+                let win: String = profit.to_string();
+                let swin: &str = &win;
+                info!(swin);
+
+                if profit < treasury_account_balance {
+                    **treasury_account.lamports.borrow_mut() -= profit;
+                    **user_account.lamports.borrow_mut() += fund_account_balance + profit;
+                } else {
+                    **user_account.lamports.borrow_mut() += fund_account_balance;
+                    info!("Treasury not enough for payout, returning funds");
+                    return Ok(());
+                }
+            }
+
+            // Only rolled games pay fees, out of the treasury, and count in
+            // the player's stats and the roll history
+            fees::take_lamport_fees(program_id, &game, wager, treasury_account, &fee_accounts)?;
+            player_stats::update(program_id, &game, wager, payout, player_stats_account)?;
+            roll_history::append(program_id, treasury_account.key, &game, wager, roll_history_account)?;
+            oracle::update(
+                program_id,
+                treasury_account.key,
+                treasury_account.lamports(),
+                None,
+                sysvar_account,
+                oracle_account,
+            )?;
         }
     } else if command_number == 2 {
         // Set accounts
@@ -329,6 +522,32 @@ fn process_instruction(
         let user_token_account = next_account_info(accounts_iter)?;
         let spl_token_program = next_account_info(accounts_iter)?;
         let treasury_account = next_account_info(accounts_iter)?;
+        let treasury_state_account = next_account_info(accounts_iter)?;
+        let deposit_account = next_account_info(accounts_iter)?;
+        let clock_account = next_account_info(accounts_iter)?;
+        let rent_account = next_account_info(accounts_iter)?;
+        let system_program_account = next_account_info(accounts_iter)?;
+        let oracle_account = next_account_info(accounts_iter)?;
+        let allowlist_account = next_account_info(accounts_iter).ok();
+
+        assert_signer(payer_account)?;
+        assert_program_account(fund_account, program_id)?;
+        assert_program_account(treasury_account, program_id)?;
+        assert_owned_by(treasury_token_account, &spl_token::ID)?;
+        assert_key(spl_token_program, &spl_token::ID)?;
+        assert_sysvar(clock_account, &clock::id())?;
+        assert_sysvar(rent_account, &rent::id())?;
+        assert_key(system_program_account, &system_program::id())?;
+        assert_distinct(&[
+            payer_account,
+            fund_account,
+            treasury_token_account,
+            user_token_account,
+            treasury_account,
+            treasury_state_account,
+        ])?;
+        let mut treasury = treasury::load_treasury(program_id, treasury_account, treasury_state_account)?;
+        pause::assert_not_paused(treasury.paused, PAUSE_DEPOSITS)?;
 
         let fund_account_balance = fund_account.lamports();
         let treasury_account_balance = treasury_account.lamports();
@@ -338,6 +557,27 @@ fn process_instruction(
             return Err(ProgramError::InvalidAccountData);
         }
 
+        // Checked before minting: the depositor must be allowlisted if the
+        // treasury is, and the deposit must fit the cap
+        deposit_limits::assert_allowed(
+            program_id,
+            treasury_account.key,
+            treasury.allowlist_only,
+            payer_account.key,
+            allowlist_account,
+        )?;
+        // Only the depositor's own lockup is recorded
+        let previous_balance = token_pool::load_token_account(user_token_account, payer_account.key)?.amount;
+        let capped = deposit_limits::capped_amount(
+            fund_account_balance,
+            treasury_account_balance,
+            treasury.max_liquidity,
+        );
+        if capped < fund_account_balance {
+            info!("Deposit exceeds the treasury cap");
+            return Err(RollError::DepositCapExceeded.into());
+        }
+
         info!("invoke: spl_token::instruction::mint_to");
 
         let treasury_mint = unpack_mint(&treasury_token_account.data.borrow())?;
@@ -347,6 +587,8 @@ fn process_instruction(
         let ssupply = supply.to_string();
         let supply_str: &str = &ssupply;
         info!(supply_str);
+        withdraw_queue::open_treasury_epoch(&mut treasury, treasury_account_balance, Some(supply), clock_account)?;
+        treasury.pack(&mut treasury_state_account.data.borrow_mut())?;
 
         let (mint_address, mint_bump_seed) = Pubkey::find_program_address(&[&payer_account.key.to_bytes(), br"mint"], &spl_token_program.key);
 
@@ -364,7 +606,7 @@ fn process_instruction(
         info!(streasury_account_balance_str);
 
         if supply > 0 && treasury_account_balance > 0 {
-            amount = ((fund_account_balance as f64 / treasury_account_balance as f64) * supply as f64) as u64;
+            amount = shares::deposit_tokens_out(fund_account_balance, treasury_account_balance, supply);
             let amount_str = amount.to_string();
             let samount_str: &str = &amount_str;
             info!("Fund amount ratioed:");
@@ -401,90 +643,106 @@ fn process_instruction(
 
         info!("Mint successful");
 
-        **fund_account.lamports.borrow_mut() -= fund_account_balance;
-        **treasury_account.lamports.borrow_mut() += fund_account_balance;
-
-        info!("Deposit successful");
-
-    } else if command_number == 3 {
-        // Set accounts
-        let user_account = next_account_info(accounts_iter)?;
-        let payer_account = next_account_info(accounts_iter)?;
-        let treasury_token_account = next_account_info(accounts_iter)?;
-        let user_token_account = next_account_info(accounts_iter)?;
-        let spl_token_program = next_account_info(accounts_iter)?;
-        let treasury_account = next_account_info(accounts_iter)?;
-
-        let treasury_account_balance = treasury_account.lamports();
-
-        info!("invoke: spl_token::instruction::burn");
-
-        let treasury_mint = unpack_mint(&treasury_token_account.data.borrow())?;
-
-        info!("Token supply:");
-        let supply = treasury_mint.supply;
-        let ssupply = supply.to_string();
-        let supply_str: &str = &ssupply;
-        info!(supply_str);
-
-        let token_amount = LittleEndian::read_u64(&_instruction_data[1..9]);
-        let amount_str = token_amount.to_string();
-        let samount_str: &str = &amount_str;
-        info!("Burning token amount:");
-        info!(samount_str);
-
-        let treasury_account_balance_str = treasury_account_balance.to_string();
-        let streasury_account_balance_str: &str = &treasury_account_balance_str;
-        info!("Treasury balance:");
-        info!(streasury_account_balance_str);
-
-        let mut amount = token_amount;
-        let amount_str = amount.to_string();
-        let samount_str: &str = &amount_str;
-        info!("Fund amount:");
-        info!(samount_str);
-
-        if supply > 0 && treasury_account_balance > 0 {
-            amount = ((token_amount as f64 / supply as f64) * treasury_account_balance as f64) as u64;
-            let amount_str = amount.to_string();
-            let samount_str: &str = &amount_str;
-            info!("Fund amount ratioed:");
-            info!(samount_str);
-        }
-
-        if amount <= 0 {
-            info!("Withdraw amount must be > 0");
-            return Err(ProgramError::InvalidAccountData);
-        }
-
-        let mint_to_instr = spl_token::instruction::burn(
-            &spl_token::ID,
-            user_token_account.key,
-            treasury_token_account.key,
-            user_account.key,
-            &[],
-            token_amount
+        // The deposit starts the depositor's lockup, weighted against the
+        // treasury tokens they already held
+        lockup::record_deposit(
+            program_id,
+            treasury_account.key,
+            payer_account.key,
+            previous_balance,
+            amount,
+            Clock::from_account_info(clock_account)?.slot,
+            treasury.lockup_slots,
+            payer_account,
+            deposit_account,
+            system_program_account,
+            &Rent::from_account_info(rent_account)?,
         )?;
 
-        let account_infos = &[
-            user_token_account.clone(),
-            treasury_token_account.clone(),
-            spl_token_program.clone(),
-            user_account.clone(),
-        ];
+        **fund_account.lamports.borrow_mut() -= fund_account_balance;
+        **treasury_account.lamports.borrow_mut() += fund_account_balance;
 
-        invoke_signed(
-            &mint_to_instr,
-            account_infos,
-            &[],
+        oracle::update(
+            program_id,
+            treasury_account.key,
+            treasury_account.lamports(),
+            Some(supply.saturating_add(amount)),
+            clock_account,
+            oracle_account,
         )?;
 
-        info!("Burn successful");
-
-        **treasury_account.lamports.borrow_mut() -= amount;
-        **user_account.lamports.borrow_mut() += amount;
+        info!("Deposit successful");
 
-        info!("Withdraw successful");
+    } else if command_number == 3 {
+        withdraw_queue::process_request_withdraw(program_id, accounts, _instruction_data, TreasuryKind::Lamports)?;
+    } else if command_number == 4 {
+        token_pool::process_init_pool(program_id, accounts)?;
+    } else if command_number == 5 {
+        token_pool::process_commit(program_id, accounts, _instruction_data)?;
+    } else if command_number == 6 {
+        token_pool::process_settle(program_id, accounts, _instruction_data)?;
+    } else if command_number == 7 {
+        token_pool::process_deposit(program_id, accounts, _instruction_data)?;
+    } else if command_number == 8 {
+        withdraw_queue::process_request_withdraw(program_id, accounts, _instruction_data, TreasuryKind::TokenPool)?;
+    } else if command_number == 9 {
+        swap::process_swap_and_commit(program_id, accounts, _instruction_data)?;
+    } else if command_number == 10 {
+        swap::process_settle_and_swap(program_id, accounts, _instruction_data)?;
+    } else if command_number == 11 {
+        treasury::process_init_treasury(program_id, accounts)?;
+    } else if command_number == 12 {
+        quote::process_quote(program_id, accounts, _instruction_data)?;
+    } else if command_number == 13 {
+        fees::process_init_fee_vault(program_id, accounts, _instruction_data)?;
+    } else if command_number == 14 {
+        fees::process_claim_fees(program_id, accounts, _instruction_data)?;
+    } else if command_number == 15 {
+        referral::process_register_referrer(program_id, accounts)?;
+    } else if command_number == 16 {
+        referral::process_claim_referral(program_id, accounts)?;
+    } else if command_number == 17 {
+        player_stats::process_init_player_stats(program_id, accounts)?;
+    } else if command_number == 18 {
+        roll_history::process_init_roll_history(program_id, accounts, _instruction_data)?;
+    } else if command_number == 19 {
+        pause::process_set_paused(program_id, accounts, _instruction_data)?;
+    } else if command_number == 20 {
+        admin::process_init_admin(program_id, accounts, _instruction_data)?;
+    } else if command_number == 21 {
+        admin::process_propose(program_id, accounts, _instruction_data)?;
+    } else if command_number == 22 {
+        admin::process_approve(program_id, accounts)?;
+    } else if command_number == 23 {
+        admin::process_execute(program_id, accounts)?;
+    } else if command_number == 24 {
+        withdraw_queue::process_init_withdraw_queue(program_id, accounts, _instruction_data)?;
+    } else if command_number == 25 {
+        withdraw_queue::process_execute_withdraw(program_id, accounts, _instruction_data)?;
+    } else if command_number == 26 {
+        lockup::process_set_lockup(program_id, accounts, _instruction_data)?;
+    } else if command_number == 27 {
+        deposit_limits::process_set_deposit_limits(program_id, accounts, _instruction_data)?;
+    } else if command_number == 28 {
+        deposit_limits::process_set_allowed(program_id, accounts, _instruction_data)?;
+    } else if command_number == 29 {
+        oracle::process_init_oracle(program_id, accounts, _instruction_data)?;
+    } else if command_number == 30 {
+        house_seed::process_init_house_chain(program_id, accounts, _instruction_data)?;
+    } else if command_number == 31 {
+        vrf::process_init_vrf(program_id, accounts, _instruction_data)?;
+    } else if command_number == 32 {
+        vrf::process_fulfill_vrf(program_id, accounts, _instruction_data)?;
+    } else if command_number == 33 {
+        randomness::process_set_randomness(program_id, accounts, _instruction_data)?;
+    } else if command_number == 34 {
+        crank::process_crank(program_id, accounts, _instruction_data)?;
+    } else if command_number == 35 {
+        crank::process_set_keeper_fee(program_id, accounts, _instruction_data)?;
+    } else if command_number == 36 {
+        crank::process_settle_batch(program_id, accounts)?;
+    } else if command_number == 37 {
+        house_seed::process_post_house_seed(program_id, accounts, _instruction_data)?;
     }
 
     Ok(())
@@ -495,6 +753,7 @@ fn process_instruction(
 mod test {
     use super::*;
     use solana_sdk::clock::Epoch;
+    use std::mem;
 
     #[test]
     fn test_sanity() {
@@ -518,11 +777,12 @@ mod test {
 
         let accounts = vec![account];
 
+        // Instructions without a command are rejected, leaving accounts as they were
+        assert_eq!(
+            process_instruction(&program_id, &accounts, &instruction_data),
+            Err(ProgramError::InvalidInstructionData)
+        );
         assert_eq!(LittleEndian::read_u64(&accounts[0].data.borrow()), 0);
-        process_instruction(&program_id, &accounts, &instruction_data).unwrap();
-        assert_eq!(LittleEndian::read_u64(&accounts[0].data.borrow()), 1);
-        process_instruction(&program_id, &accounts, &instruction_data).unwrap();
-        assert_eq!(LittleEndian::read_u64(&accounts[0].data.borrow()), 2);
     }
 }
 
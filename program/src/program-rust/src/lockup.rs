@@ -0,0 +1,268 @@
+//! Deposit lockup of treasury and token pool LPs
+//!
+//! Every deposit records its slot in the depositor's deposit record PDA
+//! `["deposit", treasury, depositor]`, and the depositor must own the token
+//! account the treasury tokens are minted to, so nobody can touch another
+//! LP's lockup. A deposit on top of liquidity already in that account moves
+//! the recorded slot to the average of the old and new slots, weighted by
+//! the old balance and the new tokens, and liquidity past its lockup counts
+//! as deposited exactly one lockup ago. Withdrawals requested within the
+//! treasury's lockup of the recorded slot pay the early exit fee, which
+//! stays in the treasury for the remaining LPs, so LPs gain nothing by
+//! hopping in and out around big bets. LPs without a deposit record, such
+//! as holders of transferred treasury tokens, pay no fee.
+
+use crate::{
+    instruction::TreasuryKind,
+    odds::BPS,
+    state::{LpDeposit, Pool, DEPOSIT_SEED},
+    token_pool::create_pda_account,
+    treasury::load_treasury,
+    validation::{assert_key, assert_program_account, assert_signer},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::rent::Rent,
+};
+
+/// Whether `slot` is within `lockup_slots` of `deposit_slot`
+pub fn is_locked(deposit_slot: u64, lockup_slots: u64, slot: u64) -> bool {
+    slot < deposit_slot.saturating_add(lockup_slots)
+}
+
+/// Fee left in the treasury out of an early withdrawal of `amount`
+pub fn early_exit_fee(amount: u64, early_exit_fee_bps: u16) -> u64 {
+    (amount as u128 * early_exit_fee_bps as u128 / BPS as u128) as u64
+}
+
+/// Slot recorded for a deposit of `amount` treasury tokens at `slot` into
+/// an account already holding `previous_balance`, last recorded at
+/// `recorded_slot` if the depositor has a record
+pub fn weighted_deposit_slot(
+    recorded_slot: Option<u64>,
+    previous_balance: u64,
+    amount: u64,
+    slot: u64,
+    lockup_slots: u64,
+) -> u64 {
+    let unlocked_slot = slot.saturating_sub(lockup_slots);
+    let previous_slot = recorded_slot.map_or(unlocked_slot, |recorded| recorded.max(unlocked_slot));
+    let total = previous_balance as u128 + amount as u128;
+    if total == 0 {
+        return slot;
+    }
+    let elapsed = slot.saturating_sub(previous_slot) as u128;
+    previous_slot + (elapsed * amount as u128 / total) as u64
+}
+
+/// Record a deposit of `amount` treasury tokens by `owner` at `slot` into
+/// their token account holding `previous_balance`, creating their record on
+/// their first deposit
+#[allow(clippy::too_many_arguments)]
+pub fn record_deposit<'a>(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    owner: &Pubkey,
+    previous_balance: u64,
+    amount: u64,
+    slot: u64,
+    lockup_slots: u64,
+    payer: &AccountInfo<'a>,
+    deposit_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    rent: &Rent,
+) -> ProgramResult {
+    let (address, bump_seed) = LpDeposit::find_address(program_id, treasury, owner);
+    assert_key(deposit_account, &address)?;
+    let recorded_slot = if deposit_account.owner == program_id {
+        let record = LpDeposit::unpack(&deposit_account.data.borrow())?;
+        Some(record.deposit_slot).filter(|_| record.is_initialized)
+    } else {
+        create_pda_account(
+            payer,
+            deposit_account,
+            system_program_account,
+            program_id,
+            LpDeposit::LEN,
+            rent,
+            &[DEPOSIT_SEED, treasury.as_ref(), owner.as_ref(), &[bump_seed]],
+        )?;
+        None
+    };
+    let record = LpDeposit {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury,
+        owner: *owner,
+        deposit_slot: weighted_deposit_slot(recorded_slot, previous_balance, amount, slot, lockup_slots),
+    };
+    record.pack(&mut deposit_account.data.borrow_mut())
+}
+
+/// Early exit fee owed by `owner` withdrawing at `slot`, from their deposit
+/// record. LPs without one owe no fee.
+pub fn owed_fee_bps(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    owner: &Pubkey,
+    lockup_slots: u64,
+    early_exit_fee_bps: u16,
+    slot: u64,
+    deposit_account: &AccountInfo,
+) -> Result<u16, ProgramError> {
+    let (address, _) = LpDeposit::find_address(program_id, treasury, owner);
+    assert_key(deposit_account, &address)?;
+    if lockup_slots == 0 || deposit_account.owner != program_id {
+        return Ok(0);
+    }
+    let record = LpDeposit::unpack(&deposit_account.data.borrow())?;
+    if record.is_initialized && is_locked(record.deposit_slot, lockup_slots, slot) {
+        return Ok(early_exit_fee_bps);
+    }
+    Ok(0)
+}
+
+/// 26 - set the deposit lockup and early exit fee of a treasury or pool
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2..10] lockup in
+/// slots, [10..12] early exit fee in bps. Accounts: authority (signer),
+/// treasury or pool, treasury state (lamports only)
+pub fn process_set_lockup(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 12 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let lockup_slots = LittleEndian::read_u64(&data[2..10]);
+    let early_exit_fee_bps = LittleEndian::read_u16(&data[10..12]);
+    if early_exit_fee_bps as u64 > BPS {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    assert_signer(authority_account)?;
+
+    match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            let mut treasury = load_treasury(program_id, treasury_account, state_account)?;
+            assert_key(authority_account, &treasury.authority)?;
+            treasury.lockup_slots = lockup_slots;
+            treasury.early_exit_fee_bps = early_exit_fee_bps;
+            treasury.pack(&mut state_account.data.borrow_mut())?;
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            let mut pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(authority_account, &pool.authority)?;
+            pool.lockup_slots = lockup_slots;
+            pool.early_exit_fee_bps = early_exit_fee_bps;
+            pool.pack(&mut treasury_account.data.borrow_mut())?;
+        }
+    }
+
+    info!(&format!(
+        "LOCKUP treasury={} slots={} fee_bps={}",
+        treasury_account.key, lockup_slots, early_exit_fee_bps
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::clock::Epoch;
+
+    #[test]
+    fn test_lockup() {
+        assert!(is_locked(100, 50, 149));
+        assert!(!is_locked(100, 50, 150));
+        assert!(!is_locked(100, 0, 100));
+        assert!(is_locked(u64::MAX - 1, 50, u64::MAX - 1));
+    }
+
+    #[test]
+    fn test_early_exit_fee() {
+        assert_eq!(early_exit_fee(1_000_000, 50), 5_000);
+        assert_eq!(early_exit_fee(1_000_000, 0), 0);
+        assert_eq!(early_exit_fee(u64::MAX, 10_000), u64::MAX);
+    }
+
+    #[test]
+    fn test_weighted_deposit_slot() {
+        // A first deposit, or one into an empty account, locks from its slot
+        assert_eq!(weighted_deposit_slot(None, 0, 500, 1_000, 100), 1_000);
+        assert_eq!(weighted_deposit_slot(Some(400), 0, 500, 1_000, 100), 1_000);
+        // Liquidity still locked keeps most of its lockup
+        assert_eq!(weighted_deposit_slot(Some(950), 900, 100, 1_000, 100), 955);
+        // Liquidity past its lockup, or without a record, counts as deposited
+        // one lockup ago, so dust barely moves it
+        assert_eq!(weighted_deposit_slot(Some(400), 1_000_000, 1, 1_000, 100), 900);
+        assert_eq!(weighted_deposit_slot(None, 1_000, 1_000, 1_000, 100), 950);
+        assert_eq!(weighted_deposit_slot(Some(u64::MAX), u64::MAX, u64::MAX, u64::MAX, 0), u64::MAX);
+    }
+
+    #[test]
+    fn test_record_deposit() {
+        let (program_id, treasury, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (address, bump_seed) = LpDeposit::find_address(&program_id, &treasury, &owner);
+        let record = LpDeposit {
+            is_initialized: true,
+            bump_seed,
+            treasury,
+            owner,
+            deposit_slot: 950,
+        };
+        let (mut lamports, mut data) = (0, vec![0; LpDeposit::LEN]);
+        record.pack(&mut data).unwrap();
+        let deposit = AccountInfo::new(&address, false, true, &mut lamports, &mut data, &program_id, false, Epoch::default());
+        let system = Pubkey::default();
+        let (mut payer_lamports, mut payer_data) = (0, vec![]);
+        let payer = AccountInfo::new(&owner, true, true, &mut payer_lamports, &mut payer_data, &system, false, Epoch::default());
+        let (mut system_lamports, mut system_data) = (0, vec![]);
+        let system_program = AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default());
+        let rent = Rent::default();
+
+        // A dust deposit on top of a locked balance keeps its lockup
+        record_deposit(&program_id, &treasury, &owner, 900, 100, 1_000, 100, &payer, &deposit, &system_program, &rent).unwrap();
+        assert_eq!(LpDeposit::unpack(&deposit.data.borrow()).unwrap().deposit_slot, 955);
+        assert!(record_deposit(&program_id, &Pubkey::new_unique(), &owner, 0, 1, 1_000, 100, &payer, &deposit, &system_program, &rent).is_err());
+    }
+
+    #[test]
+    fn test_owed_fee_bps() {
+        let (program_id, treasury, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (address, bump_seed) = LpDeposit::find_address(&program_id, &treasury, &owner);
+
+        let record = LpDeposit {
+            is_initialized: true,
+            bump_seed,
+            treasury,
+            owner,
+            deposit_slot: 950,
+        };
+        let (mut lamports, mut data) = (0, vec![0; LpDeposit::LEN]);
+        record.pack(&mut data).unwrap();
+        let deposit = AccountInfo::new(&address, false, true, &mut lamports, &mut data, &program_id, false, Epoch::default());
+        assert_eq!(owed_fee_bps(&program_id, &treasury, &owner, 100, 50, 1_000, &deposit), Ok(50));
+        assert_eq!(owed_fee_bps(&program_id, &treasury, &owner, 100, 50, 1_050, &deposit), Ok(0));
+        assert_eq!(owed_fee_bps(&program_id, &treasury, &owner, 0, 50, 1_000, &deposit), Ok(0));
+        assert!(owed_fee_bps(&program_id, &Pubkey::new_unique(), &owner, 100, 50, 1_000, &deposit).is_err());
+
+        // The depositor's locked tokens transferred to another LP, who has
+        // no deposit record, owe no fee
+        let receiver = Pubkey::new_unique();
+        let (receiver_address, _) = LpDeposit::find_address(&program_id, &treasury, &receiver);
+        let system = Pubkey::default();
+        let (mut lamports, mut data) = (0, vec![]);
+        let missing = AccountInfo::new(&receiver_address, false, true, &mut lamports, &mut data, &system, false, Epoch::default());
+        assert_eq!(owed_fee_bps(&program_id, &treasury, &receiver, 100, 50, 1_000, &missing), Ok(0));
+        assert!(owed_fee_bps(&program_id, &treasury, &owner, 100, 50, 1_000, &missing).is_err());
+    }
+}
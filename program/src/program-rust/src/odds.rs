@@ -0,0 +1,197 @@
+//! Game odds and payout math
+//!
+//! Every game draws its result from the same random value, reduced to the
+//! game's number of outcomes. Dice results are `0..=9999`, so a target
+//! expresses a win probability with two decimal places (e.g. a roll under
+//! 4950 wins 49.50% of the time). All ratios are in basis points to keep the
+//! math in integers. Roulette odds depend on the whole chip layout and live
+//! in the `roulette` module.
+
+use crate::roulette::ROULETTE_OUTCOMES;
+
+/// Number of possible dice results, `0..=9999`
+pub const RESULT_RANGE: u64 = 10_000;
+
+/// Number of coin flip results, heads (0) or tails (1)
+pub const COIN_OUTCOMES: u64 = 2;
+
+/// Number of exact-number results, `0..=99`
+pub const EXACT_OUTCOMES: u64 = 100;
+
+/// Basis points denominator
+pub const BPS: u64 = 10_000;
+
+/// House edge taken from every payout, 1%
+pub const HOUSE_EDGE_BPS: u64 = 100;
+
+/// Smallest number of winning dice results a bet may cover (0.01%)
+pub const MIN_WINNING_RESULTS: u64 = 1;
+
+/// Largest number of winning dice results a bet may cover (98%)
+pub const MAX_WINNING_RESULTS: u64 = 9_800;
+
+/// Game selected by a commit, all settled against the same treasury
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameKind {
+    /// Wins when the dice result is strictly under the target
+    DiceUnder = 0,
+    /// Wins when the dice result is strictly over the target
+    DiceOver = 1,
+    /// Wins when the flip matches the target, heads (0) or tails (1), ~1.98x
+    CoinFlip = 2,
+    /// Wins when the result equals the target in `0..=99`, ~99x
+    ExactNumber = 3,
+    /// European roulette, target unused, see `roulette::RouletteBet`
+    Roulette = 4,
+}
+
+impl GameKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(GameKind::DiceUnder),
+            1 => Some(GameKind::DiceOver),
+            2 => Some(GameKind::CoinFlip),
+            3 => Some(GameKind::ExactNumber),
+            4 => Some(GameKind::Roulette),
+            _ => None,
+        }
+    }
+
+    /// Number of equally likely results the game draws from
+    pub fn outcomes(self) -> u64 {
+        match self {
+            GameKind::DiceUnder | GameKind::DiceOver => RESULT_RANGE,
+            GameKind::CoinFlip => COIN_OUTCOMES,
+            GameKind::ExactNumber => EXACT_OUTCOMES,
+            GameKind::Roulette => ROULETTE_OUTCOMES,
+        }
+    }
+}
+
+/// Map a raw random value onto a result of the given game
+pub fn roll_result(kind: GameKind, random: u64) -> u64 {
+    random % kind.outcomes()
+}
+
+/// Number of results that win the bet, zero for roulette
+pub fn winning_results(kind: GameKind, target: u16) -> u64 {
+    let target = target as u64;
+    match kind {
+        GameKind::Roulette => 0,
+        GameKind::DiceUnder => target.min(RESULT_RANGE),
+        GameKind::DiceOver => (RESULT_RANGE - 1).saturating_sub(target),
+        GameKind::CoinFlip | GameKind::ExactNumber => {
+            if target < kind.outcomes() {
+                1
+            } else {
+                0
+            }
+        }
+    }
+}
+
+/// True if the bet's target is valid for the game and inside the allowed odds
+pub fn is_valid_target(kind: GameKind, target: u16) -> bool {
+    let winning = winning_results(kind, target);
+    match kind {
+        GameKind::DiceUnder | GameKind::DiceOver => {
+            (MIN_WINNING_RESULTS..=MAX_WINNING_RESULTS).contains(&winning)
+        }
+        GameKind::CoinFlip | GameKind::ExactNumber => winning == 1,
+        GameKind::Roulette => true,
+    }
+}
+
+/// True if `result` wins the bet
+pub fn is_win(kind: GameKind, target: u16, result: u64) -> bool {
+    let target = target as u64;
+    match kind {
+        GameKind::DiceUnder => result < target,
+        GameKind::DiceOver => result > target,
+        GameKind::CoinFlip | GameKind::ExactNumber => result == target,
+        GameKind::Roulette => false,
+    }
+}
+
+/// Win probability in basis points
+pub fn win_probability_bps(kind: GameKind, target: u16) -> u64 {
+    winning_results(kind, target) * BPS / kind.outcomes()
+}
+
+/// Payout multiplier (wager included) in basis points, after the house edge.
+/// A 49.50% dice bet returns 20000 (2.00x), a coin flip 19800 (1.98x).
+pub fn multiplier_bps(kind: GameKind, target: u16) -> Option<u64> {
+    let winning = winning_results(kind, target);
+    if winning == 0 {
+        return None;
+    }
+    Some((BPS - HOUSE_EDGE_BPS) * kind.outcomes() / winning)
+}
+
+/// Total amount returned to the player on a win, wager included
+pub fn payout(wager: u64, kind: GameKind, target: u16) -> Option<u64> {
+    let winning = winning_results(kind, target);
+    if winning == 0 {
+        return None;
+    }
+    let payout = wager as u128 * (BPS - HOUSE_EDGE_BPS) as u128 * kind.outcomes() as u128
+        / (winning as u128 * BPS as u128);
+    if payout > u64::MAX as u128 {
+        return None;
+    }
+    Some(payout as u64)
+}
+
+/// Profit paid out of the treasury on a win, wager excluded
+pub fn profit(wager: u64, kind: GameKind, target: u16) -> Option<u64> {
+    payout(wager, kind, target).map(|p| p.saturating_sub(wager))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_even_odds() {
+        // Equivalent of the old "roll under 51" on a 1..=100 die
+        assert_eq!(win_probability_bps(GameKind::DiceUnder, 5000), 5000);
+        assert_eq!(multiplier_bps(GameKind::DiceUnder, 5000), Some(19_800));
+        assert_eq!(payout(1_000_000, GameKind::DiceUnder, 5000), Some(1_980_000));
+        assert_eq!(profit(1_000_000, GameKind::DiceUnder, 5000), Some(980_000));
+    }
+
+    #[test]
+    fn test_over_mirrors_under() {
+        assert_eq!(
+            winning_results(GameKind::DiceOver, 9999 - 2500),
+            winning_results(GameKind::DiceUnder, 2500)
+        );
+        assert!(is_win(GameKind::DiceOver, 7499, 7500));
+        assert!(!is_win(GameKind::DiceOver, 7499, 7499));
+        assert!(is_win(GameKind::DiceUnder, 2500, 2499));
+        assert!(!is_win(GameKind::DiceUnder, 2500, 2500));
+    }
+
+    #[test]
+    fn test_target_bounds() {
+        assert!(!is_valid_target(GameKind::DiceUnder, 0));
+        assert!(is_valid_target(GameKind::DiceUnder, 1));
+        assert!(!is_valid_target(GameKind::DiceUnder, 9900));
+        assert!(!is_valid_target(GameKind::DiceOver, 9999));
+        assert!(is_valid_target(GameKind::DiceOver, 9998));
+        assert_eq!(payout(1, GameKind::DiceUnder, 0), None);
+    }
+
+    #[test]
+    fn test_side_bets() {
+        assert!(is_valid_target(GameKind::CoinFlip, 1));
+        assert!(!is_valid_target(GameKind::CoinFlip, 2));
+        assert!(is_valid_target(GameKind::ExactNumber, 99));
+        assert!(!is_valid_target(GameKind::ExactNumber, 100));
+        assert_eq!(multiplier_bps(GameKind::CoinFlip, 0), Some(19_800));
+        assert_eq!(multiplier_bps(GameKind::ExactNumber, 42), Some(990_000));
+        assert_eq!(payout(100, GameKind::ExactNumber, 42), Some(9_900));
+        assert_eq!(roll_result(GameKind::CoinFlip, 7), 1);
+        assert_eq!(roll_result(GameKind::ExactNumber, 12_345), 45);
+    }
+}
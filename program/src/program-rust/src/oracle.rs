@@ -0,0 +1,215 @@
+//! Treasury token price oracle
+//!
+//! Each treasury can keep a price oracle PDA `["oracle", treasury]`, updated
+//! in the same instruction as every deposit, withdrawal and settle, so it
+//! always holds the latest price per share. It also accumulates the price
+//! over time and snapshots it at the first update of each epoch, so other
+//! programs can read a time-weighted price that a single large bet or
+//! deposit cannot move. Treasuries without one work as before.
+
+use crate::{
+    instruction::TreasuryKind,
+    shares,
+    state::{OracleSnapshot, Pool, PoolOracle, ORACLE_SEED, ORACLE_SNAPSHOTS},
+    token_pool::{create_pda_account, liquidity},
+    validation::{assert_key, assert_owned_by, assert_signer, assert_sysvar, assert_writable},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, clock::Clock, rent, rent::Rent, Sysvar},
+};
+use spl_token::state::Mint;
+
+/// Record a treasury holding `liquidity` backed by `supply` treasury tokens,
+/// accumulating the previous price over the time since the last update and
+/// snapshotting the first update of each epoch
+pub fn observe(oracle: &mut PoolOracle, liquidity: u64, supply: u64, clock: &Clock) {
+    if oracle.last_timestamp != 0 && clock.unix_timestamp > oracle.last_timestamp {
+        let elapsed = (clock.unix_timestamp - oracle.last_timestamp) as u128;
+        oracle.cumulative_price = oracle
+            .cumulative_price
+            .saturating_add(oracle.price as u128 * elapsed);
+    }
+    oracle.liquidity = liquidity;
+    oracle.supply = supply;
+    oracle.price = shares::price_per_share(liquidity, supply);
+    oracle.last_slot = clock.slot;
+    oracle.last_timestamp = oracle.last_timestamp.max(clock.unix_timestamp);
+
+    let new_epoch = match oracle.latest_snapshot() {
+        Some(snapshot) => clock.epoch > snapshot.epoch,
+        None => true,
+    };
+    if new_epoch {
+        oracle.snapshots[oracle.next_snapshot as usize] = OracleSnapshot {
+            epoch: clock.epoch,
+            price: oracle.price,
+            cumulative_price: oracle.cumulative_price,
+            timestamp: oracle.last_timestamp,
+        };
+        oracle.next_snapshot = ((oracle.next_snapshot as usize + 1) % ORACLE_SNAPSHOTS) as u8;
+        oracle.snapshot_count = (oracle.snapshot_count as usize + 1).min(ORACLE_SNAPSHOTS) as u8;
+    }
+}
+
+/// Cumulative price of `oracle` at `timestamp`, counting the latest price
+/// since the last update
+pub fn cumulative_price_at(oracle: &PoolOracle, timestamp: i64) -> u128 {
+    let elapsed = timestamp.saturating_sub(oracle.last_timestamp).max(0) as u128;
+    oracle
+        .cumulative_price
+        .saturating_add(oracle.price as u128 * elapsed)
+}
+
+/// Time-weighted average price between `from` and a later cumulative price
+/// at `timestamp`, `None` if no time has passed
+pub fn twap(from: &OracleSnapshot, cumulative_price: u128, timestamp: i64) -> Option<u64> {
+    if timestamp <= from.timestamp {
+        return None;
+    }
+    let elapsed = (timestamp - from.timestamp) as u128;
+    Some((cumulative_price.saturating_sub(from.cumulative_price) / elapsed) as u64)
+}
+
+/// Update the oracle of `treasury`, if it keeps one, with the treasury's
+/// `liquidity` after the instruction. Settles leave the supply unchanged
+/// and pass `None`.
+pub fn update(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    liquidity: u64,
+    supply: Option<u64>,
+    clock_account: &AccountInfo,
+    oracle_account: &AccountInfo,
+) -> ProgramResult {
+    let (address, _) = PoolOracle::find_address(program_id, treasury);
+    assert_key(oracle_account, &address)?;
+    if oracle_account.owner != program_id {
+        return Ok(());
+    }
+    let mut oracle = PoolOracle::unpack(&oracle_account.data.borrow())?;
+    if !oracle.is_initialized {
+        return Ok(());
+    }
+    assert_writable(oracle_account)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    let clock = Clock::from_account_info(clock_account)?;
+    let supply = supply.unwrap_or(oracle.supply);
+    observe(&mut oracle, liquidity, supply, &clock);
+    oracle.pack(&mut oracle_account.data.borrow_mut())
+}
+
+/// 29 - create a treasury's price oracle
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool. Accounts: payer
+/// (signer), treasury or pool, treasury token mint, oracle, clock sysvar,
+/// rent sysvar, system program, then the vault for token pools
+pub fn process_init_oracle(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_owned_by(treasury_account, program_id)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let treasury_liquidity = match kind {
+        TreasuryKind::Lamports => treasury_account.lamports(),
+        TreasuryKind::TokenPool => {
+            let vault_account = next_account_info(accounts_iter)?;
+            let pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(vault_account, &pool.vault)?;
+            assert_key(mint_account, &pool.lp_mint)?;
+            liquidity(&pool, vault_account)?
+        }
+    };
+    let supply = Mint::unpack(&mint_account.data.borrow())?.supply;
+
+    let (oracle_address, bump_seed) = PoolOracle::find_address(program_id, treasury_account.key);
+    assert_key(oracle_account, &oracle_address)?;
+
+    info!("Creating price oracle");
+    create_pda_account(
+        payer_account,
+        oracle_account,
+        system_program_account,
+        program_id,
+        PoolOracle::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[ORACLE_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let mut oracle = PoolOracle {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury_account.key,
+        ..PoolOracle::default()
+    };
+    observe(&mut oracle, treasury_liquidity, supply, &Clock::from_account_info(clock_account)?);
+    oracle.pack(&mut oracle_account.data.borrow_mut())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shares::PRICE_SCALE;
+
+    fn clock(slot: u64, epoch: u64, unix_timestamp: i64) -> Clock {
+        Clock {
+            slot,
+            epoch,
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    #[test]
+    fn test_time_weighted_price() {
+        let mut oracle = PoolOracle::default();
+        observe(&mut oracle, 1_000, 1_000, &clock(10, 0, 100));
+        assert_eq!(oracle.price, PRICE_SCALE);
+        assert_eq!(oracle.cumulative_price, 0);
+
+        // Price doubles after 10 seconds at 1.0
+        observe(&mut oracle, 2_000, 1_000, &clock(20, 0, 110));
+        assert_eq!(oracle.cumulative_price, 10 * PRICE_SCALE as u128);
+
+        // 10 seconds at 1.0 then 30 at 2.0 average 1.75
+        let start = *oracle.latest_snapshot().unwrap();
+        let average = twap(&start, cumulative_price_at(&oracle, 140), 140).unwrap();
+        assert_eq!(average, PRICE_SCALE * 7 / 4);
+        assert_eq!(twap(&start, start.cumulative_price, start.timestamp), None);
+    }
+
+    #[test]
+    fn test_epoch_snapshots_wrap() {
+        let mut oracle = PoolOracle::default();
+        observe(&mut oracle, 1_000, 1_000, &clock(1, 0, 1));
+        observe(&mut oracle, 1_000, 1_000, &clock(2, 0, 2));
+        assert_eq!(oracle.snapshot_count, 1);
+
+        for epoch in 1..ORACLE_SNAPSHOTS as u64 + 3 {
+            observe(&mut oracle, 1_000 + epoch, 1_000, &clock(epoch * 10, epoch, epoch as i64 * 10));
+        }
+        assert_eq!(oracle.snapshot_count as usize, ORACLE_SNAPSHOTS);
+        assert_eq!(oracle.latest_snapshot().unwrap().epoch, ORACLE_SNAPSHOTS as u64 + 2);
+    }
+}
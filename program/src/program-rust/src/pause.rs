@@ -0,0 +1,92 @@
+//! Emergency pause
+//!
+//! The authority of the lamport treasury or of a token pool can pause
+//! commits, settles, deposits and withdrawals separately. Paused settles
+//! still close pending bets, refunding them instead of rolling, so no wager
+//! is ever locked in. Every change is logged as one line:
+//!
+//! ```text
+//! PAUSED treasury=<address> flags=3
+//! ```
+
+use crate::{
+    error::RollError,
+    instruction::TreasuryKind,
+    state::Pool,
+    treasury::load_treasury,
+    validation::{assert_key, assert_program_account, assert_signer},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// New bets
+pub const PAUSE_COMMITS: u8 = 1;
+/// Rolling pending bets, which are refunded instead
+pub const PAUSE_SETTLES: u8 = 1 << 1;
+/// Liquidity deposits
+pub const PAUSE_DEPOSITS: u8 = 1 << 2;
+/// Liquidity withdrawals
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 3;
+
+/// Every known flag
+pub const PAUSE_ALL: u8 = PAUSE_COMMITS | PAUSE_SETTLES | PAUSE_DEPOSITS | PAUSE_WITHDRAWALS;
+
+/// Whether `flag` is set in `paused`
+pub fn is_paused(paused: u8, flag: u8) -> bool {
+    paused & flag != 0
+}
+
+/// Fails if `flag` is set in `paused`
+pub fn assert_not_paused(paused: u8, flag: u8) -> ProgramResult {
+    if is_paused(paused, flag) {
+        info!("SolanaRoll operation is paused");
+        return Err(RollError::Paused.into());
+    }
+    Ok(())
+}
+
+/// 19 - set the paused operations of a treasury or pool
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool, [2] pause flags.
+/// Accounts: authority (signer), treasury or pool, treasury state (lamports
+/// only)
+pub fn process_set_paused(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 3 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let kind = TreasuryKind::from_u8(data[1]).ok_or(ProgramError::InvalidInstructionData)?;
+    let paused = data[2];
+    if paused & !PAUSE_ALL != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    assert_signer(authority_account)?;
+
+    match kind {
+        TreasuryKind::Lamports => {
+            let state_account = next_account_info(accounts_iter)?;
+            let mut treasury = load_treasury(program_id, treasury_account, state_account)?;
+            assert_key(authority_account, &treasury.authority)?;
+            treasury.paused = paused;
+            treasury.pack(&mut state_account.data.borrow_mut())?;
+        }
+        TreasuryKind::TokenPool => {
+            assert_program_account(treasury_account, program_id)?;
+            let mut pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(authority_account, &pool.authority)?;
+            pool.paused = paused;
+            pool.pack(&mut treasury_account.data.borrow_mut())?;
+        }
+    }
+
+    info!(&format!("PAUSED treasury={} flags={}", treasury_account.key, paused));
+    Ok(())
+}
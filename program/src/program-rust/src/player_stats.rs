@@ -0,0 +1,131 @@
+//! Per-player betting statistics
+//!
+//! A player's stats PDA `["player_stats", player]` is updated in the same
+//! instruction that settles each of their rolled bets, so leaderboards read
+//! authoritative numbers. Stats must be created with `InitPlayerStats`
+//! first; settling is never blocked on them and skips players without any.
+
+use crate::{
+    error::RollError,
+    state::{Game, PlayerStats, PLAYER_STATS_SEED},
+    token_pool::create_pda_account,
+    validation::{assert_key, assert_signer, assert_sysvar, assert_writable},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+
+/// Add a settled bet of `wager` that paid `payout` to `stats`. A win is a
+/// bet that paid back more than its wager.
+pub fn record(stats: &mut PlayerStats, wager: u64, payout: u64, slot: u64) {
+    if stats.bets == 0 {
+        stats.first_bet_slot = slot;
+    }
+    stats.last_bet_slot = stats.last_bet_slot.max(slot);
+    stats.bets = stats.bets.saturating_add(1);
+    stats.total_wagered = stats.total_wagered.saturating_add(wager);
+    stats.total_paid_out = stats.total_paid_out.saturating_add(payout);
+
+    if payout > wager {
+        stats.wins = stats.wins.saturating_add(1);
+        stats.biggest_win = stats.biggest_win.max(payout - wager);
+        stats.current_streak = stats.current_streak.saturating_add(1);
+        stats.longest_streak = stats.longest_streak.max(stats.current_streak);
+    } else {
+        stats.current_streak = 0;
+    }
+}
+
+/// Record a rolled `game` in the stats of its player, if they have any
+pub fn update(
+    program_id: &Pubkey,
+    game: &Game,
+    wager: u64,
+    payout: u64,
+    stats_account: &AccountInfo,
+) -> ProgramResult {
+    let (address, _) = PlayerStats::find_address(program_id, &game.player);
+    assert_key(stats_account, &address)?;
+    if stats_account.owner != program_id {
+        return Ok(());
+    }
+    assert_writable(stats_account)?;
+    let mut stats = PlayerStats::unpack(&stats_account.data.borrow())?;
+    if !stats.is_initialized {
+        return Ok(());
+    }
+    record(&mut stats, wager, payout, game.commit_slot);
+    stats.pack(&mut stats_account.data.borrow_mut())
+}
+
+/// 17 - create a player's stats
+///
+/// The player is the wallet committing lamport games, or the token account
+/// token games pay out to. Accounts: payer (signer), player, player stats,
+/// rent sysvar, system program
+pub fn process_init_player_stats(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let player_account = next_account_info(accounts_iter)?;
+    let stats_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    if *player_account.key == Pubkey::default() {
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    let (stats_address, bump_seed) = PlayerStats::find_address(program_id, player_account.key);
+    assert_key(stats_account, &stats_address)?;
+
+    info!("Creating player stats");
+    create_pda_account(
+        payer_account,
+        stats_account,
+        system_program_account,
+        program_id,
+        PlayerStats::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[PLAYER_STATS_SEED, player_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let stats = PlayerStats {
+        is_initialized: true,
+        bump_seed,
+        player: *player_account.key,
+        ..PlayerStats::default()
+    };
+    stats.pack(&mut stats_account.data.borrow_mut())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_streaks() {
+        let mut stats = PlayerStats::default();
+        record(&mut stats, 100, 198, 10);
+        record(&mut stats, 100, 198, 11);
+        record(&mut stats, 100, 0, 12);
+        record(&mut stats, 100, 500, 13);
+
+        assert_eq!(stats.bets, 4);
+        assert_eq!(stats.wins, 3);
+        assert_eq!(stats.total_wagered, 400);
+        assert_eq!(stats.total_paid_out, 896);
+        assert_eq!(stats.biggest_win, 400);
+        assert_eq!(stats.current_streak, 1);
+        assert_eq!(stats.longest_streak, 2);
+        assert_eq!(stats.first_bet_slot, 10);
+        assert_eq!(stats.last_bet_slot, 13);
+    }
+}
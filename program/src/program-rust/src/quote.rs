@@ -0,0 +1,115 @@
+//! Read-only quotes for clients
+//!
+//! Programs on this runtime cannot return data, so `Quote` reports through
+//! one structured log line that clients read from `simulateTransaction`:
+//!
+//! ```text
+//! QUOTE multiplier_bps=19800 payout=1980 max_wager=10204 price_per_share=1000000000 deposit_out=500
+//! ```
+//!
+//! `payout` is what the bet returns if every roll, or the best roulette
+//! pocket, wins. `price_per_share` is in `shares::PRICE_SCALE` units.
+
+use crate::{
+    error::RollError,
+    instruction::{Quote, TreasuryKind},
+    risk::{self, RiskConfig},
+    shares,
+    state::{Pool, Treasury},
+    token_pool::liquidity,
+    validation::{assert_key, assert_owned_by},
+    withdraw_queue::load_queue,
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::state::Mint;
+
+/// Numbers reported by a quote
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuoteResult {
+    pub multiplier_bps: u64,
+    pub payout: u64,
+    pub max_wager: u64,
+    pub price_per_share: u64,
+    pub deposit_out: u64,
+}
+
+impl QuoteResult {
+    /// Price `quote` against a treasury holding `liquidity` backed by
+    /// `supply` treasury tokens, `reserved` of which is promised to open
+    /// bets, with the treasury's risk `config`
+    pub fn new(
+        quote: &Quote,
+        liquidity: u64,
+        reserved: u64,
+        supply: u64,
+        config: &RiskConfig,
+    ) -> Option<Self> {
+        let odds = quote.bet.odds()?;
+        let wager = quote.bet.total_wager()?;
+        Some(QuoteResult {
+            multiplier_bps: odds.multiplier_bps,
+            payout: wager.checked_add(quote.bet.worst_case_profit(wager)?)?,
+            max_wager: risk::max_wager(odds, liquidity.saturating_sub(reserved), config),
+            price_per_share: shares::price_per_share(liquidity, supply),
+            deposit_out: shares::deposit_tokens_out(quote.deposit, liquidity, supply),
+        })
+    }
+
+    /// The structured log line clients parse
+    pub fn to_log(&self) -> String {
+        format!(
+            "QUOTE multiplier_bps={} payout={} max_wager={} price_per_share={} deposit_out={}",
+            self.multiplier_bps,
+            self.payout,
+            self.max_wager,
+            self.price_per_share,
+            self.deposit_out
+        )
+    }
+}
+
+/// 12 - log a quote for a bet and a deposit, changing nothing
+///
+/// Data is laid out as `Quote`. Accounts for the lamport treasury: treasury,
+/// treasury state, treasury token mint, withdraw queue, which records the
+/// mint. For a token pool: pool, vault, treasury token mint. None of them
+/// need to be writable.
+pub fn process_quote(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let quote = Quote::unpack(data)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let treasury_account = next_account_info(accounts_iter)?;
+    let state_account = next_account_info(accounts_iter)?;
+    let lp_mint_account = next_account_info(accounts_iter)?;
+
+    let (treasury_liquidity, reserved, config) = match quote.source {
+        TreasuryKind::Lamports => {
+            assert_owned_by(state_account, program_id)?;
+            let treasury = Treasury::unpack(&state_account.data.borrow())?;
+            assert_key(treasury_account, &treasury.treasury)?;
+            let queue_account = next_account_info(accounts_iter)?;
+            load_queue(program_id, treasury_account, queue_account, lp_mint_account)?;
+            (treasury_account.lamports(), treasury.reserved, treasury.risk_config())
+        }
+        TreasuryKind::TokenPool => {
+            // Pool first, then its vault
+            assert_owned_by(treasury_account, program_id)?;
+            let pool = Pool::unpack_initialized(&treasury_account.data.borrow())?;
+            assert_key(state_account, &pool.vault)?;
+            assert_key(lp_mint_account, &pool.lp_mint)?;
+            (liquidity(&pool, state_account)?, pool.reserved, pool.risk_config())
+        }
+    };
+    let supply = Mint::unpack(&lp_mint_account.data.borrow())?.supply;
+
+    let result = QuoteResult::new(&quote, treasury_liquidity, reserved, supply, &config)
+        .ok_or(RollError::InvalidTarget)?;
+    info!(&result.to_log());
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! Randomness sources games are rolled on
+//!
+//! Settlement only asks a `RandomnessSource` for a game's roll seed, so the
+//! fairness model is pool config (`Pool::randomness`) rather than part of the
+//! roll. A token pool rolls on the slot hash of the commit slot and the
+//! player's reveal, on the house's signed seed chain (`house_seed`), or on a
+//! VRF output written by the pool's authorized worker mixed with a secret
+//! the player committed to (`vrf`). Lamport treasury games roll on slot
+//! hashes.
+
+use crate::{
+    error::RollError,
+    hash_value,
+    house_seed::{self, HouseChainSource},
+    settle,
+    state::{Game, HouseChain, Pool, Randomness},
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar},
+    vrf::{self, VrfSource},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::slot_hashes,
+};
+
+/// How a settle resolves a committed game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Roll {
+    /// Roll the game on this seed
+    Seed(u64),
+    /// Return the stake to the player
+    Refund,
+    /// The player let the game expire after its randomness was known, so
+    /// the stake goes to the treasury or pool
+    Forfeit,
+    /// The house never posted the game's seed and the crank delay passed,
+    /// so the player is paid as if the game won its worst case
+    Withheld,
+}
+
+/// Where a game's roll seed comes from
+pub trait RandomnessSource {
+    /// Tie `game`, being committed with the hash of the player's secret,
+    /// if any, to the source
+    fn commit(&self, game: &Pubkey, secret_hash: Option<&[u8; 32]>) -> ProgramResult;
+
+    /// Roll of the committed `game` at `game_key`, from the player's
+    /// `reveal_number`, `None` when a keeper cranks the game, and any
+    /// `seed_data` of the settle. A game that is not rolled is refunded or
+    /// forfeited, after logging why. Sources handing out randomness once use
+    /// it up here, whatever the roll.
+    fn roll_seed(
+        &self,
+        game_key: &Pubkey,
+        game: &Game,
+        reveal_number: Option<u32>,
+        seed_data: Option<&[u8]>,
+    ) -> Result<Roll, ProgramError>;
+}
+
+/// Slot hash of the commit slot, mixed with the player's reveal
+pub struct SlotHashSource<'a, 'b> {
+    pub clock: &'b AccountInfo<'a>,
+    pub slot_hashes: &'b AccountInfo<'a>,
+}
+
+impl RandomnessSource for SlotHashSource<'_, '_> {
+    fn commit(&self, _game: &Pubkey, _secret_hash: Option<&[u8; 32]>) -> ProgramResult {
+        Ok(())
+    }
+
+    fn roll_seed(
+        &self,
+        _game_key: &Pubkey,
+        game: &Game,
+        reveal_number: Option<u32>,
+        _seed_data: Option<&[u8]>,
+    ) -> Result<Roll, ProgramError> {
+        match reveal_number {
+            Some(reveal_number) => settle::reveal_seed(game, reveal_number, self.clock, self.slot_hashes),
+            // The player saw the slot hash and never revealed
+            None => {
+                info!("Slot hash games are forfeited when cranked");
+                Ok(Roll::Forfeit)
+            }
+        }
+    }
+}
+
+/// Roll seed of `game` from 32 bytes of randomness, mixed with the player's
+/// hashed reveal like a slot hash
+pub fn mix(game: &Game, randomness: &[u8; 32]) -> u64 {
+    hash_value(game.hashed_reveal.wrapping_add(hash_value(randomness)))
+}
+
+/// Randomness accounts of a token game: the slot hashes sysvar, or the
+/// pool's house chain or VRF queue. On settle a house chain is followed by
+/// the instructions sysvar.
+pub struct RandomnessAccounts<'a, 'b> {
+    pub source: &'b AccountInfo<'a>,
+    pub instructions: Option<&'b AccountInfo<'a>>,
+}
+
+impl<'a, 'b> RandomnessAccounts<'a, 'b> {
+    /// The single randomness account of a commit
+    pub fn commit(source: &'b AccountInfo<'a>) -> Self {
+        RandomnessAccounts { source, instructions: None }
+    }
+
+    /// Randomness accounts of a settle
+    pub fn next<I: Iterator<Item = &'b AccountInfo<'a>>>(iter: &mut I) -> Result<Self, ProgramError> {
+        let source = next_account_info(iter)?;
+        let instructions = if source.data_len() == HouseChain::LEN {
+            Some(next_account_info(iter)?)
+        } else {
+            None
+        };
+        Ok(RandomnessAccounts { source, instructions })
+    }
+}
+
+/// Randomness source of `pool`, configured with `randomness`, over its
+/// accounts
+pub fn source<'a, 'b>(
+    program_id: &'b Pubkey,
+    pool: &'b Pubkey,
+    randomness: Randomness,
+    clock: &'b AccountInfo<'a>,
+    accounts: &RandomnessAccounts<'a, 'b>,
+) -> Result<Box<dyn RandomnessSource + 'b>, ProgramError> {
+    Ok(match randomness {
+        Randomness::SlotHashes => {
+            assert_sysvar(accounts.source, &slot_hashes::id())?;
+            Box::new(SlotHashSource {
+                clock,
+                slot_hashes: accounts.source,
+            })
+        }
+        Randomness::HouseChain => Box::new(HouseChainSource {
+            program_id,
+            pool,
+            clock,
+            chain: accounts.source,
+            instructions: accounts.instructions,
+        }),
+        Randomness::Vrf => Box::new(VrfSource {
+            program_id,
+            pool,
+            clock,
+            queue: accounts.source,
+        }),
+    })
+}
+
+/// 33 - select a token pool's randomness source
+///
+/// The pool must have no open bets, and a house chain or VRF queue must
+/// already be created. Data: [1] source, 0 slot hashes, 1 house chain, 2
+/// VRF. Accounts: pool authority (signer), pool, slot hashes sysvar or the
+/// pool's house chain or VRF queue
+pub fn process_set_randomness(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let randomness = data
+        .get(1)
+        .and_then(|value| Randomness::from_u8(*value))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let authority_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let source_account = next_account_info(accounts_iter)?;
+
+    assert_signer(authority_account)?;
+    assert_program_account(pool_account, program_id)?;
+
+    let mut pool = Pool::unpack_initialized(&pool_account.data.borrow())?;
+    assert_key(authority_account, &pool.authority)?;
+    set_randomness(program_id, pool_account.key, &mut pool, randomness, source_account)?;
+    pool.pack(&mut pool_account.data.borrow_mut())
+}
+
+/// Roll `pool` on `randomness` from `source_account`, its slot hashes
+/// sysvar or created house chain or VRF queue, once it has no open bets
+pub(crate) fn set_randomness(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    pool: &mut Pool,
+    randomness: Randomness,
+    source_account: &AccountInfo,
+) -> ProgramResult {
+    // Open bets were committed against the current source
+    if pool.escrowed != 0 {
+        info!("SolanaRoll pool has open bets");
+        return Err(RollError::OpenBets.into());
+    }
+    match randomness {
+        Randomness::SlotHashes => assert_sysvar(source_account, &slot_hashes::id())?,
+        Randomness::HouseChain => {
+            house_seed::load_chain(program_id, pool_key, source_account)?;
+        }
+        Randomness::Vrf => {
+            vrf::load_queue(program_id, pool_key, source_account)?;
+        }
+    }
+    pool.randomness = randomness;
+
+    info!(&format!("RANDOMNESS pool={} source={}", pool_key, randomness as u8));
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Referrer registry for lamport games
+//!
+//! A player registers a referrer once in their referral PDA
+//! `["referral", player]`. Every lamport bet they commit afterwards records
+//! that referrer, and on settle the referrer share of the house edge is
+//! credited to the referrer's stats PDA `["referrer", referrer]`, which
+//! holds the lamports until the referrer claims them.
+
+use crate::{
+    error::RollError,
+    state::{Referral, ReferrerStats, REFERRAL_SEED, REFERRER_SEED},
+    token_pool::create_pda_account,
+    validation::{
+        assert_distinct, assert_key, assert_program_account, assert_signer, assert_sysvar,
+        assert_writable,
+    },
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+
+/// Referrer registered by `player`, read from their referral account when
+/// one is passed. Unregistered players have none.
+pub fn registered_referrer(
+    program_id: &Pubkey,
+    player: &Pubkey,
+    referral_account: Option<&AccountInfo>,
+) -> Result<Pubkey, ProgramError> {
+    let referral_account = match referral_account {
+        Some(referral_account) => referral_account,
+        None => return Ok(Pubkey::default()),
+    };
+    let (address, _) = Referral::find_address(program_id, player);
+    assert_key(referral_account, &address)?;
+    if referral_account.owner != program_id {
+        return Ok(Pubkey::default());
+    }
+    let referral = Referral::unpack(&referral_account.data.borrow())?;
+    if !referral.is_initialized {
+        return Ok(Pubkey::default());
+    }
+    Ok(referral.referrer)
+}
+
+/// Load the stats of `referrer`, checking the account's address
+pub fn load_stats(
+    program_id: &Pubkey,
+    referrer: &Pubkey,
+    stats_account: &AccountInfo,
+) -> Result<ReferrerStats, ProgramError> {
+    let (address, _) = ReferrerStats::find_address(program_id, referrer);
+    assert_key(stats_account, &address)?;
+    assert_program_account(stats_account, program_id)?;
+    let stats = ReferrerStats::unpack(&stats_account.data.borrow())?;
+    if !stats.is_initialized {
+        info!("SolanaRoll referrer stats are not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(stats)
+}
+
+/// Record a settled bet of `volume` that earned the referrer `earnings`.
+/// The caller moves the earned lamports into the stats account.
+pub fn credit(stats: &mut ReferrerStats, volume: u64, earnings: u64) {
+    stats.referred_volume = stats.referred_volume.saturating_add(volume);
+    stats.earnings = stats.earnings.saturating_add(earnings);
+    stats.claimable = stats.claimable.saturating_add(earnings);
+}
+
+/// 15 - register the player's referrer, once
+///
+/// Creates the referrer's stats on their first referral. Accounts: player
+/// (signer), player referral, referrer, referrer stats, rent sysvar, system
+/// program
+pub fn process_register_referrer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let player_account = next_account_info(accounts_iter)?;
+    let referral_account = next_account_info(accounts_iter)?;
+    let referrer_account = next_account_info(accounts_iter)?;
+    let stats_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(player_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    if player_account.key == referrer_account.key {
+        info!("Players cannot refer themselves");
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    let (referral_address, referral_bump) = Referral::find_address(program_id, player_account.key);
+    let (stats_address, stats_bump) = ReferrerStats::find_address(program_id, referrer_account.key);
+    assert_key(referral_account, &referral_address)?;
+    assert_key(stats_account, &stats_address)?;
+    let rent = Rent::from_account_info(rent_account)?;
+
+    // Fails if the player already registered a referrer
+    info!("Registering referrer");
+    create_pda_account(
+        player_account,
+        referral_account,
+        system_program_account,
+        program_id,
+        Referral::LEN,
+        &rent,
+        &[REFERRAL_SEED, player_account.key.as_ref(), &[referral_bump]],
+    )?;
+    let referral = Referral {
+        is_initialized: true,
+        bump_seed: referral_bump,
+        player: *player_account.key,
+        referrer: *referrer_account.key,
+    };
+    referral.pack(&mut referral_account.data.borrow_mut())?;
+
+    let mut stats = if stats_account.owner == program_id {
+        load_stats(program_id, referrer_account.key, stats_account)?
+    } else {
+        create_pda_account(
+            player_account,
+            stats_account,
+            system_program_account,
+            program_id,
+            ReferrerStats::LEN,
+            &rent,
+            &[REFERRER_SEED, referrer_account.key.as_ref(), &[stats_bump]],
+        )?;
+        ReferrerStats {
+            is_initialized: true,
+            bump_seed: stats_bump,
+            referrer: *referrer_account.key,
+            ..ReferrerStats::default()
+        }
+    };
+    stats.referrals = stats.referrals.saturating_add(1);
+    stats.pack(&mut stats_account.data.borrow_mut())
+}
+
+/// 16 - claim the referrer's earnings
+///
+/// Accounts: referrer (signer), referrer stats, destination
+pub fn process_claim_referral(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let referrer_account = next_account_info(accounts_iter)?;
+    let stats_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+
+    assert_signer(referrer_account)?;
+    assert_writable(destination_account)?;
+    assert_distinct(&[stats_account, destination_account])?;
+    let mut stats = load_stats(program_id, referrer_account.key, stats_account)?;
+
+    let amount = stats.claimable;
+    if amount == 0 {
+        info!("No referral earnings to claim");
+        return Err(RollError::InvalidAmount.into());
+    }
+    info!("Claiming referral earnings:");
+    info!(&amount.to_string());
+    **stats_account.lamports.borrow_mut() -= amount;
+    **destination_account.lamports.borrow_mut() += amount;
+
+    stats.claimable = 0;
+    stats.pack(&mut stats_account.data.borrow_mut())
+}
@@ -0,0 +1,150 @@
+//! Bet size limits
+//!
+//! The treasury takes the other side of every bet. Putting a share `x` of
+//! its liquidity at risk on a bet paying `m` times the wager with win
+//! probability `p`, it loses `x` with probability `p` and wins `x / (m - 1)`
+//! otherwise. Maximizing expected log growth gives the Kelly bound
+//! `x = 1 - p * m`, which is the bet's house edge: the treasury may risk
+//! its edge share of liquidity on any one bet. Wagers are capped so their
+//! profit stays within a configurable fraction of that bound, which makes
+//! long shots smaller bets than even money ones.
+
+use crate::{
+    odds::{self, GameKind, BPS, HOUSE_EDGE_BPS},
+    roulette::{RouletteBet, ROULETTE_OUTCOMES},
+};
+
+/// Share of the Kelly bound risked by default, full Kelly. With a 1% edge
+/// this is the original 1% of the treasury as max profit.
+pub const DEFAULT_KELLY_FRACTION_BPS: u64 = BPS;
+
+/// Expected share of every wager kept by the treasury for a game kind.
+/// Every European roulette placement has the single zero's 1/37 edge.
+pub fn edge_bps(kind: GameKind) -> u64 {
+    if kind == GameKind::Roulette {
+        BPS / ROULETTE_OUTCOMES
+    } else {
+        HOUSE_EDGE_BPS
+    }
+}
+
+/// Odds of a bet as seen by the treasury
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Odds {
+    /// Chance the player wins
+    pub win_probability_bps: u64,
+    /// Amount returned on a win per unit wagered, wager included
+    pub multiplier_bps: u64,
+    /// Expected share of the wager kept by the treasury
+    pub edge_bps: u64,
+}
+
+impl Odds {
+    /// Odds of a dice, coin flip or exact number bet
+    pub fn new(kind: GameKind, target: u16) -> Option<Self> {
+        if !odds::is_valid_target(kind, target) || kind == GameKind::Roulette {
+            return None;
+        }
+        Some(Odds {
+            win_probability_bps: odds::win_probability_bps(kind, target),
+            multiplier_bps: odds::multiplier_bps(kind, target)?,
+            edge_bps: edge_bps(kind),
+        })
+    }
+
+    /// Odds of a roulette layout, treated as one bet paying its worst case
+    pub fn roulette(bet: &RouletteBet) -> Option<Self> {
+        let total = bet.total_wager()?;
+        if total == 0 {
+            return None;
+        }
+        let multiplier_bps = (bet.worst_case_payout()? as u128 * BPS as u128 / total as u128) as u64;
+        let edge_bps = edge_bps(GameKind::Roulette);
+        Some(Odds {
+            win_probability_bps: (BPS - edge_bps) * BPS / multiplier_bps.max(1),
+            multiplier_bps,
+            edge_bps,
+        })
+    }
+}
+
+/// Risk settings of a treasury
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RiskConfig {
+    /// Share of the Kelly bound a single bet may put at risk
+    pub kelly_fraction_bps: u64,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            kelly_fraction_bps: DEFAULT_KELLY_FRACTION_BPS,
+        }
+    }
+}
+
+/// Largest wager a bet with `odds` may place against `liquidity`
+///
+/// A bet that cannot win more than its wager never puts the treasury at
+/// risk and is only limited by `u64::MAX`.
+pub fn max_wager(odds: Odds, liquidity: u64, config: &RiskConfig) -> u64 {
+    if odds.multiplier_bps <= BPS {
+        return u64::MAX;
+    }
+    // wager * (m - 1) <= liquidity * edge * fraction
+    let max = liquidity as u128 * odds.edge_bps as u128 * config.kelly_fraction_bps as u128
+        / (BPS as u128 * (odds.multiplier_bps - BPS) as u128);
+    if max > u64::MAX as u128 {
+        u64::MAX
+    } else {
+        max as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::roulette::{BetType, Placement};
+
+    #[test]
+    fn test_even_money_matches_flat_max_profit() {
+        let odds = Odds::new(GameKind::DiceUnder, 5000).unwrap();
+        let wager = max_wager(odds, 1_000_000, &RiskConfig::default());
+        assert_eq!(wager, 10_204);
+        // profit of the largest wager is 1% of liquidity
+        assert_eq!(odds::profit(wager, GameKind::DiceUnder, 5000), Some(9_999));
+    }
+
+    #[test]
+    fn test_long_shots_bet_less() {
+        let config = RiskConfig::default();
+        let even = max_wager(Odds::new(GameKind::CoinFlip, 0).unwrap(), 1_000_000, &config);
+        let exact = max_wager(Odds::new(GameKind::ExactNumber, 7).unwrap(), 1_000_000, &config);
+        assert!(exact * 50 < even);
+
+        let half = RiskConfig {
+            kelly_fraction_bps: BPS / 2,
+        };
+        assert_eq!(
+            max_wager(Odds::new(GameKind::CoinFlip, 0).unwrap(), 1_000_000, &half),
+            even / 2
+        );
+    }
+
+    #[test]
+    fn test_roulette_edge() {
+        let bet = RouletteBet {
+            placements: vec![Placement {
+                bet_type: BetType::Straight,
+                number: 17,
+                second: 0,
+                amount: 10,
+            }],
+        };
+        let odds = Odds::roulette(&bet).unwrap();
+        assert_eq!(odds.multiplier_bps, 360_000);
+        assert_eq!(odds.edge_bps, 270);
+        // 2.7% of liquidity over 35:1
+        assert_eq!(max_wager(odds, 1_000_000, &RiskConfig::default()), 771);
+    }
+}
@@ -0,0 +1,131 @@
+//! On-chain history of recent rolls
+//!
+//! Each treasury can keep a roll history PDA `["roll_history", treasury]`
+//! holding its last `ROLL_HISTORY_CAPACITY` settled bets. Settles append to
+//! it in the same instruction, so the history is as authoritative as the
+//! games themselves, and clients or other programs read it in place.
+//! Treasuries without one settle as before.
+
+use crate::{
+    instruction::TreasuryKind,
+    state::{Game, Pool, RollHistory, RollRecord, ROLL_HISTORY_SEED},
+    token_pool::create_pda_account,
+    validation::{assert_key, assert_owned_by, assert_signer, assert_sysvar, assert_writable},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+
+/// Append a rolled `game` with `wager` at stake to the history of
+/// `treasury`, if it keeps one
+pub fn append(
+    program_id: &Pubkey,
+    treasury: &Pubkey,
+    game: &Game,
+    wager: u64,
+    history_account: &AccountInfo,
+) -> ProgramResult {
+    let (address, _) = RollHistory::find_address(program_id, treasury);
+    assert_key(history_account, &address)?;
+    if history_account.owner != program_id {
+        return Ok(());
+    }
+    assert_writable(history_account)?;
+    let mut data = history_account.try_borrow_mut_data()?;
+    let history = RollHistory::load_mut(&mut data)?;
+    if history.is_initialized == 0 {
+        return Ok(());
+    }
+    history.append(RollRecord::new(game, wager));
+    Ok(())
+}
+
+/// 18 - create a treasury's roll history
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool. Accounts: payer
+/// (signer), treasury or pool, roll history, rent sysvar, system program
+pub fn process_init_roll_history(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let history_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_owned_by(treasury_account, program_id)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    if kind == TreasuryKind::TokenPool {
+        Pool::unpack_initialized(&treasury_account.data.borrow())?;
+    }
+
+    let (history_address, bump_seed) = RollHistory::find_address(program_id, treasury_account.key);
+    assert_key(history_account, &history_address)?;
+
+    info!("Creating roll history");
+    create_pda_account(
+        payer_account,
+        history_account,
+        system_program_account,
+        program_id,
+        RollHistory::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[ROLL_HISTORY_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let mut data = history_account.try_borrow_mut_data()?;
+    let history = RollHistory::load_mut(&mut data)?;
+    history.is_initialized = 1;
+    history.bump_seed = bump_seed;
+    history.treasury.copy_from_slice(treasury_account.key.as_ref());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::ROLL_HISTORY_CAPACITY;
+
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let mut data = vec![0u8; RollHistory::LEN];
+        let history = RollHistory::load_mut(&mut data).unwrap();
+        for i in 0..ROLL_HISTORY_CAPACITY as u64 + 3 {
+            history.append(RollRecord {
+                wager: i.to_le_bytes(),
+                ..RollRecord::default()
+            });
+        }
+
+        let history = RollHistory::load(&data).unwrap();
+        assert_eq!(history.total(), ROLL_HISTORY_CAPACITY as u64 + 3);
+        let wagers: Vec<u64> = history.recent().map(|record| record.wager()).collect();
+        assert_eq!(wagers.len(), ROLL_HISTORY_CAPACITY);
+        assert_eq!(wagers[0], ROLL_HISTORY_CAPACITY as u64 + 2);
+        assert_eq!(wagers[ROLL_HISTORY_CAPACITY - 1], 3);
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(std::mem::size_of::<RollRecord>(), 72);
+        assert_eq!(RollHistory::LEN, 48 + 72 * ROLL_HISTORY_CAPACITY);
+        // Creatable through a cross-program call
+        assert!(RollHistory::LEN <= solana_sdk::entrypoint::MAX_PERMITTED_DATA_INCREASE);
+    }
+}
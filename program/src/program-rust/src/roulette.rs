@@ -0,0 +1,331 @@
+//! European roulette placements and payouts
+//!
+//! A roulette game account holds the usual `Game` header followed by the
+//! chip layout, big endian like the rest of the game data:
+//!
+//! | bytes          | field                                   |
+//! |----------------|-----------------------------------------|
+//! | 226            | placement count                         |
+//! | 227 + 11 * i   | bet type, number, second number, amount |
+//!
+//! The result is drawn from `0..=36` using the same random value as dice.
+
+use crate::state::Game;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use solana_sdk::{entrypoint_deprecated::ProgramResult, program_error::ProgramError};
+
+/// Number of pockets on a European wheel, `0..=36`
+pub const ROULETTE_OUTCOMES: u64 = 37;
+
+/// Largest number of chips one bet may place
+pub const MAX_PLACEMENTS: usize = 16;
+
+const RED_NUMBERS: [u8; 18] = [
+    1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36,
+];
+
+/// Kind of chip placement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BetType {
+    /// One number, `number` in `0..=36`, pays 35:1
+    Straight = 0,
+    /// Two adjacent numbers `number` and `second`, pays 17:1
+    Split = 1,
+    /// Row of three starting at `number` (1, 4, .., 34), pays 11:1
+    Street = 2,
+    /// Square of four with `number` top left, pays 8:1
+    Corner = 3,
+    /// `number` 0, 1 or 2 for 1-12, 13-24, 25-36, pays 2:1
+    Dozen = 4,
+    /// `number` 0, 1 or 2 for the column starting at 1, 2 or 3, pays 2:1
+    Column = 5,
+    /// `number` 0 red, 1 black, pays 1:1
+    RedBlack = 6,
+    /// `number` 0 even, 1 odd, pays 1:1
+    OddEven = 7,
+}
+
+impl BetType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(BetType::Straight),
+            1 => Some(BetType::Split),
+            2 => Some(BetType::Street),
+            3 => Some(BetType::Corner),
+            4 => Some(BetType::Dozen),
+            5 => Some(BetType::Column),
+            6 => Some(BetType::RedBlack),
+            7 => Some(BetType::OddEven),
+            _ => None,
+        }
+    }
+
+    /// Standard "to one" odds of the placement
+    pub fn odds(self) -> u64 {
+        match self {
+            BetType::Straight => 35,
+            BetType::Split => 17,
+            BetType::Street => 11,
+            BetType::Corner => 8,
+            BetType::Dozen | BetType::Column => 2,
+            BetType::RedBlack | BetType::OddEven => 1,
+        }
+    }
+}
+
+pub fn is_red(number: u8) -> bool {
+    RED_NUMBERS.contains(&number)
+}
+
+/// One chip on the layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub bet_type: BetType,
+    pub number: u8,
+    /// Only used by splits
+    pub second: u8,
+    /// Chip value in lamports
+    pub amount: u64,
+}
+
+impl Placement {
+    pub const LEN: usize = 11;
+
+    /// Read a placement from instruction data, amount little endian
+    pub fn unpack_instruction(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(Placement {
+            bet_type: BetType::from_u8(data[0]).ok_or(ProgramError::InvalidInstructionData)?,
+            number: data[1],
+            second: data[2],
+            amount: LittleEndian::read_u64(&data[3..11]),
+        })
+    }
+
+    /// Write a placement as instruction data, amount little endian
+    pub fn pack_instruction(&self, data: &mut [u8]) {
+        data[0] = self.bet_type as u8;
+        data[1] = self.number;
+        data[2] = self.second;
+        LittleEndian::write_u64(&mut data[3..11], self.amount);
+    }
+
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Ok(Placement {
+            bet_type: BetType::from_u8(data[0]).ok_or(ProgramError::InvalidAccountData)?,
+            number: data[1],
+            second: data[2],
+            amount: BigEndian::read_u64(&data[3..11]),
+        })
+    }
+
+    fn pack(&self, data: &mut [u8]) {
+        data[0] = self.bet_type as u8;
+        data[1] = self.number;
+        data[2] = self.second;
+        BigEndian::write_u64(&mut data[3..11], self.amount);
+    }
+
+    /// True if the placement describes a real spot on the layout
+    pub fn is_valid(&self) -> bool {
+        let n = self.number;
+        if self.amount == 0 {
+            return false;
+        }
+        match self.bet_type {
+            BetType::Straight => n <= 36,
+            BetType::Split => {
+                let (a, b) = (n.min(self.second), n.max(self.second));
+                if a == 0 {
+                    // zero splits with 1, 2 or 3
+                    (1..=3).contains(&b)
+                } else {
+                    b <= 36 && ((b == a + 1 && a % 3 != 0) || b == a + 3)
+                }
+            }
+            BetType::Street => (1..=34).contains(&n) && (n - 1).is_multiple_of(3),
+            BetType::Corner => (1..=32).contains(&n) && !n.is_multiple_of(3),
+            BetType::Dozen | BetType::Column => n <= 2,
+            BetType::RedBlack | BetType::OddEven => n <= 1,
+        }
+    }
+
+    /// True if the placement wins when the ball lands on `result`
+    pub fn covers(&self, result: u8) -> bool {
+        let n = self.number;
+        match self.bet_type {
+            BetType::Straight => result == n,
+            BetType::Split => result == n || result == self.second,
+            BetType::Street => result >= n && result <= n + 2,
+            BetType::Corner => result == n || result == n + 1 || result == n + 3 || result == n + 4,
+            BetType::Dozen => result != 0 && (result - 1) / 12 == n,
+            BetType::Column => result != 0 && (result - 1) % 3 == n,
+            BetType::RedBlack => result != 0 && is_red(result) == (n == 0),
+            BetType::OddEven => result != 0 && result % 2 == n,
+        }
+    }
+
+    /// Amount returned for this chip, wager included
+    pub fn payout(&self, result: u8) -> Option<u64> {
+        if !self.covers(result) {
+            return Some(0);
+        }
+        self.amount.checked_mul(self.bet_type.odds() + 1)
+    }
+}
+
+/// Chip layout of a roulette game
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouletteBet {
+    pub placements: Vec<Placement>,
+}
+
+impl RouletteBet {
+    /// Offset of the layout in the game account
+    pub const OFFSET: usize = Game::LEN;
+    /// Game account size needed for a roulette game
+    pub const LEN: usize = Game::LEN + 1 + MAX_PLACEMENTS * Placement::LEN;
+
+    /// Read the layout from commit instruction data: count, then placements
+    pub fn unpack_instruction(data: &[u8]) -> Result<Self, ProgramError> {
+        let count = *data.first().ok_or(ProgramError::InvalidInstructionData)? as usize;
+        if count == 0 || count > MAX_PLACEMENTS || data.len() < 1 + count * Placement::LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let mut placements = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = 1 + i * Placement::LEN;
+            placements.push(Placement::unpack_instruction(&data[start..start + Placement::LEN])?);
+        }
+        Ok(RouletteBet { placements })
+    }
+
+    /// Commit instruction data of the layout: count, then placements
+    pub fn pack_instruction(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 1 + self.placements.len() * Placement::LEN];
+        data[0] = self.placements.len() as u8;
+        for (i, placement) in self.placements.iter().enumerate() {
+            let start = 1 + i * Placement::LEN;
+            placement.pack_instruction(&mut data[start..start + Placement::LEN]);
+        }
+        data
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let count = data[Self::OFFSET] as usize;
+        if count > MAX_PLACEMENTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut placements = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = Self::OFFSET + 1 + i * Placement::LEN;
+            placements.push(Placement::unpack(&data[start..start + Placement::LEN])?);
+        }
+        Ok(RouletteBet { placements })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[Self::OFFSET] = self.placements.len() as u8;
+        for (i, placement) in self.placements.iter().enumerate() {
+            let start = Self::OFFSET + 1 + i * Placement::LEN;
+            placement.pack(&mut data[start..start + Placement::LEN]);
+        }
+        Ok(())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.placements.is_empty()
+            && self.placements.len() <= MAX_PLACEMENTS
+            && self.placements.iter().all(|p| p.is_valid())
+            && self.total_wager().is_some()
+    }
+
+    /// Sum of all chips
+    pub fn total_wager(&self) -> Option<u64> {
+        self.placements
+            .iter()
+            .try_fold(0u64, |total, p| total.checked_add(p.amount))
+    }
+
+    /// Amount returned for the whole layout when the ball lands on `result`
+    pub fn total_payout(&self, result: u8) -> Option<u64> {
+        self.placements
+            .iter()
+            .try_fold(0u64, |total, p| total.checked_add(p.payout(result)?))
+    }
+
+    /// Largest amount the layout can return over every pocket
+    pub fn worst_case_payout(&self) -> Option<u64> {
+        let mut worst = 0;
+        for result in 0..ROULETTE_OUTCOMES as u8 {
+            worst = worst.max(self.total_payout(result)?);
+        }
+        Some(worst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chip(bet_type: BetType, number: u8, second: u8) -> Placement {
+        Placement {
+            bet_type,
+            number,
+            second,
+            amount: 10,
+        }
+    }
+
+    #[test]
+    fn test_layout_validity() {
+        assert!(chip(BetType::Straight, 0, 0).is_valid());
+        assert!(!chip(BetType::Straight, 37, 0).is_valid());
+        assert!(chip(BetType::Split, 1, 2).is_valid());
+        assert!(chip(BetType::Split, 5, 2).is_valid());
+        assert!(chip(BetType::Split, 0, 3).is_valid());
+        assert!(!chip(BetType::Split, 3, 4).is_valid());
+        assert!(chip(BetType::Street, 34, 0).is_valid());
+        assert!(!chip(BetType::Street, 2, 0).is_valid());
+        assert!(chip(BetType::Corner, 32, 0).is_valid());
+        assert!(!chip(BetType::Corner, 3, 0).is_valid());
+    }
+
+    #[test]
+    fn test_payouts() {
+        assert_eq!(chip(BetType::Straight, 17, 0).payout(17), Some(360));
+        assert_eq!(chip(BetType::Corner, 1, 0).payout(5), Some(90));
+        assert_eq!(chip(BetType::Dozen, 2, 0).payout(36), Some(30));
+        assert_eq!(chip(BetType::Column, 0, 0).payout(34), Some(30));
+        assert_eq!(chip(BetType::RedBlack, 0, 0).payout(1), Some(20));
+        assert_eq!(chip(BetType::RedBlack, 1, 0).payout(0), Some(0));
+        assert_eq!(chip(BetType::OddEven, 1, 0).payout(7), Some(20));
+    }
+
+    #[test]
+    fn test_instruction_round_trip() {
+        let bet = RouletteBet {
+            placements: vec![chip(BetType::Split, 1, 2), chip(BetType::Dozen, 2, 0)],
+        };
+        assert_eq!(RouletteBet::unpack_instruction(&bet.pack_instruction()).unwrap(), bet);
+    }
+
+    #[test]
+    fn test_worst_case() {
+        let bet = RouletteBet {
+            placements: vec![chip(BetType::Straight, 0, 0), chip(BetType::RedBlack, 0, 0)],
+        };
+        assert_eq!(bet.total_wager(), Some(20));
+        assert_eq!(bet.total_payout(0), Some(360));
+        assert_eq!(bet.total_payout(1), Some(20));
+        assert_eq!(bet.worst_case_payout(), Some(360));
+    }
+}
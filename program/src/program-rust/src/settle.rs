@@ -0,0 +1,202 @@
+//! Bet settlement shared by the lamport and token games
+
+use crate::{
+    error::RollError,
+    get_slot_hash, hash_value,
+    odds::{self, GameKind},
+    randomness::Roll,
+    risk::{self, Odds, RiskConfig},
+    roulette::RouletteBet,
+    state::Game,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::AccountInfo,
+    hash::{hashv, Hash, HASH_BYTES},
+    info,
+    program_error::ProgramError,
+    sysvar::{clock::Clock, Sysvar},
+};
+
+/// Amounts of a rolled game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outcome {
+    /// Total amount at stake
+    pub wager: u64,
+    /// Largest profit the game could have won, checked against max profit
+    pub winnings: u64,
+    /// Amount returned to the player for the rolled result, wager included
+    pub payout: u64,
+}
+
+/// Reserve the `exposure` of a `wager` placed at `odds` against a treasury
+/// holding `liquidity`, `reserved` of which is already promised to open
+/// bets, with the treasury's risk `config`. The max wager is measured
+/// against the unreserved part, so open bets can never promise more than
+/// the treasury holds. Returns the new reserved total.
+pub fn reserve(
+    reserved: u64,
+    liquidity: u64,
+    wager: u64,
+    odds: Odds,
+    exposure: u64,
+    config: &RiskConfig,
+) -> Result<u64, ProgramError> {
+    let max_wager = risk::max_wager(odds, liquidity.saturating_sub(reserved), config);
+    info!("Wager, treasury max wager, potential winnings:");
+    info!(&wager.to_string());
+    info!(&max_wager.to_string());
+    info!(&exposure.to_string());
+    if wager > max_wager {
+        info!("Wager exceeds max wager allowed");
+        return Err(RollError::MaxWagerExceeded.into());
+    }
+    reserved
+        .checked_add(exposure)
+        .ok_or_else(|| RollError::InvalidAmount.into())
+}
+
+/// Fails unless `amount` can leave a treasury holding `liquidity` without
+/// touching the `reserved` exposure of open bets
+pub fn assert_unreserved(reserved: u64, liquidity: u64, amount: u64) -> Result<(), ProgramError> {
+    if amount > liquidity.saturating_sub(reserved) {
+        info!("Amount exceeds unreserved treasury liquidity");
+        return Err(RollError::InsufficientLiquidity.into());
+    }
+    Ok(())
+}
+
+/// Total amount a committed game has at stake, for games committed with a
+/// roll count or a roulette layout
+pub fn stake(game: &Game, data: &[u8]) -> Result<u64, ProgramError> {
+    let stake = if game.kind == GameKind::Roulette {
+        RouletteBet::unpack(data)?.total_wager()
+    } else {
+        game.wager.checked_mul(game.roll_count as u64)
+    };
+    match stake {
+        Some(stake) if stake > 0 => Ok(stake),
+        _ => Err(RollError::InvalidRollCount.into()),
+    }
+}
+
+/// Random value of roll `index` in a multi-roll bet, hash(seed || index)
+pub fn sub_roll_value(seed: u64, index: u8) -> u64 {
+    let hash = hashv(&[&seed.to_le_bytes(), &[index]]);
+    LittleEndian::read_u64(&hash.as_ref()[..8])
+}
+
+/// Check the reveal against the commit and derive the game's random seed
+/// from the reveal number and the hash of the commit slot. A wrong reveal
+/// fails rather than refunds, as only the player reveals and could
+/// otherwise take back a losing bet. A game settled in its commit slot is
+/// refunded, and one whose commit slot hash has expired is forfeited, as the
+/// player could see the hash and hold back a losing reveal.
+pub fn reveal_seed(
+    game: &Game,
+    reveal_number: u32,
+    clock_account: &AccountInfo,
+    slot_hashes_account: &AccountInfo,
+) -> Result<Roll, ProgramError> {
+    // confirm same reveal number
+    let hashed_reveal = hash_value(reveal_number);
+    if game.hashed_reveal != hashed_reveal {
+        info!("Reveal number does not match saved reveal number");
+        return Err(RollError::InvalidReveal.into());
+    }
+
+    let current_slot = Clock::from_account_info(clock_account)?.slot;
+    if game.commit_slot >= current_slot {
+        info!("Block height invalid, returning funds");
+        return Ok(Roll::Refund);
+    }
+
+    // Get slot hash of saved transaction, zero if no longer in recent slots
+    let slot_hashes_data = slot_hashes_account.try_borrow_data()?;
+    let slot_hash = get_slot_hash(&slot_hashes_data, game.commit_slot);
+    if slot_hash == Hash::new(&[0u8; HASH_BYTES]) {
+        info!("Block hash expired, wager is forfeited");
+        return Ok(Roll::Forfeit);
+    }
+
+    info!("Block height and hash valid, obtaining result");
+    let hashed_slot_hash = hash_value(slot_hash);
+    Ok(Roll::Seed(hash_value(hashed_reveal.wrapping_add(hashed_slot_hash))))
+}
+
+/// Roll the game from `seed`, recording the result, wins and payout in
+/// `game`. `legacy_wager` is the stake of a game committed without a roll
+/// count, i.e. the whole fund account balance.
+pub fn roll(game: &mut Game, data: &[u8], seed: u64, legacy_wager: u64) -> Result<Outcome, ProgramError> {
+    let multi_roll = game.roll_count > 1;
+    let result = if multi_roll {
+        odds::roll_result(game.kind, sub_roll_value(seed, 0))
+    } else {
+        odds::roll_result(game.kind, seed)
+    };
+
+    match game.kind {
+        GameKind::DiceUnder => info!("Rolling for a number under:"),
+        GameKind::DiceOver => info!("Rolling for a number over:"),
+        GameKind::CoinFlip => info!("Flipping for side:"),
+        GameKind::ExactNumber => info!("Rolling for exactly:"),
+        GameKind::Roulette => info!("Spinning the roulette wheel, target unused:"),
+    }
+    info!(&game.target.to_string());
+    info!("You rolled a:");
+    info!(&result.to_string());
+
+    let outcome = if game.kind == GameKind::Roulette {
+        let bet = RouletteBet::unpack(data)?;
+        let wager = bet.total_wager().ok_or(RollError::InvalidPlacement)?;
+        let worst_case = bet.worst_case_payout().ok_or(RollError::InvalidPlacement)?;
+        let payout = bet.total_payout(result as u8).ok_or(RollError::InvalidPlacement)?;
+        if payout > 0 {
+            game.set_win(0);
+        }
+        Outcome {
+            wager,
+            winnings: worst_case.saturating_sub(wager),
+            payout,
+        }
+    } else {
+        // Every roll wagers the same amount, so max profit is
+        // checked against all of them winning
+        let roll_wager = if game.roll_count == 0 {
+            legacy_wager
+        } else {
+            game.wager
+        };
+        let roll_winnings =
+            odds::profit(roll_wager, game.kind, game.target).ok_or(RollError::InvalidTarget)?;
+        let mut payout: u64 = 0;
+        for i in 0..game.rolls() {
+            let roll_result = if multi_roll {
+                odds::roll_result(game.kind, sub_roll_value(seed, i))
+            } else {
+                result
+            };
+            if odds::is_win(game.kind, game.target, roll_result) {
+                game.set_win(i);
+                payout = payout
+                    .checked_add(roll_wager + roll_winnings)
+                    .ok_or(RollError::InvalidRollCount)?;
+            }
+        }
+        let rolls = game.rolls() as u64;
+        if multi_roll {
+            info!("Rolls, wins:");
+            info!(&rolls.to_string());
+            info!(&game.wins.iter().map(|b| b.count_ones()).sum::<u32>().to_string());
+        }
+        Outcome {
+            wager: roll_wager.checked_mul(rolls).ok_or(RollError::InvalidRollCount)?,
+            winnings: roll_winnings.checked_mul(rolls).ok_or(RollError::InvalidRollCount)?,
+            payout,
+        }
+    };
+
+    game.result = result;
+    game.payout = outcome.payout;
+    Ok(outcome)
+}
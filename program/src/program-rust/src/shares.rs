@@ -0,0 +1,56 @@
+//! Treasury token pricing, shared by deposits, withdrawals and quotes
+
+/// Scale of `price_per_share`, the value of one treasury token unit in
+/// billionths of the treasury's asset
+pub const PRICE_SCALE: u64 = 1_000_000_000;
+
+fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    let value = a as u128 * b as u128 / c as u128;
+    if value > u64::MAX as u128 {
+        u64::MAX
+    } else {
+        value as u64
+    }
+}
+
+/// Treasury tokens minted for depositing `amount` into a treasury holding
+/// `liquidity` backed by `supply` tokens. An empty treasury mints one token
+/// per unit deposited.
+pub fn deposit_tokens_out(amount: u64, liquidity: u64, supply: u64) -> u64 {
+    if supply == 0 || liquidity == 0 {
+        return amount;
+    }
+    mul_div(amount, supply, liquidity)
+}
+
+/// Amount paid out for burning `lp_amount` of `supply` treasury tokens
+pub fn withdraw_amount_out(lp_amount: u64, liquidity: u64, supply: u64) -> u64 {
+    if supply == 0 {
+        return 0;
+    }
+    mul_div(lp_amount, liquidity, supply)
+}
+
+/// Value of one treasury token in `PRICE_SCALE` units
+pub fn price_per_share(liquidity: u64, supply: u64) -> u64 {
+    if supply == 0 {
+        return PRICE_SCALE;
+    }
+    mul_div(liquidity, PRICE_SCALE, supply)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(deposit_tokens_out(500, 0, 0), 500);
+        // treasury doubled since the first deposit
+        let minted = deposit_tokens_out(500, 2_000, 1_000);
+        assert_eq!(minted, 250);
+        assert_eq!(withdraw_amount_out(minted, 2_500, 1_250), 500);
+        assert_eq!(price_per_share(2_000, 1_000), 2 * PRICE_SCALE);
+        assert_eq!(price_per_share(0, 0), PRICE_SCALE);
+    }
+}
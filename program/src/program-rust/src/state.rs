@@ -0,0 +1,1753 @@
+//! Account data layouts
+
+use crate::{odds::GameKind, risk::RiskConfig};
+use byteorder::{BigEndian, ByteOrder};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::{
+    entrypoint_deprecated::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey,
+};
+
+/// Largest number of rolls a single commit may settle
+pub const MAX_ROLLS: u8 = 100;
+
+/// Bytes needed for one win bit per roll
+pub const WIN_BITMAP_LEN: usize = (MAX_ROLLS as usize).div_ceil(8);
+
+/// Lifecycle of a game account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameStatus {
+    /// Fresh account, never committed
+    Uninitialized = 0,
+    /// Committed and waiting for the reveal
+    Committed = 1,
+    /// Rolled, refunded or forfeited, can be committed again
+    Settled = 2,
+}
+
+impl GameStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(GameStatus::Uninitialized),
+            1 => Some(GameStatus::Committed),
+            2 => Some(GameStatus::Settled),
+            _ => None,
+        }
+    }
+}
+
+/// Game account data, stored big endian so the UI can read the result directly
+///
+/// | bytes    | field                    |
+/// |----------|--------------------------|
+/// | 0..2     | target                   |
+/// | 2        | game kind                |
+/// | 3        | roll count               |
+/// | 4..12    | hashed reveal            |
+/// | 12..20   | commit slot              |
+/// | 20..28   | result (first roll)      |
+/// | 28..36   | wager per roll           |
+/// | 36..49   | win bitmap, bit i roll i |
+/// | 49..57   | total payout             |
+/// | 57..89   | pool, zero for lamports  |
+/// | 89..121  | player or payout account |
+/// | 121      | status                   |
+/// | 122..130 | reserved exposure        |
+/// | 130..162 | referrer, zero for none  |
+/// | 162..194 | fund, zero for pools     |
+/// | 194..226 | treasury, zero for pools |
+#[derive(Clone, Debug, PartialEq)]
+pub struct Game {
+    /// Target result, interpreted according to `kind`
+    pub target: u16,
+    /// Game the bet is placed on
+    pub kind: GameKind,
+    /// Number of rolls settled by one reveal. Zero is a single roll
+    /// wagering the whole fund account balance.
+    pub roll_count: u8,
+    /// Hash of the player's reveal number
+    pub hashed_reveal: u64,
+    /// Slot the commit landed in
+    pub commit_slot: u64,
+    /// Rolled result, set on settle
+    pub result: u64,
+    /// Amount wagered on each roll when `roll_count` is set
+    pub wager: u64,
+    /// Which rolls won, set on settle
+    pub wins: [u8; WIN_BITMAP_LEN],
+    /// Amount returned to the player, wagers included, set on settle
+    pub payout: u64,
+    /// Token pool the bet is escrowed in, default for lamport games
+    pub pool: Pubkey,
+    /// Token account paid on settle, or the committing wallet of a
+    /// lamport game
+    pub player: Pubkey,
+    /// Whether the game is waiting for its reveal
+    pub status: GameStatus,
+    /// Worst case profit reserved against the treasury at commit
+    pub reserved: u64,
+    /// Account paid the referrer fee on settle, default for none
+    pub referrer: Pubkey,
+    /// Fund account holding a lamport game's wager, default for token games
+    pub fund: Pubkey,
+    /// Treasury backing a lamport game, default for token games
+    pub treasury: Pubkey,
+}
+
+impl Game {
+    pub const LEN: usize = 226;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll game account data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let kind = GameKind::from_u8(data[2]).ok_or(ProgramError::InvalidAccountData)?;
+        let mut wins = [0u8; WIN_BITMAP_LEN];
+        wins.copy_from_slice(&data[36..49]);
+        Ok(Game {
+            target: BigEndian::read_u16(&data[0..2]),
+            kind,
+            roll_count: data[3],
+            hashed_reveal: BigEndian::read_u64(&data[4..12]),
+            commit_slot: BigEndian::read_u64(&data[12..20]),
+            result: BigEndian::read_u64(&data[20..28]),
+            wager: BigEndian::read_u64(&data[28..36]),
+            wins,
+            payout: BigEndian::read_u64(&data[49..57]),
+            pool: Pubkey::new(&data[57..89]),
+            player: Pubkey::new(&data[89..121]),
+            status: GameStatus::from_u8(data[121]).ok_or(ProgramError::InvalidAccountData)?,
+            reserved: BigEndian::read_u64(&data[122..130]),
+            referrer: Pubkey::new(&data[130..162]),
+            fund: Pubkey::new(&data[162..194]),
+            treasury: Pubkey::new(&data[194..226]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll game account data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        BigEndian::write_u16(&mut data[0..2], self.target);
+        data[2] = self.kind as u8;
+        data[3] = self.roll_count;
+        BigEndian::write_u64(&mut data[4..12], self.hashed_reveal);
+        BigEndian::write_u64(&mut data[12..20], self.commit_slot);
+        BigEndian::write_u64(&mut data[20..28], self.result);
+        BigEndian::write_u64(&mut data[28..36], self.wager);
+        data[36..49].copy_from_slice(&self.wins);
+        BigEndian::write_u64(&mut data[49..57], self.payout);
+        data[57..89].copy_from_slice(self.pool.as_ref());
+        data[89..121].copy_from_slice(self.player.as_ref());
+        data[121] = self.status as u8;
+        BigEndian::write_u64(&mut data[122..130], self.reserved);
+        data[130..162].copy_from_slice(self.referrer.as_ref());
+        data[162..194].copy_from_slice(self.fund.as_ref());
+        data[194..226].copy_from_slice(self.treasury.as_ref());
+        Ok(())
+    }
+
+    pub fn set_win(&mut self, roll: u8) {
+        self.wins[roll as usize / 8] |= 1 << (roll % 8);
+    }
+
+    pub fn is_win(&self, roll: u8) -> bool {
+        self.wins[roll as usize / 8] & (1 << (roll % 8)) != 0
+    }
+
+    /// Number of rolls settled, counting a legacy zero as one
+    pub fn rolls(&self) -> u8 {
+        self.roll_count.max(1)
+    }
+}
+
+/// Seed of the pool state PDA, `["pool", mint]`, which also signs for the
+/// vault and the treasury token mint
+pub const POOL_SEED: &[u8] = b"pool";
+
+/// Seed of the pool's token vault PDA, `["vault", mint]`
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/// Seed of the pool's treasury token mint PDA, `["lp_mint", mint]`
+pub const LP_MINT_SEED: &[u8] = b"lp_mint";
+
+/// Source of the randomness a token pool's games are rolled with
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Randomness {
+    /// Slot hash of the commit slot and the player's reveal
+    #[default]
+    SlotHashes = 0,
+    /// Next seed of the house's hash chain, see `house_seed`
+    HouseChain = 1,
+    /// VRF output the pool's worker writes for the game, see `vrf`
+    Vrf = 2,
+}
+
+impl Randomness {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Randomness::SlotHashes),
+            1 => Some(Randomness::HouseChain),
+            2 => Some(Randomness::Vrf),
+            _ => None,
+        }
+    }
+}
+
+/// Treasury pool for one SPL mint
+///
+/// | bytes    | field               |
+/// |----------|---------------------|
+/// | 0        | initialized         |
+/// | 1        | pool bump seed      |
+/// | 2..34    | mint                |
+/// | 34..66   | vault               |
+/// | 66..98   | treasury token mint |
+/// | 98..106  | escrowed wagers     |
+/// | 106..114 | reserved exposure   |
+/// | 114..146 | authority           |
+/// | 146      | paused flags        |
+/// | 147..149 | Kelly fraction, bps |
+/// | 149..157 | liquidity cap       |
+/// | 157      | allowlist only      |
+/// | 158      | randomness source   |
+/// | 159..161 | keeper fee, bps     |
+/// | 161..169 | lockup, slots       |
+/// | 169..171 | early exit fee, bps |
+/// | 171..196 | epoch start         |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pool {
+    pub is_initialized: bool,
+    /// Bump seed of the pool PDA, used to sign for the vault and mint
+    pub bump_seed: u8,
+    /// Mint wagered in this pool
+    pub mint: Pubkey,
+    /// Token account holding the pool's liquidity and escrowed wagers
+    pub vault: Pubkey,
+    /// Treasury token minted to liquidity providers
+    pub lp_mint: Pubkey,
+    /// Wagers of committed, unsettled bets held in the vault
+    pub escrowed: u64,
+    /// Worst case profit promised to committed, unsettled bets
+    pub reserved: u64,
+    /// Creator of the pool, who sets up its fee vault and pauses it
+    pub authority: Pubkey,
+    /// Paused operations, `pause` flags
+    pub paused: u8,
+    /// Share of the Kelly bound a single bet may put at risk
+    pub kelly_fraction_bps: u16,
+    /// Most liquidity deposits may bring the pool to, 0 for no cap
+    pub max_liquidity: u64,
+    /// Whether only allowlisted depositors may deposit
+    pub allowlist_only: bool,
+    /// Where the pool's games get their randomness
+    pub randomness: Randomness,
+    /// Share of a cranked game's stake paid to the keeper
+    pub keeper_fee_bps: u16,
+    /// Slots after a deposit during which withdrawing pays the early exit fee
+    pub lockup_slots: u64,
+    /// Share of an early withdrawal left in the pool
+    pub early_exit_fee_bps: u16,
+    /// Pool value at the start of the latest epoch, the withdrawal price
+    pub epoch_start: EpochStart,
+}
+
+impl Pool {
+    pub const LEN: usize = 171 + EpochStart::LEN;
+
+    pub fn find_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[POOL_SEED, mint.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll pool account data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(Pool {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            mint: Pubkey::new(&data[2..34]),
+            vault: Pubkey::new(&data[34..66]),
+            lp_mint: Pubkey::new(&data[66..98]),
+            escrowed: BigEndian::read_u64(&data[98..106]),
+            reserved: BigEndian::read_u64(&data[106..114]),
+            authority: Pubkey::new(&data[114..146]),
+            paused: data[146],
+            kelly_fraction_bps: BigEndian::read_u16(&data[147..149]),
+            max_liquidity: BigEndian::read_u64(&data[149..157]),
+            allowlist_only: data[157] != 0,
+            randomness: Randomness::from_u8(data[158]).ok_or(ProgramError::InvalidAccountData)?,
+            keeper_fee_bps: BigEndian::read_u16(&data[159..161]),
+            lockup_slots: BigEndian::read_u64(&data[161..169]),
+            early_exit_fee_bps: BigEndian::read_u16(&data[169..171]),
+            epoch_start: EpochStart::unpack(&data[171..Self::LEN]),
+        })
+    }
+
+    /// Unpack a pool, failing unless it has been initialized
+    pub fn unpack_initialized(data: &[u8]) -> Result<Self, ProgramError> {
+        let pool = Self::unpack(data)?;
+        if !pool.is_initialized {
+            info!("SolanaRoll pool is not initialized");
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(pool)
+    }
+
+    pub fn risk_config(&self) -> RiskConfig {
+        RiskConfig {
+            kelly_fraction_bps: self.kelly_fraction_bps as u64,
+        }
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll pool account data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.mint.as_ref());
+        data[34..66].copy_from_slice(self.vault.as_ref());
+        data[66..98].copy_from_slice(self.lp_mint.as_ref());
+        BigEndian::write_u64(&mut data[98..106], self.escrowed);
+        BigEndian::write_u64(&mut data[106..114], self.reserved);
+        data[114..146].copy_from_slice(self.authority.as_ref());
+        data[146] = self.paused;
+        BigEndian::write_u16(&mut data[147..149], self.kelly_fraction_bps);
+        BigEndian::write_u64(&mut data[149..157], self.max_liquidity);
+        data[157] = self.allowlist_only as u8;
+        data[158] = self.randomness as u8;
+        BigEndian::write_u16(&mut data[159..161], self.keeper_fee_bps);
+        BigEndian::write_u64(&mut data[161..169], self.lockup_slots);
+        BigEndian::write_u16(&mut data[169..171], self.early_exit_fee_bps);
+        self.epoch_start.pack(&mut data[171..Self::LEN]);
+        Ok(())
+    }
+}
+
+/// Seed of the lamport treasury's state PDA, `["treasury", treasury]`
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Bookkeeping for the lamport treasury, whose balance lives in the
+/// treasury account itself
+///
+/// | bytes   | field                |
+/// |---------|----------------------|
+/// | 0       | initialized          |
+/// | 1       | bump seed            |
+/// | 2..34   | treasury account     |
+/// | 34..42  | reserved exposure    |
+/// | 42..74  | authority            |
+/// | 74      | paused flags         |
+/// | 75..77  | Kelly fraction, bps  |
+/// | 77..85  | deposit lockup slots |
+/// | 85..87  | early exit fee, bps  |
+/// | 87..95  | liquidity cap        |
+/// | 95      | allowlist only       |
+/// | 96..98  | keeper fee, bps      |
+/// | 98..123 | epoch start          |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Treasury {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    /// Program owned account holding the treasury lamports
+    pub treasury: Pubkey,
+    /// Worst case profit promised to committed, unsettled bets
+    pub reserved: u64,
+    /// Account allowed to pause the treasury
+    pub authority: Pubkey,
+    /// Paused operations, `pause` flags
+    pub paused: u8,
+    /// Share of the Kelly bound a single bet may put at risk
+    pub kelly_fraction_bps: u16,
+    /// Slots after a deposit during which withdrawing pays the early exit fee
+    pub lockup_slots: u64,
+    /// Share of an early withdrawal left in the treasury
+    pub early_exit_fee_bps: u16,
+    /// Most liquidity deposits may bring the treasury to, 0 for no cap
+    pub max_liquidity: u64,
+    /// Whether only allowlisted depositors may deposit
+    pub allowlist_only: bool,
+    /// Share of a cranked game's fund paid to the keeper
+    pub keeper_fee_bps: u16,
+    /// Treasury value at the start of the latest epoch, the withdrawal price
+    pub epoch_start: EpochStart,
+}
+
+impl Treasury {
+    pub const LEN: usize = 98 + EpochStart::LEN;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[TREASURY_SEED, treasury.as_ref()], program_id)
+    }
+
+    pub fn risk_config(&self) -> RiskConfig {
+        RiskConfig {
+            kelly_fraction_bps: self.kelly_fraction_bps as u64,
+        }
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll treasury state data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(Treasury {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            reserved: BigEndian::read_u64(&data[34..42]),
+            authority: Pubkey::new(&data[42..74]),
+            paused: data[74],
+            kelly_fraction_bps: BigEndian::read_u16(&data[75..77]),
+            lockup_slots: BigEndian::read_u64(&data[77..85]),
+            early_exit_fee_bps: BigEndian::read_u16(&data[85..87]),
+            max_liquidity: BigEndian::read_u64(&data[87..95]),
+            allowlist_only: data[95] != 0,
+            keeper_fee_bps: BigEndian::read_u16(&data[96..98]),
+            epoch_start: EpochStart::unpack(&data[98..Self::LEN]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll treasury state data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        BigEndian::write_u64(&mut data[34..42], self.reserved);
+        data[42..74].copy_from_slice(self.authority.as_ref());
+        data[74] = self.paused;
+        BigEndian::write_u16(&mut data[75..77], self.kelly_fraction_bps);
+        BigEndian::write_u64(&mut data[77..85], self.lockup_slots);
+        BigEndian::write_u16(&mut data[85..87], self.early_exit_fee_bps);
+        BigEndian::write_u64(&mut data[87..95], self.max_liquidity);
+        data[95] = self.allowlist_only as u8;
+        BigEndian::write_u16(&mut data[96..98], self.keeper_fee_bps);
+        self.epoch_start.pack(&mut data[98..Self::LEN]);
+        Ok(())
+    }
+}
+
+/// Seed of a treasury's fee vault PDA, `["fee_vault", treasury]`, where
+/// `treasury` is the lamport treasury account or a token pool
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+
+/// Seed of a token pool's fee token account PDA, `["fee_tokens", pool]`
+pub const FEE_TOKENS_SEED: &[u8] = b"fee_tokens";
+
+/// Fee split and accrued protocol fees of one treasury. Lamport fees are
+/// held by the fee vault account itself, token fees by its fee token account.
+///
+/// | bytes   | field                                |
+/// |---------|--------------------------------------|
+/// | 0       | initialized                          |
+/// | 1       | bump seed                            |
+/// | 2..34   | treasury or pool                     |
+/// | 34..66  | fee authority                        |
+/// | 66..68  | protocol fee, bps of edge revenue    |
+/// | 68..70  | referrer fee, bps of edge revenue    |
+/// | 70..78  | accrued protocol fees                |
+/// | 78..110 | fee token account, zero for lamports |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeVault {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    /// Lamport treasury account or token pool the fees are taken from
+    pub treasury: Pubkey,
+    /// Account allowed to claim the accrued protocol fees
+    pub authority: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub referrer_fee_bps: u16,
+    /// Protocol fees not claimed yet
+    pub accrued: u64,
+    /// Token account holding a pool's fees, default for lamports
+    pub fee_tokens: Pubkey,
+}
+
+impl FeeVault {
+    pub const LEN: usize = 110;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[FEE_VAULT_SEED, treasury.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll fee vault data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(FeeVault {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            authority: Pubkey::new(&data[34..66]),
+            protocol_fee_bps: BigEndian::read_u16(&data[66..68]),
+            referrer_fee_bps: BigEndian::read_u16(&data[68..70]),
+            accrued: BigEndian::read_u64(&data[70..78]),
+            fee_tokens: Pubkey::new(&data[78..110]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll fee vault data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34..66].copy_from_slice(self.authority.as_ref());
+        BigEndian::write_u16(&mut data[66..68], self.protocol_fee_bps);
+        BigEndian::write_u16(&mut data[68..70], self.referrer_fee_bps);
+        BigEndian::write_u64(&mut data[70..78], self.accrued);
+        data[78..110].copy_from_slice(self.fee_tokens.as_ref());
+        Ok(())
+    }
+}
+
+/// Seed of a player's referral PDA, `["referral", player]`
+pub const REFERRAL_SEED: &[u8] = b"referral";
+
+/// Seed of a referrer's stats PDA, `["referrer", referrer]`
+pub const REFERRER_SEED: &[u8] = b"referrer";
+
+/// Referrer a player registered once, credited on every later lamport bet
+///
+/// | bytes  | field       |
+/// |--------|-------------|
+/// | 0      | initialized |
+/// | 1      | bump seed   |
+/// | 2..34  | player      |
+/// | 34..66 | referrer    |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Referral {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub player: Pubkey,
+    pub referrer: Pubkey,
+}
+
+impl Referral {
+    pub const LEN: usize = 66;
+
+    pub fn find_address(program_id: &Pubkey, player: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[REFERRAL_SEED, player.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll referral data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(Referral {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            player: Pubkey::new(&data[2..34]),
+            referrer: Pubkey::new(&data[34..66]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll referral data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.player.as_ref());
+        data[34..66].copy_from_slice(self.referrer.as_ref());
+        Ok(())
+    }
+}
+
+/// Earnings of a referrer. Claimable lamports are held by the stats account
+/// itself on top of its rent.
+///
+/// | bytes  | field                              |
+/// |--------|------------------------------------|
+/// | 0      | initialized                        |
+/// | 1      | bump seed                          |
+/// | 2..34  | referrer                           |
+/// | 34..38 | players referred                   |
+/// | 38..46 | volume wagered by referred players |
+/// | 46..54 | total earnings                     |
+/// | 54..62 | claimable earnings                 |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReferrerStats {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub referrer: Pubkey,
+    pub referrals: u32,
+    pub referred_volume: u64,
+    pub earnings: u64,
+    pub claimable: u64,
+}
+
+impl ReferrerStats {
+    pub const LEN: usize = 62;
+
+    pub fn find_address(program_id: &Pubkey, referrer: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[REFERRER_SEED, referrer.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll referrer stats data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(ReferrerStats {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            referrer: Pubkey::new(&data[2..34]),
+            referrals: BigEndian::read_u32(&data[34..38]),
+            referred_volume: BigEndian::read_u64(&data[38..46]),
+            earnings: BigEndian::read_u64(&data[46..54]),
+            claimable: BigEndian::read_u64(&data[54..62]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll referrer stats data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.referrer.as_ref());
+        BigEndian::write_u32(&mut data[34..38], self.referrals);
+        BigEndian::write_u64(&mut data[38..46], self.referred_volume);
+        BigEndian::write_u64(&mut data[46..54], self.earnings);
+        BigEndian::write_u64(&mut data[54..62], self.claimable);
+        Ok(())
+    }
+}
+
+/// Seed of a player's stats PDA, `["player_stats", player]`
+pub const PLAYER_STATS_SEED: &[u8] = b"player_stats";
+
+/// Betting history of a player. Lamport games are keyed by the committing
+/// wallet, token games by the payout token account, so every stats account
+/// counts in a single unit.
+///
+/// | bytes  | field                  |
+/// |--------|------------------------|
+/// | 0      | initialized            |
+/// | 1      | bump seed              |
+/// | 2..34  | player                 |
+/// | 34..42 | total wagered          |
+/// | 42..50 | total paid out         |
+/// | 50..58 | bets                   |
+/// | 58..66 | wins                   |
+/// | 66..74 | biggest win, as profit |
+/// | 74..78 | current win streak     |
+/// | 78..82 | longest win streak     |
+/// | 82..90 | first bet slot         |
+/// | 90..98 | last bet slot          |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerStats {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub player: Pubkey,
+    pub total_wagered: u64,
+    pub total_paid_out: u64,
+    pub bets: u64,
+    pub wins: u64,
+    pub biggest_win: u64,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub first_bet_slot: u64,
+    pub last_bet_slot: u64,
+}
+
+impl PlayerStats {
+    pub const LEN: usize = 98;
+
+    pub fn find_address(program_id: &Pubkey, player: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[PLAYER_STATS_SEED, player.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll player stats data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(PlayerStats {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            player: Pubkey::new(&data[2..34]),
+            total_wagered: BigEndian::read_u64(&data[34..42]),
+            total_paid_out: BigEndian::read_u64(&data[42..50]),
+            bets: BigEndian::read_u64(&data[50..58]),
+            wins: BigEndian::read_u64(&data[58..66]),
+            biggest_win: BigEndian::read_u64(&data[66..74]),
+            current_streak: BigEndian::read_u32(&data[74..78]),
+            longest_streak: BigEndian::read_u32(&data[78..82]),
+            first_bet_slot: BigEndian::read_u64(&data[82..90]),
+            last_bet_slot: BigEndian::read_u64(&data[90..98]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll player stats data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.player.as_ref());
+        BigEndian::write_u64(&mut data[34..42], self.total_wagered);
+        BigEndian::write_u64(&mut data[42..50], self.total_paid_out);
+        BigEndian::write_u64(&mut data[50..58], self.bets);
+        BigEndian::write_u64(&mut data[58..66], self.wins);
+        BigEndian::write_u64(&mut data[66..74], self.biggest_win);
+        BigEndian::write_u32(&mut data[74..78], self.current_streak);
+        BigEndian::write_u32(&mut data[78..82], self.longest_streak);
+        BigEndian::write_u64(&mut data[82..90], self.first_bet_slot);
+        BigEndian::write_u64(&mut data[90..98], self.last_bet_slot);
+        Ok(())
+    }
+}
+
+/// Seed of a treasury's roll history PDA, `["roll_history", treasury]`,
+/// where `treasury` is the lamport treasury account or a token pool
+pub const ROLL_HISTORY_SEED: &[u8] = b"roll_history";
+
+/// Settled bets kept by a roll history. A program can only create accounts
+/// of up to 10 KiB through a cross-program call (`MAX_PERMITTED_DATA_INCREASE`),
+/// so 128 records of 72 bytes (9,264 bytes with the header) fit where 256
+/// (18,480 bytes) would not.
+pub const ROLL_HISTORY_CAPACITY: usize = 128;
+
+/// One settled bet in a roll history. Every field is a byte array, little
+/// endian for numbers, so records have no padding or alignment and can be
+/// cast straight from account data.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RollRecord {
+    /// Wallet of a lamport game, payout token account of a token game
+    pub player: [u8; 32],
+    pub wager: [u8; 8],
+    pub payout: [u8; 8],
+    pub result: [u8; 8],
+    /// Slot the bet was committed in
+    pub slot: [u8; 8],
+    pub target: [u8; 2],
+    pub kind: u8,
+    pub padding: [u8; 5],
+}
+
+unsafe impl Zeroable for RollRecord {}
+unsafe impl Pod for RollRecord {}
+
+impl RollRecord {
+    /// Record of a settled `game` with `wager` at stake
+    pub fn new(game: &Game, wager: u64) -> Self {
+        let mut player = [0u8; 32];
+        player.copy_from_slice(game.player.as_ref());
+        RollRecord {
+            player,
+            wager: wager.to_le_bytes(),
+            payout: game.payout.to_le_bytes(),
+            result: game.result.to_le_bytes(),
+            slot: game.commit_slot.to_le_bytes(),
+            target: game.target.to_le_bytes(),
+            kind: game.kind as u8,
+            padding: [0; 5],
+        }
+    }
+
+    pub fn player(&self) -> Pubkey {
+        Pubkey::new(&self.player)
+    }
+
+    pub fn wager(&self) -> u64 {
+        u64::from_le_bytes(self.wager)
+    }
+
+    pub fn payout(&self) -> u64 {
+        u64::from_le_bytes(self.payout)
+    }
+
+    pub fn result(&self) -> u64 {
+        u64::from_le_bytes(self.result)
+    }
+
+    pub fn slot(&self) -> u64 {
+        u64::from_le_bytes(self.slot)
+    }
+
+    pub fn target(&self) -> u16 {
+        u16::from_le_bytes(self.target)
+    }
+}
+
+/// Ring buffer of a treasury's most recent settled bets, read in place with
+/// `bytemuck`. Record `total % ROLL_HISTORY_CAPACITY` is overwritten next.
+///
+/// | bytes   | field                          |
+/// |---------|--------------------------------|
+/// | 0       | initialized                    |
+/// | 1       | bump seed                      |
+/// | 2..8    | padding                        |
+/// | 8..40   | treasury or pool               |
+/// | 40..48  | bets appended so far, LE       |
+/// | 48..    | records, 72 bytes each         |
+#[repr(C)]
+#[derive(Copy)]
+pub struct RollHistory {
+    pub is_initialized: u8,
+    pub bump_seed: u8,
+    pub padding: [u8; 6],
+    pub treasury: [u8; 32],
+    pub total: [u8; 8],
+    pub records: [RollRecord; ROLL_HISTORY_CAPACITY],
+}
+
+// Arrays this long only get `Clone` through `Copy` on older compilers
+impl Clone for RollHistory {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+unsafe impl Zeroable for RollHistory {}
+unsafe impl Pod for RollHistory {}
+
+impl RollHistory {
+    pub const LEN: usize = std::mem::size_of::<RollHistory>();
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[ROLL_HISTORY_SEED, treasury.as_ref()], program_id)
+    }
+
+    /// View account data as a roll history, without copying
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        data.get(..Self::LEN)
+            .map(bytemuck::from_bytes)
+            .ok_or(ProgramError::AccountDataTooSmall)
+    }
+
+    /// Mutable view of account data as a roll history, without copying
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        data.get_mut(..Self::LEN)
+            .map(bytemuck::from_bytes_mut)
+            .ok_or(ProgramError::AccountDataTooSmall)
+    }
+
+    pub fn total(&self) -> u64 {
+        u64::from_le_bytes(self.total)
+    }
+
+    /// Overwrite the oldest record with `record`
+    pub fn append(&mut self, record: RollRecord) {
+        let total = self.total();
+        self.records[(total % ROLL_HISTORY_CAPACITY as u64) as usize] = record;
+        self.total = total.wrapping_add(1).to_le_bytes();
+    }
+
+    /// Records from newest to oldest
+    pub fn recent(&self) -> impl Iterator<Item = &RollRecord> {
+        let total = self.total();
+        let len = total.min(ROLL_HISTORY_CAPACITY as u64);
+        (1..=len).map(move |i| {
+            &self.records[((total - i) % ROLL_HISTORY_CAPACITY as u64) as usize]
+        })
+    }
+}
+
+/// Seed of a treasury's multisig admin PDA, `["admin", treasury]`, where
+/// `treasury` is the lamport treasury account or a token pool
+pub const ADMIN_SEED: &[u8] = b"admin";
+
+/// Seed of a multisig proposal PDA, `["proposal", multisig, index]`, with
+/// the index little endian
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+
+/// Most signers a multisig admin can have, one approval bit each
+pub const MAX_SIGNERS: usize = 8;
+
+/// M-of-N admin of a treasury, which becomes the treasury's authority
+///
+/// | bytes    | field                                |
+/// |----------|--------------------------------------|
+/// | 0        | initialized                          |
+/// | 1        | bump seed                            |
+/// | 2..34    | treasury or pool                     |
+/// | 34       | treasury kind                        |
+/// | 35       | approvals needed                     |
+/// | 36       | signer count                         |
+/// | 37..293  | signers, unused slots zero           |
+/// | 293..301 | slots between proposal and execution |
+/// | 301..309 | proposals made                       |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Multisig {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    /// `TreasuryKind` of `treasury`
+    pub kind: u8,
+    pub threshold: u8,
+    pub signer_count: u8,
+    pub signers: [Pubkey; MAX_SIGNERS],
+    /// Timelock every proposal waits out before it can execute
+    pub delay_slots: u64,
+    pub proposal_count: u64,
+}
+
+impl Multisig {
+    pub const LEN: usize = 309;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[ADMIN_SEED, treasury.as_ref()], program_id)
+    }
+
+    /// Position of `key` among the signers, its approval bit
+    pub fn signer_index(&self, key: &Pubkey) -> Option<usize> {
+        self.signers[..self.signer_count as usize]
+            .iter()
+            .position(|signer| signer == key)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll multisig data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let mut signers = [Pubkey::default(); MAX_SIGNERS];
+        for (i, signer) in signers.iter_mut().enumerate() {
+            *signer = Pubkey::new(&data[37 + 32 * i..69 + 32 * i]);
+        }
+        Ok(Multisig {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            kind: data[34],
+            threshold: data[35],
+            signer_count: data[36],
+            signers,
+            delay_slots: BigEndian::read_u64(&data[293..301]),
+            proposal_count: BigEndian::read_u64(&data[301..309]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll multisig data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34] = self.kind;
+        data[35] = self.threshold;
+        data[36] = self.signer_count;
+        for (i, signer) in self.signers.iter().enumerate() {
+            data[37 + 32 * i..69 + 32 * i].copy_from_slice(signer.as_ref());
+        }
+        BigEndian::write_u64(&mut data[293..301], self.delay_slots);
+        BigEndian::write_u64(&mut data[301..309], self.proposal_count);
+        Ok(())
+    }
+}
+
+/// Pending admin change of a multisig
+///
+/// | bytes  | field                     |
+/// |--------|---------------------------|
+/// | 0      | initialized               |
+/// | 1      | bump seed                 |
+/// | 2..34  | multisig                  |
+/// | 34..42 | index                     |
+/// | 42..76 | action, as `AdminAction`  |
+/// | 76..84 | earliest execution slot   |
+/// | 84     | approvals, bit i signer i |
+/// | 85     | executed                  |
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proposal {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub multisig: Pubkey,
+    pub index: u64,
+    pub action: [u8; 34],
+    pub eta_slot: u64,
+    pub approvals: u8,
+    pub executed: bool,
+}
+
+impl Proposal {
+    pub const LEN: usize = 86;
+
+    pub fn find_address(program_id: &Pubkey, multisig: &Pubkey, index: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[PROPOSAL_SEED, multisig.as_ref(), &index.to_le_bytes()],
+            program_id,
+        )
+    }
+
+    pub fn approval_count(&self) -> u32 {
+        self.approvals.count_ones()
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll proposal data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let mut action = [0u8; 34];
+        action.copy_from_slice(&data[42..76]);
+        Ok(Proposal {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            multisig: Pubkey::new(&data[2..34]),
+            index: BigEndian::read_u64(&data[34..42]),
+            action,
+            eta_slot: BigEndian::read_u64(&data[76..84]),
+            approvals: data[84],
+            executed: data[85] != 0,
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll proposal data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.multisig.as_ref());
+        BigEndian::write_u64(&mut data[34..42], self.index);
+        data[42..76].copy_from_slice(&self.action);
+        BigEndian::write_u64(&mut data[76..84], self.eta_slot);
+        data[84] = self.approvals;
+        data[85] = self.executed as u8;
+        Ok(())
+    }
+}
+
+/// Value of a treasury or token pool as the epoch began, recorded by the
+/// epoch's first instruction to change it before it does
+///
+/// | bytes  | field                             |
+/// |--------|-----------------------------------|
+/// | 0..8   | epoch                             |
+/// | 8..16  | unreserved liquidity              |
+/// | 16..24 | treasury token supply             |
+/// | 24     | supply recorded                   |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EpochStart {
+    pub epoch: u64,
+    pub liquidity: u64,
+    pub supply: u64,
+    /// Whether an instruction reading the treasury token mint has recorded
+    /// the supply yet
+    pub has_supply: bool,
+}
+
+impl EpochStart {
+    pub const LEN: usize = 25;
+
+    pub fn unpack(data: &[u8]) -> Self {
+        EpochStart {
+            epoch: BigEndian::read_u64(&data[0..8]),
+            liquidity: BigEndian::read_u64(&data[8..16]),
+            supply: BigEndian::read_u64(&data[16..24]),
+            has_supply: data[24] != 0,
+        }
+    }
+
+    pub fn pack(&self, data: &mut [u8]) {
+        BigEndian::write_u64(&mut data[0..8], self.epoch);
+        BigEndian::write_u64(&mut data[8..16], self.liquidity);
+        BigEndian::write_u64(&mut data[16..24], self.supply);
+        data[24] = self.has_supply as u8;
+    }
+}
+
+/// Seed of a treasury's withdrawal queue PDA, `["withdraw_queue",
+/// treasury]`, where `treasury` is the lamport treasury account or a token
+/// pool
+pub const WITHDRAW_QUEUE_SEED: &[u8] = b"withdraw_queue";
+
+/// Seed of the queue's escrow of requested treasury tokens,
+/// `["withdraw_escrow", treasury]`, a token account owned by the queue
+pub const WITHDRAW_ESCROW_SEED: &[u8] = b"withdraw_escrow";
+
+/// Seed of an LP's pending withdrawal PDA,
+/// `["withdraw_request", treasury, owner]`
+pub const WITHDRAW_REQUEST_SEED: &[u8] = b"withdraw_request";
+
+/// Withdrawal queue of a lamport treasury or token pool, with the treasury
+/// token price snapshot taken at the start of the latest epoch
+///
+/// | bytes  | field                                 |
+/// |--------|---------------------------------------|
+/// | 0      | initialized                           |
+/// | 1      | bump seed                             |
+/// | 2..34  | treasury or pool                      |
+/// | 34..66 | treasury token mint                   |
+/// | 66     | escrow bump seed                      |
+/// | 67..75 | epoch of the snapshot                 |
+/// | 75..83 | unreserved liquidity at the snapshot  |
+/// | 83..91 | treasury token supply at the snapshot |
+/// | 91..99 | treasury tokens queued                |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WithdrawQueue {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    pub mint: Pubkey,
+    pub escrow_bump_seed: u8,
+    pub epoch: u64,
+    pub liquidity: u64,
+    pub supply: u64,
+    pub queued: u64,
+}
+
+impl WithdrawQueue {
+    pub const LEN: usize = 99;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[WITHDRAW_QUEUE_SEED, treasury.as_ref()], program_id)
+    }
+
+    pub fn find_escrow_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[WITHDRAW_ESCROW_SEED, treasury.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll withdraw queue data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(WithdrawQueue {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            mint: Pubkey::new(&data[34..66]),
+            escrow_bump_seed: data[66],
+            epoch: BigEndian::read_u64(&data[67..75]),
+            liquidity: BigEndian::read_u64(&data[75..83]),
+            supply: BigEndian::read_u64(&data[83..91]),
+            queued: BigEndian::read_u64(&data[91..99]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll withdraw queue data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34..66].copy_from_slice(self.mint.as_ref());
+        data[66] = self.escrow_bump_seed;
+        BigEndian::write_u64(&mut data[67..75], self.epoch);
+        BigEndian::write_u64(&mut data[75..83], self.liquidity);
+        BigEndian::write_u64(&mut data[83..91], self.supply);
+        BigEndian::write_u64(&mut data[91..99], self.queued);
+        Ok(())
+    }
+}
+
+/// Treasury tokens an LP queued for withdrawal, paid at the price of the
+/// first epoch snapshot after the request
+///
+/// | bytes  | field                  |
+/// |--------|------------------------|
+/// | 0      | initialized            |
+/// | 1      | bump seed              |
+/// | 2..34  | treasury               |
+/// | 34..66 | owner                  |
+/// | 66..74 | treasury tokens queued |
+/// | 74..82 | epoch of the request   |
+/// | 82..84 | early exit fee, bps    |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WithdrawRequest {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    pub owner: Pubkey,
+    pub tokens: u64,
+    pub epoch: u64,
+    /// Fee owed for requesting within the owner's deposit lockup
+    pub early_exit_fee_bps: u16,
+}
+
+impl WithdrawRequest {
+    pub const LEN: usize = 84;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[WITHDRAW_REQUEST_SEED, treasury.as_ref(), owner.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll withdraw request data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(WithdrawRequest {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            owner: Pubkey::new(&data[34..66]),
+            tokens: BigEndian::read_u64(&data[66..74]),
+            epoch: BigEndian::read_u64(&data[74..82]),
+            early_exit_fee_bps: BigEndian::read_u16(&data[82..84]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll withdraw request data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34..66].copy_from_slice(self.owner.as_ref());
+        BigEndian::write_u64(&mut data[66..74], self.tokens);
+        BigEndian::write_u64(&mut data[74..82], self.epoch);
+        BigEndian::write_u16(&mut data[82..84], self.early_exit_fee_bps);
+        Ok(())
+    }
+}
+
+/// Seed of an LP's deposit record PDA, `["deposit", treasury, owner]`
+pub const DEPOSIT_SEED: &[u8] = b"deposit";
+
+/// Deposits of an LP into a treasury or pool, which start the LP's lockup
+/// from their weighted deposit slot
+///
+/// | bytes  | field        |
+/// |--------|--------------|
+/// | 0      | initialized  |
+/// | 1      | bump seed    |
+/// | 2..34  | treasury     |
+/// | 34..66 | owner        |
+/// | 66..74 | deposit slot |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LpDeposit {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    /// Depositor, who held the token account the treasury tokens were
+    /// minted to
+    pub owner: Pubkey,
+    pub deposit_slot: u64,
+}
+
+impl LpDeposit {
+    pub const LEN: usize = 74;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[DEPOSIT_SEED, treasury.as_ref(), owner.as_ref()], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll deposit record data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(LpDeposit {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            owner: Pubkey::new(&data[34..66]),
+            deposit_slot: BigEndian::read_u64(&data[66..74]),
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll deposit record data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34..66].copy_from_slice(self.owner.as_ref());
+        BigEndian::write_u64(&mut data[66..74], self.deposit_slot);
+        Ok(())
+    }
+}
+
+/// Seed of a depositor's allowlist entry PDA,
+/// `["allowlist", treasury, depositor]`, where `treasury` is the lamport
+/// treasury account or a token pool
+pub const ALLOWLIST_SEED: &[u8] = b"allowlist";
+
+/// Whether a depositor may deposit into an allowlisted treasury
+///
+/// | bytes  | field       |
+/// |--------|-------------|
+/// | 0      | initialized |
+/// | 1      | bump seed   |
+/// | 2..34  | treasury    |
+/// | 34..66 | depositor   |
+/// | 66     | allowed     |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllowlistEntry {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    pub depositor: Pubkey,
+    pub allowed: bool,
+}
+
+impl AllowlistEntry {
+    pub const LEN: usize = 67;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey, depositor: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[ALLOWLIST_SEED, treasury.as_ref(), depositor.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll allowlist entry data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(AllowlistEntry {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            depositor: Pubkey::new(&data[34..66]),
+            allowed: data[66] != 0,
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll allowlist entry data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        data[34..66].copy_from_slice(self.depositor.as_ref());
+        data[66] = self.allowed as u8;
+        Ok(())
+    }
+}
+
+/// Seed of a treasury's price oracle PDA, `["oracle", treasury]`, where
+/// `treasury` is the lamport treasury account or a token pool
+pub const ORACLE_SEED: &[u8] = b"oracle";
+
+/// Epoch snapshots a price oracle keeps, oldest overwritten first
+pub const ORACLE_SNAPSHOTS: usize = 16;
+
+/// Treasury token price and cumulative price at the first update of an
+/// epoch
+///
+/// | bytes  | field                  |
+/// |--------|------------------------|
+/// | 0..8   | epoch                  |
+/// | 8..16  | price per share        |
+/// | 16..32 | cumulative price, u128 |
+/// | 32..40 | unix timestamp, i64    |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OracleSnapshot {
+    pub epoch: u64,
+    pub price: u64,
+    pub cumulative_price: u128,
+    pub timestamp: i64,
+}
+
+impl OracleSnapshot {
+    pub const LEN: usize = 40;
+
+    pub fn unpack(data: &[u8]) -> Self {
+        OracleSnapshot {
+            epoch: BigEndian::read_u64(&data[0..8]),
+            price: BigEndian::read_u64(&data[8..16]),
+            cumulative_price: BigEndian::read_u128(&data[16..32]),
+            timestamp: BigEndian::read_i64(&data[32..40]),
+        }
+    }
+
+    pub fn pack(&self, data: &mut [u8]) {
+        BigEndian::write_u64(&mut data[0..8], self.epoch);
+        BigEndian::write_u64(&mut data[8..16], self.price);
+        BigEndian::write_u128(&mut data[16..32], self.cumulative_price);
+        BigEndian::write_i64(&mut data[32..40], self.timestamp);
+    }
+}
+
+/// Treasury token price oracle, updated on every deposit, withdrawal and
+/// settle of its treasury. Prices are in `shares::PRICE_SCALE` units and
+/// the cumulative price sums the price over every second since the first
+/// update.
+///
+/// | bytes   | field                       |
+/// |---------|-----------------------------|
+/// | 0       | initialized                 |
+/// | 1       | bump seed                   |
+/// | 2..34   | treasury or pool            |
+/// | 34..42  | liquidity                   |
+/// | 42..50  | treasury token supply       |
+/// | 50..58  | price per share             |
+/// | 58..66  | last update slot            |
+/// | 66..74  | last update unix timestamp  |
+/// | 74..90  | cumulative price, u128      |
+/// | 90      | snapshots taken, at most 16 |
+/// | 91      | next snapshot index         |
+/// | 92..732 | epoch snapshots             |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolOracle {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub treasury: Pubkey,
+    pub liquidity: u64,
+    pub supply: u64,
+    pub price: u64,
+    pub last_slot: u64,
+    pub last_timestamp: i64,
+    pub cumulative_price: u128,
+    pub snapshot_count: u8,
+    pub next_snapshot: u8,
+    pub snapshots: [OracleSnapshot; ORACLE_SNAPSHOTS],
+}
+
+impl PoolOracle {
+    pub const LEN: usize = 92 + ORACLE_SNAPSHOTS * OracleSnapshot::LEN;
+
+    pub fn find_address(program_id: &Pubkey, treasury: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[ORACLE_SEED, treasury.as_ref()], program_id)
+    }
+
+    /// Latest snapshot, if any
+    pub fn latest_snapshot(&self) -> Option<&OracleSnapshot> {
+        if self.snapshot_count == 0 {
+            return None;
+        }
+        let index = (self.next_snapshot as usize + ORACLE_SNAPSHOTS - 1) % ORACLE_SNAPSHOTS;
+        Some(&self.snapshots[index])
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll oracle data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let mut snapshots = [OracleSnapshot::default(); ORACLE_SNAPSHOTS];
+        for (i, snapshot) in snapshots.iter_mut().enumerate() {
+            let start = 92 + i * OracleSnapshot::LEN;
+            *snapshot = OracleSnapshot::unpack(&data[start..start + OracleSnapshot::LEN]);
+        }
+        Ok(PoolOracle {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            treasury: Pubkey::new(&data[2..34]),
+            liquidity: BigEndian::read_u64(&data[34..42]),
+            supply: BigEndian::read_u64(&data[42..50]),
+            price: BigEndian::read_u64(&data[50..58]),
+            last_slot: BigEndian::read_u64(&data[58..66]),
+            last_timestamp: BigEndian::read_i64(&data[66..74]),
+            cumulative_price: BigEndian::read_u128(&data[74..90]),
+            snapshot_count: data[90],
+            next_snapshot: data[91],
+            snapshots,
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll oracle data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.treasury.as_ref());
+        BigEndian::write_u64(&mut data[34..42], self.liquidity);
+        BigEndian::write_u64(&mut data[42..50], self.supply);
+        BigEndian::write_u64(&mut data[50..58], self.price);
+        BigEndian::write_u64(&mut data[58..66], self.last_slot);
+        BigEndian::write_i64(&mut data[66..74], self.last_timestamp);
+        BigEndian::write_u128(&mut data[74..90], self.cumulative_price);
+        data[90] = self.snapshot_count;
+        data[91] = self.next_snapshot;
+        for (i, snapshot) in self.snapshots.iter().enumerate() {
+            let start = 92 + i * OracleSnapshot::LEN;
+            snapshot.pack(&mut data[start..start + OracleSnapshot::LEN]);
+        }
+        Ok(())
+    }
+}
+
+/// Seed of a pool's house seed chain PDA, `["house_chain", pool]`
+pub const HOUSE_CHAIN_SEED: &[u8] = b"house_chain";
+
+/// Games a house chain holds waiting for their seed
+pub const MAX_PENDING_SEEDS: usize = 16;
+
+/// A seed the house posted on chain for a game waiting for it
+///
+/// | bytes | field                    |
+/// |-------|--------------------------|
+/// | 0     | posted                   |
+/// | 1..9  | slot the seed was posted |
+/// | 9..41 | seed                     |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PostedSeed {
+    pub posted: bool,
+    pub slot: u64,
+    pub seed: [u8; 32],
+}
+
+impl PostedSeed {
+    pub const LEN: usize = 41;
+
+    pub fn unpack(data: &[u8]) -> Self {
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&data[9..41]);
+        PostedSeed {
+            posted: data[0] != 0,
+            slot: BigEndian::read_u64(&data[1..9]),
+            seed,
+        }
+    }
+
+    pub fn pack(&self, data: &mut [u8]) {
+        data[0] = self.posted as u8;
+        BigEndian::write_u64(&mut data[1..9], self.slot);
+        data[9..41].copy_from_slice(&self.seed);
+    }
+}
+
+/// Hash chain the house commits to for a token pool's randomness. Every
+/// commit takes the next seed, in order, and each revealed seed hashes to
+/// the one revealed before it, the head starting at the house's commitment.
+/// Seeds the house never posted are skipped once their game is past the
+/// crank delay, so the next revealed seed hashes to the head once per
+/// skipped seed, plus one.
+///
+/// | bytes     | field                                    |
+/// |-----------|------------------------------------------|
+/// | 0         | initialized                              |
+/// | 1         | bump seed                                |
+/// | 2..34     | pool                                     |
+/// | 34..66    | house signer, an Ed25519 public key      |
+/// | 66..98    | head, the last revealed seed             |
+/// | 98..106   | seeds revealed or skipped                |
+/// | 106..114  | seeds assigned to commits                |
+/// | 114..122  | seeds skipped since the head             |
+/// | 122..634  | games waiting for a seed, by seed number |
+/// | 634..1290 | seeds posted for them, as `PostedSeed`   |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HouseChain {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub pool: Pubkey,
+    pub house: Pubkey,
+    pub head: [u8; 32],
+    pub revealed: u64,
+    pub assigned: u64,
+    pub skipped: u64,
+    pub pending: [Pubkey; MAX_PENDING_SEEDS],
+    pub posted: [PostedSeed; MAX_PENDING_SEEDS],
+}
+
+impl HouseChain {
+    pub const LEN: usize = 122 + MAX_PENDING_SEEDS * (32 + PostedSeed::LEN);
+    const POSTED_OFFSET: usize = 122 + MAX_PENDING_SEEDS * 32;
+
+    pub fn find_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[HOUSE_CHAIN_SEED, pool.as_ref()], program_id)
+    }
+
+    /// Game waiting for the next seed, if any
+    pub fn next_pending(&self) -> Option<&Pubkey> {
+        if self.assigned == self.revealed {
+            return None;
+        }
+        Some(&self.pending[self.revealed as usize % MAX_PENDING_SEEDS])
+    }
+
+    /// Position of `game` among the games waiting for a seed, 0 being next
+    pub fn position(&self, game: &Pubkey) -> Option<u64> {
+        (0..self.assigned - self.revealed)
+            .find(|position| self.pending[(self.revealed + position) as usize % MAX_PENDING_SEEDS] == *game)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll house chain data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let mut head = [0u8; 32];
+        head.copy_from_slice(&data[66..98]);
+        let mut pending = [Pubkey::default(); MAX_PENDING_SEEDS];
+        for (i, game) in pending.iter_mut().enumerate() {
+            let start = 122 + i * 32;
+            *game = Pubkey::new(&data[start..start + 32]);
+        }
+        let mut posted = [PostedSeed::default(); MAX_PENDING_SEEDS];
+        for (i, seed) in posted.iter_mut().enumerate() {
+            let start = Self::POSTED_OFFSET + i * PostedSeed::LEN;
+            *seed = PostedSeed::unpack(&data[start..start + PostedSeed::LEN]);
+        }
+        Ok(HouseChain {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            pool: Pubkey::new(&data[2..34]),
+            house: Pubkey::new(&data[34..66]),
+            head,
+            revealed: BigEndian::read_u64(&data[98..106]),
+            assigned: BigEndian::read_u64(&data[106..114]),
+            skipped: BigEndian::read_u64(&data[114..122]),
+            pending,
+            posted,
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll house chain data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.pool.as_ref());
+        data[34..66].copy_from_slice(self.house.as_ref());
+        data[66..98].copy_from_slice(&self.head);
+        BigEndian::write_u64(&mut data[98..106], self.revealed);
+        BigEndian::write_u64(&mut data[106..114], self.assigned);
+        BigEndian::write_u64(&mut data[114..122], self.skipped);
+        for (i, game) in self.pending.iter().enumerate() {
+            let start = 122 + i * 32;
+            data[start..start + 32].copy_from_slice(game.as_ref());
+        }
+        for (i, seed) in self.posted.iter().enumerate() {
+            let start = Self::POSTED_OFFSET + i * PostedSeed::LEN;
+            seed.pack(&mut data[start..start + PostedSeed::LEN]);
+        }
+        Ok(())
+    }
+}
+
+/// Seed of a pool's VRF queue PDA, `["vrf", pool]`
+pub const VRF_SEED: &[u8] = b"vrf";
+
+/// Games a VRF queue holds waiting for their output
+pub const MAX_VRF_REQUESTS: usize = 16;
+
+/// A game waiting for, or holding, its VRF output. An empty slot has the
+/// default game key.
+///
+/// | bytes  | field                         |
+/// |--------|-------------------------------|
+/// | 0..32  | game                          |
+/// | 32     | fulfilled                     |
+/// | 33..65 | VRF output                    |
+/// | 65..97 | hash of the player's secret   |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VrfRequest {
+    pub game: Pubkey,
+    pub fulfilled: bool,
+    pub output: [u8; 32],
+    pub secret_hash: [u8; 32],
+}
+
+impl VrfRequest {
+    pub const LEN: usize = 97;
+
+    pub fn unpack(data: &[u8]) -> Self {
+        let mut output = [0u8; 32];
+        output.copy_from_slice(&data[33..65]);
+        let mut secret_hash = [0u8; 32];
+        secret_hash.copy_from_slice(&data[65..97]);
+        VrfRequest {
+            game: Pubkey::new(&data[0..32]),
+            fulfilled: data[32] != 0,
+            output,
+            secret_hash,
+        }
+    }
+
+    pub fn pack(&self, data: &mut [u8]) {
+        data[0..32].copy_from_slice(self.game.as_ref());
+        data[32] = self.fulfilled as u8;
+        data[33..65].copy_from_slice(&self.output);
+        data[65..97].copy_from_slice(&self.secret_hash);
+    }
+}
+
+/// VRF requests of a token pool's committed games, fulfilled by the pool's
+/// authorized worker
+///
+/// | bytes     | field             |
+/// |-----------|-------------------|
+/// | 0         | initialized       |
+/// | 1         | bump seed         |
+/// | 2..34     | pool              |
+/// | 34..66    | worker            |
+/// | 66..1618  | requests          |
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VrfQueue {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub pool: Pubkey,
+    pub worker: Pubkey,
+    pub requests: [VrfRequest; MAX_VRF_REQUESTS],
+}
+
+impl VrfQueue {
+    pub const LEN: usize = 66 + MAX_VRF_REQUESTS * VrfRequest::LEN;
+
+    pub fn find_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[VRF_SEED, pool.as_ref()], program_id)
+    }
+
+    /// Index of the request of `game`
+    pub fn find(&self, game: &Pubkey) -> Option<usize> {
+        self.requests.iter().position(|request| request.game == *game)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll VRF queue data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let mut requests = [VrfRequest::default(); MAX_VRF_REQUESTS];
+        for (i, request) in requests.iter_mut().enumerate() {
+            let start = 66 + i * VrfRequest::LEN;
+            *request = VrfRequest::unpack(&data[start..start + VrfRequest::LEN]);
+        }
+        Ok(VrfQueue {
+            is_initialized: data[0] != 0,
+            bump_seed: data[1],
+            pool: Pubkey::new(&data[2..34]),
+            worker: Pubkey::new(&data[34..66]),
+            requests,
+        })
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            info!("SolanaRoll VRF queue data too small");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[0] = self.is_initialized as u8;
+        data[1] = self.bump_seed;
+        data[2..34].copy_from_slice(self.pool.as_ref());
+        data[34..66].copy_from_slice(self.worker.as_ref());
+        for (i, request) in self.requests.iter().enumerate() {
+            let start = 66 + i * VrfRequest::LEN;
+            request.pack(&mut data[start..start + VrfRequest::LEN]);
+        }
+        Ok(())
+    }
+}
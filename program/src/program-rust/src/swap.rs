@@ -0,0 +1,436 @@
+//! Swap-then-bet through an SPL token-swap compatible pool
+//!
+//! `SwapAndCommit` swaps the player's token into a token pool's mint and
+//! commits a bet with the proceeds in one transaction. A game committed with
+//! swap back is paid to the player's original token account and settled with
+//! `SettleAndSwap`, which pays the player in the pool's mint and swaps the
+//! payout with the player's own signature. The pool never signs for a swap
+//! program, so a swap program can only ever move tokens the player holds.
+
+use crate::{
+    error::RollError,
+    fees::FeeAccounts,
+    instruction::{SwapAndCommit, TreasuryKind},
+    oracle,
+    randomness::RandomnessAccounts,
+    state::Pool,
+    token_pool::{
+        escrow_and_commit, liquidity, load_pool, load_token_account, settle_game, token_amount,
+        transfer_from_vault,
+    },
+    validation::{
+        assert_distinct, assert_key, assert_program_account, assert_signer, assert_sysvar,
+        assert_writable,
+    },
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::clock,
+};
+
+/// Tag of the token-swap `Swap` instruction
+const SWAP_TAG: u8 = 1;
+
+/// Accounts of a token-swap pool, in the order `Swap` expects them
+pub struct SwapAccounts<'a, 'b> {
+    pub program: &'b AccountInfo<'a>,
+    pub swap: &'b AccountInfo<'a>,
+    pub authority: &'b AccountInfo<'a>,
+    /// Pool reserve receiving the input token
+    pub reserve_in: &'b AccountInfo<'a>,
+    /// Pool reserve paying out the output token
+    pub reserve_out: &'b AccountInfo<'a>,
+    pub pool_mint: &'b AccountInfo<'a>,
+    pub fee: &'b AccountInfo<'a>,
+}
+
+impl<'a, 'b> SwapAccounts<'a, 'b> {
+    fn next<I: Iterator<Item = &'b AccountInfo<'a>>>(iter: &mut I) -> Result<Self, ProgramError> {
+        Ok(SwapAccounts {
+            program: next_account_info(iter)?,
+            swap: next_account_info(iter)?,
+            authority: next_account_info(iter)?,
+            reserve_in: next_account_info(iter)?,
+            reserve_out: next_account_info(iter)?,
+            pool_mint: next_account_info(iter)?,
+            fee: next_account_info(iter)?,
+        })
+    }
+
+    /// Swap `amount_in` from `source` to `destination`, moved by `transfer_authority`
+    #[allow(clippy::too_many_arguments)]
+    fn swap(
+        &self,
+        token_program: &AccountInfo<'a>,
+        transfer_authority: &AccountInfo<'a>,
+        source: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let instr = swap_instruction(
+            self.program.key,
+            token_program.key,
+            self.swap.key,
+            self.authority.key,
+            transfer_authority.key,
+            source.key,
+            self.reserve_in.key,
+            self.reserve_out.key,
+            destination.key,
+            self.pool_mint.key,
+            self.fee.key,
+            amount_in,
+            minimum_amount_out,
+        );
+        invoke_signed(
+            &instr,
+            &[
+                self.swap.clone(),
+                self.authority.clone(),
+                transfer_authority.clone(),
+                source.clone(),
+                self.reserve_in.clone(),
+                self.reserve_out.clone(),
+                destination.clone(),
+                self.pool_mint.clone(),
+                self.fee.clone(),
+                token_program.clone(),
+                self.program.clone(),
+            ],
+            signer_seeds,
+        )
+    }
+}
+
+/// Token-swap `Swap` instruction, built by hand so any program with the
+/// SPL token-swap interface can be used
+#[allow(clippy::too_many_arguments)]
+pub fn swap_instruction(
+    swap_program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap: &Pubkey,
+    swap_authority: &Pubkey,
+    transfer_authority: &Pubkey,
+    source: &Pubkey,
+    reserve_in: &Pubkey,
+    reserve_out: &Pubkey,
+    destination: &Pubkey,
+    pool_mint: &Pubkey,
+    fee: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    let mut data = vec![0u8; 17];
+    data[0] = SWAP_TAG;
+    LittleEndian::write_u64(&mut data[1..9], amount_in);
+    LittleEndian::write_u64(&mut data[9..17], minimum_amount_out);
+    Instruction {
+        program_id: *swap_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*swap, false),
+            AccountMeta::new_readonly(*swap_authority, false),
+            AccountMeta::new_readonly(*transfer_authority, true),
+            AccountMeta::new(*source, false),
+            AccountMeta::new(*reserve_in, false),
+            AccountMeta::new(*reserve_out, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new(*pool_mint, false),
+            AccountMeta::new(*fee, false),
+            AccountMeta::new_readonly(*token_program_id, false),
+        ],
+        data,
+    }
+}
+
+/// Amount a swap paid into an account, from its balance before and after,
+/// failing when it is below the minimum the player accepted
+fn swap_received(
+    balance_before: u64,
+    balance_after: u64,
+    minimum_amount_out: u64,
+) -> Result<u64, ProgramError> {
+    let received = balance_after.saturating_sub(balance_before);
+    info!("Swap received:");
+    info!(&received.to_string());
+    if received < minimum_amount_out {
+        return Err(RollError::SlippageExceeded.into());
+    }
+    Ok(received)
+}
+
+/// 9 - swap the player's token into the pool's mint and commit the bet
+///
+/// Data is laid out as `SwapAndCommit`. The total wager is escrowed from the
+/// player's pool mint account and any extra swap output stays there.
+/// Accounts: player (signer), game, clock sysvar, slot hashes sysvar or the
+/// pool's house chain or VRF queue, pool, vault, player source token account, player pool
+/// mint token account, swap program, swap, swap authority, swap source
+/// reserve, swap destination reserve, swap pool mint, swap fee account, token
+/// program, referrer token account (optional)
+pub fn process_swap_and_commit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let swap_and_commit = SwapAndCommit::unpack(data)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let player_account = next_account_info(accounts_iter)?;
+    let game_account = next_account_info(accounts_iter)?;
+    let sysvar_account = next_account_info(accounts_iter)?;
+    let randomness_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let source_token_account = next_account_info(accounts_iter)?;
+    let wager_token_account = next_account_info(accounts_iter)?;
+    let swap = SwapAccounts::next(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let referrer_account = next_account_info(accounts_iter).ok();
+
+    assert_signer(player_account)?;
+    assert_program_account(game_account, program_id)?;
+    assert_sysvar(sysvar_account, &clock::id())?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_writable(source_token_account)?;
+    assert_writable(wager_token_account)?;
+    assert_distinct(&[
+        game_account,
+        pool_account,
+        vault_account,
+        source_token_account,
+        wager_token_account,
+    ])?;
+    if swap.program.key == program_id {
+        info!("Swap program cannot be SolanaRoll");
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    let mut pool = load_pool(program_id, pool_account, vault_account)?;
+
+    info!("Swapping for wager, amount in, minimum out:");
+    info!(&swap_and_commit.amount_in.to_string());
+    info!(&swap_and_commit.minimum_amount_out.to_string());
+    let balance_before = token_amount(wager_token_account)?;
+    swap.swap(
+        token_program,
+        player_account,
+        source_token_account,
+        wager_token_account,
+        swap_and_commit.amount_in,
+        swap_and_commit.minimum_amount_out,
+        &[],
+    )?;
+    swap_received(
+        balance_before,
+        token_amount(wager_token_account)?,
+        swap_and_commit.minimum_amount_out,
+    )?;
+
+    let payout_account = if swap_and_commit.swap_back {
+        source_token_account.key
+    } else {
+        wager_token_account.key
+    };
+    escrow_and_commit(
+        program_id,
+        &swap_and_commit.commit,
+        player_account,
+        game_account,
+        sysvar_account,
+        randomness_account,
+        pool_account,
+        vault_account,
+        &mut pool,
+        wager_token_account,
+        payout_account,
+        referrer_account,
+        token_program,
+    )
+}
+
+/// 10 - settle a swap back game, swapping the payout into the player's token
+///
+/// Data: [1] reveal number, [2..10] minimum amount out, [10..42] house seed
+/// (house-seeded pools only) or the player's secret (VRF pools only). Only
+/// the owner of the payout account may settle, so the slippage limit is
+/// theirs. The payout goes from the vault to the owner's pool mint token
+/// account and is swapped from there with the owner signing. Accounts: owner
+/// (signer), game, clock sysvar, slot hashes sysvar or the pool's VRF queue
+/// or house chain, the latter followed by the instructions sysvar, pool,
+/// vault, player token account, owner pool mint token account, swap
+/// program, swap, swap authority, swap source reserve, swap destination
+/// reserve, swap pool mint, swap fee account, token program, player stats,
+/// roll history, price oracle, fee vault, fee token account, referrer token
+/// account (only for games with a referrer)
+pub fn process_settle_and_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 10 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let reveal_number = data[1] as u32;
+    let minimum_amount_out = LittleEndian::read_u64(&data[2..10]);
+    let house_seed = data.get(10..42);
+
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?;
+    let game_account = next_account_info(accounts_iter)?;
+    let sysvar_account = next_account_info(accounts_iter)?;
+    let randomness = RandomnessAccounts::next(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let player_token_account = next_account_info(accounts_iter)?;
+    let payout_token_account = next_account_info(accounts_iter)?;
+    let swap = SwapAccounts::next(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let player_stats_account = next_account_info(accounts_iter)?;
+    let roll_history_account = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+    let fee_accounts = FeeAccounts::next(accounts_iter, TreasuryKind::TokenPool)?;
+
+    assert_signer(owner_account)?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_writable(payout_token_account)?;
+    load_token_account(player_token_account, owner_account.key)?;
+    let payout_tokens = load_token_account(payout_token_account, owner_account.key)?;
+    if swap.program.key == program_id {
+        info!("Swap program cannot be SolanaRoll");
+        return Err(RollError::IncorrectAddress.into());
+    }
+
+    let (pool, payout): (Pool, u64) = settle_game(
+        program_id,
+        Some(reveal_number),
+        house_seed,
+        game_account,
+        sysvar_account,
+        &randomness,
+        pool_account,
+        vault_account,
+        player_token_account,
+        player_stats_account,
+        roll_history_account,
+        &fee_accounts,
+        token_program,
+    )?;
+    if payout_tokens.mint != pool.mint {
+        info!("Payout token account is not for the pool mint");
+        return Err(RollError::IncorrectAddress.into());
+    }
+    if payout > 0 {
+        transfer_from_vault(&pool, pool_account, vault_account, payout_token_account, token_program, payout)?;
+        info!("Swapping payout back, minimum out:");
+        info!(&minimum_amount_out.to_string());
+        swap.swap(
+            token_program,
+            owner_account,
+            payout_token_account,
+            player_token_account,
+            payout,
+            minimum_amount_out,
+            &[],
+        )?;
+    }
+    let treasury_liquidity = liquidity(&pool, vault_account)?;
+    oracle::update(program_id, pool_account.key, treasury_liquidity, None, sysvar_account, oracle_account)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{instruction::Commit, odds::GameKind};
+
+    #[test]
+    fn test_swap_received() {
+        assert_eq!(swap_received(100, 250, 150), Ok(150));
+        assert_eq!(swap_received(100, 300, 150), Ok(200));
+        assert_eq!(
+            swap_received(100, 249, 150),
+            Err(RollError::SlippageExceeded.into())
+        );
+        // a balance that shrank received nothing
+        assert_eq!(
+            swap_received(100, 50, 1),
+            Err(RollError::SlippageExceeded.into())
+        );
+        assert_eq!(swap_received(100, 50, 0), Ok(0));
+    }
+
+    #[test]
+    fn test_swap_instruction() {
+        let keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let instruction = swap_instruction(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+            &keys[8], &keys[9], &keys[10], 1_000, 990,
+        );
+        assert_eq!(instruction.program_id, keys[0]);
+        assert_eq!(instruction.data.len(), 17);
+        assert_eq!(instruction.data[0], SWAP_TAG);
+        assert_eq!(LittleEndian::read_u64(&instruction.data[1..9]), 1_000);
+        assert_eq!(LittleEndian::read_u64(&instruction.data[9..17]), 990);
+
+        let metas: Vec<(Pubkey, bool, bool)> = instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect();
+        assert_eq!(
+            metas,
+            vec![
+                (keys[2], false, false),
+                (keys[3], false, false),
+                (keys[4], true, false),
+                (keys[5], false, true),
+                (keys[6], false, true),
+                (keys[7], false, true),
+                (keys[8], false, true),
+                (keys[9], false, true),
+                (keys[10], false, true),
+                (keys[1], false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_minimum_out_covers_wager() {
+        let commit = Commit {
+            reveal_number: 7,
+            kind: GameKind::DiceUnder,
+            target: 5000,
+            roll_count: 2,
+            wager: 250,
+            roulette: None,
+            secret_hash: None,
+        };
+        let pack = |minimum_amount_out: u64| {
+            let mut data = vec![9u8; 17];
+            LittleEndian::write_u64(&mut data[1..9], 2_000);
+            LittleEndian::write_u64(&mut data[9..17], minimum_amount_out);
+            // the commit's command byte is the swap back flag
+            data.extend_from_slice(&commit.pack(1));
+            data
+        };
+
+        let swap_and_commit = SwapAndCommit::unpack(&pack(500)).unwrap();
+        assert_eq!(swap_and_commit.amount_in, 2_000);
+        assert_eq!(swap_and_commit.minimum_amount_out, 500);
+        assert!(swap_and_commit.swap_back);
+        assert_eq!(swap_and_commit.commit, commit);
+
+        assert_eq!(
+            SwapAndCommit::unpack(&pack(499)),
+            Err(RollError::InvalidAmount.into())
+        );
+    }
+}
@@ -0,0 +1,699 @@
+//! Treasury pools for wagering SPL tokens
+//!
+//! Each mint gets its own pool: a state PDA `["pool", mint]` that owns a
+//! vault token account `["vault", mint]` and is the mint authority of the
+//! pool's treasury token `["lp_mint", mint]`. Games, odds and max profit are
+//! the same as the lamport treasury, counted in token base units.
+
+use crate::{
+    deposit_limits,
+    error::RollError,
+    hash_value,
+    fees::{self, FeeAccounts},
+    pause::{self, PAUSE_COMMITS, PAUSE_DEPOSITS, PAUSE_SETTLES},
+    player_stats,
+    randomness::{self, RandomnessAccounts, Roll},
+    risk::DEFAULT_KELLY_FRACTION_BPS,
+    roll_history,
+    instruction::{Commit, TreasuryKind},
+    lockup,
+    oracle,
+    settle::{self, Outcome},
+    shares,
+    state::{EpochStart, Game, GameStatus, Pool, Randomness, LP_MINT_SEED, POOL_SEED, VAULT_SEED},
+    validation::{
+        assert_distinct, assert_key, assert_owned_by, assert_program_account, assert_signer,
+        assert_sysvar, assert_writable,
+    },
+    withdraw_queue,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{clock, clock::Clock, rent, rent::Rent, Sysvar},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+/// Token balance of a token account
+pub(crate) fn token_amount(account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(TokenAccount::unpack(&account.data.borrow())?.amount)
+}
+
+/// Load a token account held by `owner`
+pub(crate) fn load_token_account(account: &AccountInfo, owner: &Pubkey) -> Result<TokenAccount, ProgramError> {
+    assert_owned_by(account, &spl_token::ID)?;
+    let token_account = TokenAccount::unpack(&account.data.borrow())?;
+    if token_account.owner != *owner {
+        info!("Token account is not held by the signer");
+        return Err(RollError::IncorrectOwner.into());
+    }
+    Ok(token_account)
+}
+
+/// Pool liquidity owned by its providers, i.e. the vault minus escrowed
+/// wagers. Open bets' reserved exposure is part of it until they settle.
+pub(crate) fn liquidity(pool: &Pool, vault_account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(token_amount(vault_account)?.saturating_sub(pool.escrowed))
+}
+
+/// Load an initialized pool and check the vault is the pool's
+pub(crate) fn load_pool(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    vault_account: &AccountInfo,
+) -> Result<Pool, ProgramError> {
+    assert_program_account(pool_account, program_id)?;
+    let pool = Pool::unpack_initialized(&pool_account.data.borrow())?;
+    assert_key(vault_account, &pool.vault)?;
+    assert_writable(vault_account)?;
+    Ok(pool)
+}
+
+/// Create a program derived account, paid for by `payer`
+pub(crate) fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    new_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    owner: &Pubkey,
+    space: usize,
+    rent: &Rent,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            new_account.key,
+            rent.minimum_balance(space),
+            space as u64,
+            owner,
+        ),
+        &[payer.clone(), new_account.clone(), system_program_account.clone()],
+        &[seeds],
+    )
+}
+
+/// Move tokens out of the vault, or another token account the pool owns,
+/// signed by the pool PDA
+pub(crate) fn transfer_from_vault<'a>(
+    pool: &Pool,
+    pool_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Ok(());
+    }
+    let instr = spl_token::instruction::transfer(
+        &spl_token::ID,
+        vault_account.key,
+        destination.key,
+        pool_account.key,
+        &[],
+        amount,
+    )?;
+    invoke_signed(
+        &instr,
+        &[
+            vault_account.clone(),
+            destination.clone(),
+            pool_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[POOL_SEED, pool.mint.as_ref(), &[pool.bump_seed]]],
+    )
+}
+
+/// 4 - create the pool, vault and treasury token mint for a mint
+///
+/// The payer becomes the pool's authority. Accounts: payer (signer), pool,
+/// mint, vault, treasury token mint, rent sysvar, system program, token
+/// program
+pub fn process_init_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let lp_mint_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_owned_by(mint_account, &spl_token::ID)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    assert_key(token_program, &spl_token::ID)?;
+
+    let mint = mint_account.key;
+    let (pool_address, pool_bump) = Pool::find_address(program_id, mint);
+    let (vault_address, vault_bump) =
+        Pubkey::find_program_address(&[VAULT_SEED, mint.as_ref()], program_id);
+    let (lp_mint_address, lp_mint_bump) =
+        Pubkey::find_program_address(&[LP_MINT_SEED, mint.as_ref()], program_id);
+    assert_key(pool_account, &pool_address)?;
+    assert_key(vault_account, &vault_address)?;
+    assert_key(lp_mint_account, &lp_mint_address)?;
+
+    let rent = Rent::from_account_info(rent_account)?;
+    let decimals = Mint::unpack(&mint_account.data.borrow())?.decimals;
+
+    info!("Creating token pool");
+    create_pda_account(
+        payer_account,
+        pool_account,
+        system_program_account,
+        program_id,
+        Pool::LEN,
+        &rent,
+        &[POOL_SEED, mint.as_ref(), &[pool_bump]],
+    )?;
+    create_pda_account(
+        payer_account,
+        vault_account,
+        system_program_account,
+        &spl_token::ID,
+        TokenAccount::LEN,
+        &rent,
+        &[VAULT_SEED, mint.as_ref(), &[vault_bump]],
+    )?;
+    create_pda_account(
+        payer_account,
+        lp_mint_account,
+        system_program_account,
+        &spl_token::ID,
+        Mint::LEN,
+        &rent,
+        &[LP_MINT_SEED, mint.as_ref(), &[lp_mint_bump]],
+    )?;
+
+    invoke(
+        &spl_token::instruction::initialize_mint(
+            &spl_token::ID,
+            lp_mint_account.key,
+            pool_account.key,
+            None,
+            decimals,
+        )?,
+        &[lp_mint_account.clone(), rent_account.clone(), token_program.clone()],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_account(
+            &spl_token::ID,
+            vault_account.key,
+            mint,
+            pool_account.key,
+        )?,
+        &[
+            vault_account.clone(),
+            mint_account.clone(),
+            pool_account.clone(),
+            rent_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    let pool = Pool {
+        is_initialized: true,
+        bump_seed: pool_bump,
+        mint: *mint,
+        vault: vault_address,
+        lp_mint: lp_mint_address,
+        escrowed: 0,
+        reserved: 0,
+        authority: *payer_account.key,
+        paused: 0,
+        kelly_fraction_bps: DEFAULT_KELLY_FRACTION_BPS as u16,
+        max_liquidity: 0,
+        allowlist_only: false,
+        randomness: Randomness::SlotHashes,
+        keeper_fee_bps: 0,
+        lockup_slots: 0,
+        early_exit_fee_bps: 0,
+        epoch_start: EpochStart::default(),
+    };
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+
+    info!("Token pool created");
+    Ok(())
+}
+
+/// Escrow the commit's wager from `player_token_account` into the pool vault
+/// and write the committed game, paid to `payout_account` on settle and
+/// recording the optional referrer token account. The game is tied to the
+/// pool's randomness source in `randomness_account`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn escrow_and_commit<'a>(
+    program_id: &Pubkey,
+    commit: &Commit,
+    player_account: &AccountInfo<'a>,
+    game_account: &AccountInfo<'a>,
+    clock_account: &AccountInfo<'a>,
+    randomness_account: &AccountInfo<'a>,
+    pool_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    pool: &mut Pool,
+    player_token_account: &AccountInfo<'a>,
+    payout_account: &Pubkey,
+    referrer_account: Option<&AccountInfo<'a>>,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    pause::assert_not_paused(pool.paused, PAUSE_COMMITS)?;
+    let referrer = fees::commit_referrer(referrer_account, player_token_account.key, payout_account)?;
+    if let Some(referrer_account) = referrer_account {
+        // Referrer fees are paid in the pool's mint
+        if TokenAccount::unpack(&referrer_account.data.borrow())?.mint != pool.mint {
+            info!("Referrer token account is not for the pool mint");
+            return Err(RollError::IncorrectAddress.into());
+        }
+    }
+
+    let total_wager = commit.total_wager().ok_or(RollError::InvalidRollCount)?;
+    let exposure = commit
+        .worst_case_profit(total_wager)
+        .ok_or(RollError::InvalidTarget)?;
+
+    let mut data = game_account.try_borrow_mut_data()?;
+    if Game::unpack(&data)?.status == GameStatus::Committed {
+        info!("Game is already committed");
+        return Err(RollError::GameAlreadyCommitted.into());
+    }
+    randomness::source(
+        program_id,
+        pool_account.key,
+        pool.randomness,
+        clock_account,
+        &RandomnessAccounts::commit(randomness_account),
+    )?
+    .commit(game_account.key, commit.secret_hash.as_ref())?;
+
+    withdraw_queue::open_pool_epoch(pool, vault_account, None, clock_account)?;
+    let odds = commit.odds().ok_or(RollError::InvalidTarget)?;
+    pool.reserved = settle::reserve(
+        pool.reserved,
+        liquidity(pool, vault_account)?,
+        total_wager,
+        odds,
+        exposure,
+        &pool.risk_config(),
+    )?;
+
+    info!("Escrowing wager:");
+    info!(&total_wager.to_string());
+    invoke(
+        &spl_token::instruction::transfer(
+            &spl_token::ID,
+            player_token_account.key,
+            vault_account.key,
+            player_account.key,
+            &[],
+            total_wager,
+        )?,
+        &[
+            player_token_account.clone(),
+            vault_account.clone(),
+            player_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    pool.escrowed = pool
+        .escrowed
+        .checked_add(total_wager)
+        .ok_or(RollError::InvalidAmount)?;
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+
+    if let Some(bet) = &commit.roulette {
+        bet.pack(&mut data)?;
+    }
+    let current_slot = Clock::from_account_info(clock_account)?.slot;
+    let game = commit.to_game(
+        hash_value(commit.reveal_number),
+        current_slot,
+        *pool_account.key,
+        *payout_account,
+        exposure,
+        referrer,
+    );
+    game.pack(&mut data)
+}
+
+/// 5 - escrow the wager in the pool vault and commit the bet
+///
+/// Data is laid out as `Commit`, with a roll count and wager or a roulette
+/// layout. Accounts: player (signer), game, clock sysvar, slot hashes sysvar
+/// or the pool's house chain or VRF queue, pool, vault, player token account,
+/// token program, referrer token account (optional)
+pub fn process_commit(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let commit = Commit::unpack(data)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let player_account = next_account_info(accounts_iter)?;
+    let game_account = next_account_info(accounts_iter)?;
+    let sysvar_account = next_account_info(accounts_iter)?;
+    let randomness_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let player_token_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let referrer_account = next_account_info(accounts_iter).ok();
+
+    assert_signer(player_account)?;
+    assert_program_account(game_account, program_id)?;
+    assert_sysvar(sysvar_account, &clock::id())?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_writable(player_token_account)?;
+    assert_distinct(&[game_account, pool_account, vault_account, player_token_account])?;
+
+    let mut pool = load_pool(program_id, pool_account, vault_account)?;
+    escrow_and_commit(
+        program_id,
+        &commit,
+        player_account,
+        game_account,
+        sysvar_account,
+        randomness_account,
+        pool_account,
+        vault_account,
+        &mut pool,
+        player_token_account,
+        player_token_account.key,
+        referrer_account,
+        token_program,
+    )
+}
+
+/// Roll, refund or forfeit a committed token game, release its escrow, and
+/// take the fees and record a rolled game in the player stats and roll
+/// history, returning the pool and the amount owed to the game's payout
+/// account. A forfeited stake stays in the pool. The caller moves the payout out of the vault.
+/// The pool's randomness source rolls the game, house-seeded pools on the
+/// seed in `seed_data`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn settle_game<'a>(
+    program_id: &Pubkey,
+    reveal_number: Option<u32>,
+    seed_data: Option<&[u8]>,
+    game_account: &AccountInfo<'a>,
+    clock_account: &AccountInfo<'a>,
+    randomness_accounts: &RandomnessAccounts<'a, '_>,
+    pool_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    player_token_account: &AccountInfo<'a>,
+    player_stats_account: &AccountInfo<'a>,
+    roll_history_account: &AccountInfo<'a>,
+    fee_accounts: &FeeAccounts<'a, '_>,
+    token_program: &AccountInfo<'a>,
+) -> Result<(Pool, u64), ProgramError> {
+    assert_program_account(game_account, program_id)?;
+    assert_sysvar(clock_account, &clock::id())?;
+    assert_writable(player_token_account)?;
+    assert_distinct(&[game_account, pool_account, vault_account, player_token_account])?;
+
+    let mut pool = load_pool(program_id, pool_account, vault_account)?;
+    withdraw_queue::open_pool_epoch(&mut pool, vault_account, None, clock_account)?;
+
+    let mut game_data = game_account.try_borrow_mut_data()?;
+    let mut game = Game::unpack(&game_data)?;
+    if game.status != GameStatus::Committed {
+        info!("Game is not committed");
+        return Err(RollError::GameNotCommitted.into());
+    }
+    assert_key(pool_account, &game.pool)?;
+    assert_key(player_token_account, &game.player)?;
+
+    let stake = settle::stake(&game, &game_data)?;
+    pool.escrowed = pool.escrowed.saturating_sub(stake);
+    pool.reserved = pool.reserved.saturating_sub(game.reserved);
+    game.status = GameStatus::Settled;
+
+    // Randomness is used up even when the game is not rolled, so house
+    // seeds and VRF outputs move on to the next game
+    let roll = randomness::source(
+        program_id,
+        pool_account.key,
+        pool.randomness,
+        clock_account,
+        randomness_accounts,
+    )?
+    .roll_seed(game_account.key, &game, reveal_number, seed_data)?;
+    // Paused settles refund pending bets instead of rolling them
+    let roll = if pause::is_paused(pool.paused, PAUSE_SETTLES) {
+        info!("Settles are paused, returning funds");
+        Roll::Refund
+    } else {
+        roll
+    };
+    // Games that are not rolled pay no fees
+    let (payout, rolled) = match roll {
+        Roll::Refund => (stake, false),
+        Roll::Forfeit => {
+            info!("Wager is forfeited to the pool");
+            (0, false)
+        }
+        Roll::Withheld => {
+            // The worst case profit was reserved at commit
+            game.payout = stake.saturating_add(game.reserved);
+            info!("House seed was withheld, paying the reserved winnings");
+            (game.payout, false)
+        }
+        Roll::Seed(seed) => {
+            let Outcome { winnings, payout, .. } = settle::roll(&mut game, &game_data, seed, stake)?;
+
+            if winnings > game.reserved {
+                info!("Potential profit exceeds the exposure reserved at commit");
+                game.payout = stake;
+                (stake, false)
+            } else if payout == 0 {
+                info!("You LOSE! Wager stays in the pool");
+                (0, true)
+            } else {
+                info!("You WIN! Tokens go to player");
+                info!(&payout.to_string());
+                (payout, true)
+            }
+        }
+    };
+
+    game.pack(&mut game_data)?;
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+    if rolled {
+        player_stats::update(program_id, &game, stake, payout, player_stats_account)?;
+        roll_history::append(program_id, pool_account.key, &game, stake, roll_history_account)?;
+        fees::take_token_fees(
+            program_id,
+            &game,
+            stake,
+            &pool,
+            pool_account,
+            vault_account,
+            token_program,
+            fee_accounts,
+        )?;
+    }
+    Ok((pool, payout))
+}
+
+/// 6 - settle a token game, paying winnings from the pool vault
+///
+/// Only the player, who owns the player token account, may settle with a
+/// reveal. Games of a house-seeded pool roll on the house seed alone, so
+/// anyone may settle them with the signed seed, or without one once the
+/// house posted it, paying the player. Data: [1] reveal number, [2..34]
+/// house seed (house-seeded pools only) or the player's secret (VRF pools
+/// only). Accounts: player or, for house-seeded pools, anyone (signer), game, clock sysvar, slot hashes sysvar or the pool's VRF queue
+/// or house chain, the latter followed by the instructions sysvar, pool,
+/// vault, player token account, token program, player stats, roll history, price oracle, fee
+/// vault, fee token account, referrer token account (only for games with a
+/// referrer)
+pub fn process_settle(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let reveal_number = *data.get(1).ok_or(ProgramError::InvalidInstructionData)? as u32;
+    let house_seed = data.get(2..34);
+
+    let accounts_iter = &mut accounts.iter();
+    let player_account = next_account_info(accounts_iter)?;
+    let game_account = next_account_info(accounts_iter)?;
+    let sysvar_account = next_account_info(accounts_iter)?;
+    let randomness = RandomnessAccounts::next(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let player_token_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let player_stats_account = next_account_info(accounts_iter)?;
+    let roll_history_account = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+    let fee_accounts = FeeAccounts::next(accounts_iter, TreasuryKind::TokenPool)?;
+
+    assert_signer(player_account)?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_program_account(pool_account, program_id)?;
+    if Pool::unpack_initialized(&pool_account.data.borrow())?.randomness != Randomness::HouseChain {
+        load_token_account(player_token_account, player_account.key)?;
+    }
+    let (pool, payout) = settle_game(
+        program_id,
+        Some(reveal_number),
+        house_seed,
+        game_account,
+        sysvar_account,
+        &randomness,
+        pool_account,
+        vault_account,
+        player_token_account,
+        player_stats_account,
+        roll_history_account,
+        &fee_accounts,
+        token_program,
+    )?;
+    transfer_from_vault(&pool, pool_account, vault_account, player_token_account, token_program, payout)?;
+    let treasury_liquidity = liquidity(&pool, vault_account)?;
+    oracle::update(program_id, pool_account.key, treasury_liquidity, None, sysvar_account, oracle_account)
+}
+
+/// 7 - deposit tokens into the pool and mint treasury tokens
+///
+/// Deposits over the pool's liquidity cap are filled up to it, and start the
+/// depositor's lockup. Data: [1..9] token amount. Accounts: depositor
+/// (signer), pool, vault, depositor token account, depositor treasury token
+/// account held by the depositor, treasury token mint, token
+/// program, clock sysvar, price oracle, LP's deposit record, rent sysvar,
+/// system program, then the depositor's allowlist entry for allowlisted pools
+pub fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 9 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = LittleEndian::read_u64(&data[1..9]);
+
+    let accounts_iter = &mut accounts.iter();
+    let depositor_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let vault_account = next_account_info(accounts_iter)?;
+    let depositor_token_account = next_account_info(accounts_iter)?;
+    let depositor_lp_account = next_account_info(accounts_iter)?;
+    let lp_mint_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+    let deposit_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+    let allowlist_account = next_account_info(accounts_iter).ok();
+
+    assert_signer(depositor_account)?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::ID)?;
+    assert_distinct(&[
+        pool_account,
+        vault_account,
+        depositor_token_account,
+        depositor_lp_account,
+        lp_mint_account,
+    ])?;
+
+    let mut pool = load_pool(program_id, pool_account, vault_account)?;
+    assert_key(lp_mint_account, &pool.lp_mint)?;
+    pause::assert_not_paused(pool.paused, PAUSE_DEPOSITS)?;
+    // Only the depositor's own lockup is recorded
+    let previous_balance = load_token_account(depositor_lp_account, depositor_account.key)?.amount;
+    deposit_limits::assert_allowed(
+        program_id,
+        pool_account.key,
+        pool.allowlist_only,
+        depositor_account.key,
+        allowlist_account,
+    )?;
+
+    if amount == 0 {
+        info!("Deposit amount must be > 0");
+        return Err(RollError::InvalidAmount.into());
+    }
+
+    let supply = Mint::unpack(&lp_mint_account.data.borrow())?.supply;
+    withdraw_queue::open_pool_epoch(&mut pool, vault_account, Some(supply), clock_account)?;
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+    let treasury_liquidity = liquidity(&pool, vault_account)?;
+    let amount = deposit_limits::capped_amount(amount, treasury_liquidity, pool.max_liquidity);
+    if amount == 0 {
+        info!("Pool is at its liquidity cap");
+        return Err(RollError::DepositCapExceeded.into());
+    }
+
+    // Same pro-rating as the lamport treasury
+    let lp_amount = shares::deposit_tokens_out(amount, treasury_liquidity, supply);
+    info!("Depositing tokens, minting treasury tokens:");
+    info!(&amount.to_string());
+    info!(&lp_amount.to_string());
+
+    invoke(
+        &spl_token::instruction::transfer(
+            &spl_token::ID,
+            depositor_token_account.key,
+            vault_account.key,
+            depositor_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            depositor_token_account.clone(),
+            vault_account.clone(),
+            depositor_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            &spl_token::ID,
+            lp_mint_account.key,
+            depositor_lp_account.key,
+            pool_account.key,
+            &[],
+            lp_amount,
+        )?,
+        &[
+            lp_mint_account.clone(),
+            depositor_lp_account.clone(),
+            pool_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[POOL_SEED, pool.mint.as_ref(), &[pool.bump_seed]]],
+    )?;
+
+    // The deposit starts the depositor's lockup, weighted against the
+    // treasury tokens they already held
+    lockup::record_deposit(
+        program_id,
+        pool_account.key,
+        depositor_account.key,
+        previous_balance,
+        lp_amount,
+        Clock::from_account_info(clock_account)?.slot,
+        pool.lockup_slots,
+        depositor_account,
+        deposit_account,
+        system_program_account,
+        &Rent::from_account_info(rent_account)?,
+    )?;
+
+    oracle::update(
+        program_id,
+        pool_account.key,
+        liquidity(&pool, vault_account)?,
+        Some(supply.saturating_add(lp_amount)),
+        clock_account,
+        oracle_account,
+    )?;
+
+    info!("Deposit successful");
+    Ok(())
+}
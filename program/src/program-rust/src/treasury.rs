@@ -0,0 +1,87 @@
+//! Bookkeeping for the lamport treasury
+//!
+//! The lamport treasury is a plain program owned account, so its reserved
+//! exposure lives in a separate state PDA `["treasury", treasury]`.
+
+use crate::{
+    risk::DEFAULT_KELLY_FRACTION_BPS,
+    state::{EpochStart, Treasury, TREASURY_SEED},
+    token_pool::create_pda_account,
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent, rent::Rent, Sysvar},
+};
+
+/// Load the state of `treasury_account`, checking its address
+pub fn load_treasury(
+    program_id: &Pubkey,
+    treasury_account: &AccountInfo,
+    state_account: &AccountInfo,
+) -> Result<Treasury, ProgramError> {
+    assert_program_account(state_account, program_id)?;
+    let treasury = Treasury::unpack(&state_account.data.borrow())?;
+    if !treasury.is_initialized {
+        info!("SolanaRoll treasury state is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    assert_key(treasury_account, &treasury.treasury)?;
+    Ok(treasury)
+}
+
+/// 11 - create the state PDA of a lamport treasury
+///
+/// The treasury account must sign, and the payer becomes the treasury's
+/// authority. Accounts: payer (signer), treasury (signer), treasury state,
+/// rent sysvar, system program
+pub fn process_init_treasury(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let state_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_program_account(treasury_account, program_id)?;
+    assert_signer(treasury_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let (state_address, bump_seed) = Treasury::find_address(program_id, treasury_account.key);
+    assert_key(state_account, &state_address)?;
+
+    info!("Creating treasury state");
+    create_pda_account(
+        payer_account,
+        state_account,
+        system_program_account,
+        program_id,
+        Treasury::LEN,
+        &Rent::from_account_info(rent_account)?,
+        &[TREASURY_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+
+    let treasury = Treasury {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury_account.key,
+        reserved: 0,
+        authority: *payer_account.key,
+        paused: 0,
+        kelly_fraction_bps: DEFAULT_KELLY_FRACTION_BPS as u16,
+        lockup_slots: 0,
+        early_exit_fee_bps: 0,
+        max_liquidity: 0,
+        allowlist_only: false,
+        keeper_fee_bps: 0,
+        epoch_start: EpochStart::default(),
+    };
+    treasury.pack(&mut state_account.data.borrow_mut())
+}
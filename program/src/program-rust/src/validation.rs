@@ -0,0 +1,140 @@
+//! Account validation shared by every instruction handler
+
+use crate::error::RollError;
+use solana_sdk::{
+    account_info::AccountInfo, entrypoint_deprecated::ProgramResult, info, pubkey::Pubkey,
+};
+
+/// Fails if any two of the given accounts share the same address.
+/// Aliased accounts would let lamport arithmetic credit and debit one balance.
+pub fn assert_distinct(accounts: &[&AccountInfo]) -> ProgramResult {
+    for (i, a) in accounts.iter().enumerate() {
+        for b in accounts[i + 1..].iter() {
+            if a.key == b.key {
+                info!("SolanaRoll account passed more than once:");
+                info!(&a.key.to_string());
+                return Err(RollError::DuplicateAccount.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless the account is owned by `owner`.
+pub fn assert_owned_by(account: &AccountInfo, owner: &Pubkey) -> ProgramResult {
+    if account.owner != owner {
+        info!("SolanaRoll account has an unexpected owner:");
+        info!(&account.key.to_string());
+        return Err(RollError::IncorrectOwner.into());
+    }
+    Ok(())
+}
+
+/// Fails unless the account signed the transaction.
+pub fn assert_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        info!("SolanaRoll account must be a signer:");
+        info!(&account.key.to_string());
+        return Err(RollError::MissingSigner.into());
+    }
+    Ok(())
+}
+
+/// Fails unless the account was passed as writable.
+pub fn assert_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        info!("SolanaRoll account must be writable:");
+        info!(&account.key.to_string());
+        return Err(RollError::NotWritable.into());
+    }
+    Ok(())
+}
+
+/// Fails unless the account is the sysvar with address `id`.
+/// Sysvars read as raw data (e.g. slot hashes) are not checked by the runtime.
+pub fn assert_sysvar(account: &AccountInfo, id: &Pubkey) -> ProgramResult {
+    if account.key != id {
+        info!("SolanaRoll account is not the expected sysvar:");
+        info!(&account.key.to_string());
+        return Err(RollError::InvalidSysvar.into());
+    }
+    Ok(())
+}
+
+/// Fails unless the account lives at the `expected` address.
+pub fn assert_key(account: &AccountInfo, expected: &Pubkey) -> ProgramResult {
+    if account.key != expected {
+        info!("SolanaRoll account address is incorrect:");
+        info!(&account.key.to_string());
+        return Err(RollError::IncorrectAddress.into());
+    }
+    Ok(())
+}
+
+/// Program owned account the instruction moves lamports or data through.
+pub fn assert_program_account(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    assert_owned_by(account, program_id)?;
+    assert_writable(account)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::{clock::Epoch, program_error::ProgramError, sysvar::clock};
+
+    fn check(result: ProgramResult, error: RollError) {
+        assert_eq!(result, Err(ProgramError::from(error)));
+    }
+
+    #[test]
+    fn test_signer_and_writable() {
+        let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, vec![]);
+        let signed = AccountInfo::new(&key, true, false, &mut lamports, &mut data, &owner, false, Epoch::default());
+        assert_eq!(assert_signer(&signed), Ok(()));
+        check(assert_writable(&signed), RollError::NotWritable);
+
+        let (mut lamports, mut data) = (0, vec![]);
+        let writable = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, Epoch::default());
+        assert_eq!(assert_writable(&writable), Ok(()));
+        check(assert_signer(&writable), RollError::MissingSigner);
+    }
+
+    #[test]
+    fn test_owner_and_key() {
+        let (key, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, vec![]);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, Epoch::default());
+        assert_eq!(assert_owned_by(&account, &program_id), Ok(()));
+        check(assert_owned_by(&account, &Pubkey::new_unique()), RollError::IncorrectOwner);
+        assert_eq!(assert_key(&account, &key), Ok(()));
+        check(assert_key(&account, &program_id), RollError::IncorrectAddress);
+
+        // Program accounts must be owned and writable
+        check(assert_program_account(&account, &program_id), RollError::NotWritable);
+        check(assert_program_account(&account, &key), RollError::IncorrectOwner);
+    }
+
+    #[test]
+    fn test_sysvar() {
+        let (clock_id, owner) = (clock::id(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, vec![]);
+        let account = AccountInfo::new(&clock_id, false, false, &mut lamports, &mut data, &owner, false, Epoch::default());
+        assert_eq!(assert_sysvar(&account, &clock::id()), Ok(()));
+        check(assert_sysvar(&account, &Pubkey::new_unique()), RollError::InvalidSysvar);
+    }
+
+    #[test]
+    fn test_distinct() {
+        let (a, b, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut a_lamports, mut a_data) = (0, vec![]);
+        let (mut b_lamports, mut b_data) = (0, vec![]);
+        let (mut c_lamports, mut c_data) = (0, vec![]);
+        let first = AccountInfo::new(&a, false, true, &mut a_lamports, &mut a_data, &owner, false, Epoch::default());
+        let second = AccountInfo::new(&b, false, true, &mut b_lamports, &mut b_data, &owner, false, Epoch::default());
+        let alias = AccountInfo::new(&a, false, true, &mut c_lamports, &mut c_data, &owner, false, Epoch::default());
+        assert_eq!(assert_distinct(&[&first, &second]), Ok(()));
+        assert_eq!(assert_distinct(&[]), Ok(()));
+        check(assert_distinct(&[&first, &second, &alias]), RollError::DuplicateAccount);
+    }
+}
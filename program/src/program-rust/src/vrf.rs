@@ -0,0 +1,314 @@
+//! VRF randomness for token pools
+//!
+//! A pool can take its randomness from a VRF worker it authorizes: each
+//! commit queues a request for the game in the pool's VRF queue PDA
+//! `["vrf", pool]`, holding the hash of a 32 byte secret the player picked,
+//! the worker evaluates its VRF over the game and writes the output with
+//! `FulfillVrf`, and the player's settle reveals the secret and rolls on
+//! `hash(output || secret)` mixed with the player's hashed reveal. The worker
+//! never sees the secret, so it cannot steer a roll whatever output it
+//! writes, and the player cannot change the secret after seeing the output.
+//! The proof rides in the fulfill instruction data for anyone to check
+//! against the worker's VRF key off chain.
+//!
+//! A game cannot settle before its output is written. Once the crank delay
+//! has passed, a game whose output was never written is refunded, and one
+//! whose player never revealed the secret is forfeited to the pool.
+
+use crate::{
+    crank,
+    error::RollError,
+    randomness::{self, RandomnessSource, Roll},
+    state::{Game, Pool, Randomness, VrfQueue, VrfRequest, VRF_SEED},
+    token_pool::create_pda_account,
+    validation::{assert_key, assert_program_account, assert_signer, assert_sysvar},
+};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    hash::{hash, hashv, Hash},
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock::Clock, rent, rent::Rent, Sysvar},
+};
+
+pub(crate) fn load_queue(program_id: &Pubkey, pool: &Pubkey, queue_account: &AccountInfo) -> Result<VrfQueue, ProgramError> {
+    let (address, _) = VrfQueue::find_address(program_id, pool);
+    assert_key(queue_account, &address)?;
+    assert_program_account(queue_account, program_id)?;
+    let queue = VrfQueue::unpack(&queue_account.data.borrow())?;
+    if !queue.is_initialized {
+        info!("SolanaRoll VRF queue is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(queue)
+}
+
+/// A VRF pool's request queue
+pub struct VrfSource<'a, 'b> {
+    pub program_id: &'b Pubkey,
+    pub pool: &'b Pubkey,
+    pub clock: &'b AccountInfo<'a>,
+    pub queue: &'b AccountInfo<'a>,
+}
+
+impl RandomnessSource for VrfSource<'_, '_> {
+    /// Queue a request for `game`'s output, holding the hash of the
+    /// player's secret
+    fn commit(&self, game: &Pubkey, secret_hash: Option<&[u8; 32]>) -> ProgramResult {
+        let secret_hash = secret_hash.ok_or_else(|| {
+            info!("SolanaRoll VRF commits need the hash of the player's secret");
+            ProgramError::InvalidInstructionData
+        })?;
+        let mut queue = load_queue(self.program_id, self.pool, self.queue)?;
+        let slot = queue
+            .find(&Pubkey::default())
+            .ok_or(RollError::VrfQueueFull)?;
+        queue.requests[slot] = VrfRequest {
+            game: *game,
+            secret_hash: *secret_hash,
+            ..VrfRequest::default()
+        };
+        queue.pack(&mut self.queue.data.borrow_mut())?;
+        info!(&format!("VRF_REQUEST pool={} game={}", self.pool, game));
+        Ok(())
+    }
+
+    /// Take the request of `game_key` out of the queue, rolling on its
+    /// written output and the player's secret in `seed_data`
+    fn roll_seed(
+        &self,
+        game_key: &Pubkey,
+        game: &Game,
+        _reveal_number: Option<u32>,
+        seed_data: Option<&[u8]>,
+    ) -> Result<Roll, ProgramError> {
+        let mut queue = load_queue(self.program_id, self.pool, self.queue)?;
+        let slot = queue.find(game_key).ok_or_else(|| {
+            info!("SolanaRoll game has no VRF request");
+            RollError::VrfNotFulfilled
+        })?;
+        let request = queue.requests[slot];
+        let expired = crank::is_expired(game, Clock::from_account_info(self.clock)?.slot);
+
+        let roll = match seed_data {
+            _ if !request.fulfilled => {
+                if !expired {
+                    info!("SolanaRoll VRF output is not written yet");
+                    return Err(RollError::VrfNotFulfilled.into());
+                }
+                info!("SolanaRoll VRF output was never written, returning funds");
+                Roll::Refund
+            }
+            Some(secret) => {
+                if hash(secret).to_bytes() != request.secret_hash {
+                    info!("SolanaRoll secret does not match the one committed");
+                    return Err(RollError::InvalidReveal.into());
+                }
+                let randomness = hashv(&[&request.output, secret]).to_bytes();
+                Roll::Seed(randomness::mix(game, &randomness))
+            }
+            None if expired => {
+                info!("SolanaRoll secret was never revealed, wager is forfeited");
+                Roll::Forfeit
+            }
+            None => {
+                info!("SolanaRoll VRF settles need the player's secret");
+                return Err(ProgramError::InvalidInstructionData);
+            }
+        };
+        queue.requests[slot] = VrfRequest::default();
+        queue.pack(&mut self.queue.data.borrow_mut())?;
+        Ok(roll)
+    }
+}
+
+/// 31 - create a token pool's VRF queue and roll the pool on it
+///
+/// Also replaces the pool's worker. The pool must have no open bets. Data:
+/// [1..33] worker. Accounts: payer (signer), pool authority (signer), pool,
+/// VRF queue, rent sysvar, system program
+pub fn process_init_vrf(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 33 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let worker = Pubkey::new(&data[1..33]);
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let queue_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_signer(authority_account)?;
+    assert_program_account(pool_account, program_id)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+
+    let mut pool = Pool::unpack_initialized(&pool_account.data.borrow())?;
+    assert_key(authority_account, &pool.authority)?;
+    // Open bets were committed without a request
+    if pool.escrowed != 0 {
+        info!("SolanaRoll pool has open bets");
+        return Err(RollError::OpenBets.into());
+    }
+
+    let (queue_address, bump_seed) = VrfQueue::find_address(program_id, pool_account.key);
+    assert_key(queue_account, &queue_address)?;
+    if queue_account.owner != program_id {
+        info!("Creating VRF queue");
+        create_pda_account(
+            payer_account,
+            queue_account,
+            system_program_account,
+            program_id,
+            VrfQueue::LEN,
+            &Rent::from_account_info(rent_account)?,
+            &[VRF_SEED, pool_account.key.as_ref(), &[bump_seed]],
+        )?;
+    }
+    let queue = VrfQueue {
+        is_initialized: true,
+        bump_seed,
+        pool: *pool_account.key,
+        worker,
+        ..VrfQueue::default()
+    };
+    queue.pack(&mut queue_account.data.borrow_mut())?;
+
+    pool.randomness = Randomness::Vrf;
+    pool.pack(&mut pool_account.data.borrow_mut())?;
+
+    info!(&format!("VRF_QUEUE pool={} worker={}", pool_account.key, worker));
+    Ok(())
+}
+
+/// 32 - write the VRF output of a committed game
+///
+/// Data: [1..33] game, [33..65] VRF output, then the VRF proof. Accounts:
+/// worker (signer), pool, VRF queue
+pub fn process_fulfill_vrf(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 65 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let game = Pubkey::new(&data[1..33]);
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&data[33..65]);
+
+    let accounts_iter = &mut accounts.iter();
+    let worker_account = next_account_info(accounts_iter)?;
+    let pool_account = next_account_info(accounts_iter)?;
+    let queue_account = next_account_info(accounts_iter)?;
+
+    assert_signer(worker_account)?;
+    let mut queue = load_queue(program_id, pool_account.key, queue_account)?;
+    assert_key(worker_account, &queue.worker)?;
+
+    let slot = match queue.find(&game) {
+        Some(slot) if !queue.requests[slot].fulfilled => slot,
+        _ => {
+            info!("SolanaRoll game has no open VRF request");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+    queue.requests[slot].fulfilled = true;
+    queue.requests[slot].output = output;
+    queue.pack(&mut queue_account.data.borrow_mut())?;
+
+    info!(&format!(
+        "VRF_FULFILL pool={} game={} output={}",
+        pool_account.key,
+        game,
+        Hash::new(&output)
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{crank::CRANK_DELAY_SLOTS, state::GameStatus};
+    use solana_sdk::clock::Epoch;
+
+    #[test]
+    fn test_roll_on_secret() {
+        let program_id = Pubkey::new_unique();
+        let (pool, game_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (queue_key, _) = VrfQueue::find_address(&program_id, &pool);
+        let clock_key = solana_sdk::sysvar::clock::id();
+        let mut game = Game::unpack(&[0u8; Game::LEN]).unwrap();
+        game.status = GameStatus::Committed;
+        let secret = [3u8; 32];
+
+        let mut queue_data = vec![0u8; VrfQueue::LEN];
+        VrfQueue {
+            is_initialized: true,
+            pool,
+            ..VrfQueue::default()
+        }
+        .pack(&mut queue_data)
+        .unwrap();
+        let mut clock_data = vec![0u8; Clock::size_of()];
+        let (mut queue_lamports, mut clock_lamports) = (0, 0);
+        let queue = AccountInfo::new(&queue_key, false, true, &mut queue_lamports, &mut queue_data, &program_id, false, Epoch::default());
+        let mut clock = AccountInfo::new(&clock_key, false, false, &mut clock_lamports, &mut clock_data, &clock_key, false, Epoch::default());
+        Clock::default().to_account_info(&mut clock).unwrap();
+        let source = VrfSource {
+            program_id: &program_id,
+            pool: &pool,
+            clock: &clock,
+            queue: &queue,
+        };
+
+        // Commits need the secret's hash
+        assert_eq!(source.commit(&game_key, None), Err(ProgramError::InvalidInstructionData));
+        source.commit(&game_key, Some(&hash(&secret).to_bytes())).unwrap();
+        assert_eq!(
+            source.roll_seed(&game_key, &game, None, Some(&secret)),
+            Err(RollError::VrfNotFulfilled.into())
+        );
+
+        let mut requests = load_queue(&program_id, &pool, &queue).unwrap();
+        requests.requests[0].fulfilled = true;
+        requests.requests[0].output = [9u8; 32];
+        requests.pack(&mut queue.data.borrow_mut()).unwrap();
+
+        // A secret other than the committed one fails, the worker's output
+        // alone does not settle
+        assert_eq!(
+            source.roll_seed(&game_key, &game, None, Some(&[4u8; 32])),
+            Err(RollError::InvalidReveal.into())
+        );
+        assert_eq!(
+            source.roll_seed(&game_key, &game, None, None),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        let randomness = hashv(&[&[9u8; 32], &secret]).to_bytes();
+        assert_eq!(
+            source.roll_seed(&game_key, &game, None, Some(&secret)),
+            Ok(Roll::Seed(randomness::mix(&game, &randomness)))
+        );
+        assert_eq!(load_queue(&program_id, &pool, &queue).unwrap().find(&game_key), None);
+
+        // Past the crank delay an unwritten output refunds and an unrevealed
+        // secret forfeits
+        Clock {
+            slot: CRANK_DELAY_SLOTS + 1,
+            ..Clock::default()
+        }
+        .to_account_info(&mut clock.clone())
+        .unwrap();
+        source.commit(&game_key, Some(&hash(&secret).to_bytes())).unwrap();
+        assert_eq!(source.roll_seed(&game_key, &game, None, None), Ok(Roll::Refund));
+        source.commit(&game_key, Some(&hash(&secret).to_bytes())).unwrap();
+        let mut requests = load_queue(&program_id, &pool, &queue).unwrap();
+        requests.requests[0].fulfilled = true;
+        requests.pack(&mut queue.data.borrow_mut()).unwrap();
+        assert_eq!(source.roll_seed(&game_key, &game, None, None), Ok(Roll::Forfeit));
+    }
+}
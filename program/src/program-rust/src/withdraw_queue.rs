@@ -0,0 +1,620 @@
+//! Two-phase withdrawals from a treasury
+//!
+//! A withdrawal that pays out immediately lets an LP who sees a large
+//! winning bet pending withdraw before it settles, leaving the loss to the
+//! remaining LPs. Instead, `RequestWithdraw` locks treasury tokens in the
+//! queue's escrow, and `ExecuteWithdraw` burns them at the price of a later
+//! epoch boundary. The lamport treasury and each token pool have their own
+//! queue `["withdraw_queue", treasury]`.
+//!
+//! Nothing runs at an epoch boundary, so every instruction changing a
+//! treasury's liquidity, exposure or supply first records the treasury's
+//! value in its state or pool if it is the first to do so in the epoch
+//! (`open_epoch`). The first withdrawal instruction of each epoch copies
+//! that value into the queue's snapshot. The value is the unreserved
+//! liquidity, so open bets' exposure is left to settle.
+
+use crate::{
+    error::RollError,
+    instruction::TreasuryKind,
+    lockup, oracle,
+    pause::{self, PAUSE_WITHDRAWALS},
+    settle, shares,
+    state::{
+        EpochStart, Pool, Treasury, WithdrawQueue, WithdrawRequest, WITHDRAW_ESCROW_SEED,
+        WITHDRAW_QUEUE_SEED, WITHDRAW_REQUEST_SEED,
+    },
+    token_pool::{create_pda_account, liquidity, load_pool, load_token_account, transfer_from_vault},
+    treasury::load_treasury,
+    validation::{
+        assert_distinct, assert_key, assert_program_account, assert_signer, assert_sysvar,
+        assert_writable,
+    },
+};
+use byteorder::{ByteOrder, LittleEndian};
+use solana_sdk::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint_deprecated::ProgramResult,
+    info,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, clock::Clock, rent, rent::Rent, Sysvar},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+/// Record a treasury holding `liquidity` unreserved and, if the instruction
+/// read it, `supply` treasury tokens as the value `epoch` started at,
+/// unless an earlier instruction of the epoch did. Supply only changes in
+/// instructions reading the mint, so the first of them records it.
+pub fn open_epoch(start: &mut EpochStart, epoch: u64, liquidity: u64, supply: Option<u64>) {
+    if epoch > start.epoch {
+        *start = EpochStart {
+            epoch,
+            liquidity,
+            supply: supply.unwrap_or(0),
+            has_supply: supply.is_some(),
+        };
+    } else if let (false, Some(supply)) = (start.has_supply, supply) {
+        start.supply = supply;
+        start.has_supply = true;
+    }
+}
+
+/// `open_epoch` for the lamport treasury holding `lamports`, before the
+/// instruction changes it
+pub fn open_treasury_epoch(
+    treasury: &mut Treasury,
+    lamports: u64,
+    supply: Option<u64>,
+    clock_account: &AccountInfo,
+) -> ProgramResult {
+    let epoch = Clock::from_account_info(clock_account)?.epoch;
+    let unreserved = lamports.saturating_sub(treasury.reserved);
+    open_epoch(&mut treasury.epoch_start, epoch, unreserved, supply);
+    Ok(())
+}
+
+/// `open_epoch` for a token pool with its vault, before the instruction
+/// changes it
+pub fn open_pool_epoch(
+    pool: &mut Pool,
+    vault_account: &AccountInfo,
+    supply: Option<u64>,
+    clock_account: &AccountInfo,
+) -> ProgramResult {
+    let epoch = Clock::from_account_info(clock_account)?.epoch;
+    let unreserved = liquidity(pool, vault_account)?.saturating_sub(pool.reserved);
+    open_epoch(&mut pool.epoch_start, epoch, unreserved, supply);
+    Ok(())
+}
+
+/// Take the snapshot of `epoch` unless the queue already has it. Returns
+/// whether a new snapshot was taken.
+pub fn snapshot(queue: &mut WithdrawQueue, epoch: u64, liquidity: u64, supply: u64) -> bool {
+    if epoch <= queue.epoch {
+        return false;
+    }
+    queue.epoch = epoch;
+    queue.liquidity = liquidity;
+    queue.supply = supply;
+    true
+}
+
+/// Whether a request made in `request_epoch` can be paid out
+pub fn is_due(queue: &WithdrawQueue, request_epoch: u64) -> bool {
+    request_epoch < queue.epoch
+}
+
+/// Load the queue of `treasury_account`, checking its address and mint
+pub(crate) fn load_queue(
+    program_id: &Pubkey,
+    treasury_account: &AccountInfo,
+    queue_account: &AccountInfo,
+    mint_account: &AccountInfo,
+) -> Result<WithdrawQueue, ProgramError> {
+    let (address, _) = WithdrawQueue::find_address(program_id, treasury_account.key);
+    assert_key(queue_account, &address)?;
+    assert_program_account(queue_account, program_id)?;
+    let queue = WithdrawQueue::unpack(&queue_account.data.borrow())?;
+    if !queue.is_initialized {
+        info!("SolanaRoll withdraw queue is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    assert_key(mint_account, &queue.mint)?;
+    Ok(queue)
+}
+
+/// The treasury a queue pays out of: the lamport treasury, with its state
+/// account, or a token pool, with its vault
+enum Backing {
+    Lamports(Treasury),
+    TokenPool(Pool),
+}
+
+impl Backing {
+    /// Load the treasury of `kind` in `treasury_account`, its state or vault
+    /// in `state_account`
+    fn load(
+        program_id: &Pubkey,
+        kind: TreasuryKind,
+        treasury_account: &AccountInfo,
+        state_account: &AccountInfo,
+    ) -> Result<Self, ProgramError> {
+        Ok(match kind {
+            TreasuryKind::Lamports => {
+                Backing::Lamports(load_treasury(program_id, treasury_account, state_account)?)
+            }
+            TreasuryKind::TokenPool => {
+                Backing::TokenPool(load_pool(program_id, treasury_account, state_account)?)
+            }
+        })
+    }
+
+    fn paused(&self) -> u8 {
+        match self {
+            Backing::Lamports(treasury) => treasury.paused,
+            Backing::TokenPool(pool) => pool.paused,
+        }
+    }
+
+    fn reserved(&self) -> u64 {
+        match self {
+            Backing::Lamports(treasury) => treasury.reserved,
+            Backing::TokenPool(pool) => pool.reserved,
+        }
+    }
+
+    /// Deposit lockup in slots and early exit fee in bps
+    fn lockup(&self) -> (u64, u16) {
+        match self {
+            Backing::Lamports(treasury) => (treasury.lockup_slots, treasury.early_exit_fee_bps),
+            Backing::TokenPool(pool) => (pool.lockup_slots, pool.early_exit_fee_bps),
+        }
+    }
+
+    /// Liquidity owned by the treasury's LPs
+    fn liquidity(&self, treasury_account: &AccountInfo, state_account: &AccountInfo) -> Result<u64, ProgramError> {
+        match self {
+            Backing::Lamports(_) => Ok(treasury_account.lamports()),
+            Backing::TokenPool(pool) => liquidity(pool, state_account),
+        }
+    }
+
+    fn epoch_start(&self) -> &EpochStart {
+        match self {
+            Backing::Lamports(treasury) => &treasury.epoch_start,
+            Backing::TokenPool(pool) => &pool.epoch_start,
+        }
+    }
+
+    fn open_epoch(
+        &mut self,
+        treasury_account: &AccountInfo,
+        state_account: &AccountInfo,
+        supply: u64,
+        clock_account: &AccountInfo,
+    ) -> ProgramResult {
+        match self {
+            Backing::Lamports(treasury) => {
+                open_treasury_epoch(treasury, treasury_account.lamports(), Some(supply), clock_account)
+            }
+            Backing::TokenPool(pool) => open_pool_epoch(pool, state_account, Some(supply), clock_account),
+        }
+    }
+
+    fn pack(&self, treasury_account: &AccountInfo, state_account: &AccountInfo) -> ProgramResult {
+        match self {
+            Backing::Lamports(treasury) => treasury.pack(&mut state_account.data.borrow_mut()),
+            Backing::TokenPool(pool) => pool.pack(&mut treasury_account.data.borrow_mut()),
+        }
+    }
+}
+
+/// Snapshot the treasury's value at the start of the current epoch if this
+/// is the epoch's first withdrawal instruction
+fn roll_epoch(
+    queue: &mut WithdrawQueue,
+    backing: &mut Backing,
+    clock_account: &AccountInfo,
+    treasury_account: &AccountInfo,
+    state_account: &AccountInfo,
+    mint_account: &AccountInfo,
+) -> ProgramResult {
+    assert_sysvar(clock_account, &clock::id())?;
+    let epoch = Clock::from_account_info(clock_account)?.epoch;
+    let supply = Mint::unpack(&mint_account.data.borrow())?.supply;
+    backing.open_epoch(treasury_account, state_account, supply, clock_account)?;
+    backing.pack(treasury_account, state_account)?;
+    let start = *backing.epoch_start();
+    if snapshot(queue, epoch, start.liquidity, start.supply) {
+        info!(&format!(
+            "WITHDRAW_EPOCH epoch={} liquidity={} supply={}",
+            epoch, start.liquidity, start.supply
+        ));
+    }
+    Ok(())
+}
+
+/// 24 - create a treasury's withdrawal queue and token escrow
+///
+/// Data: [1] treasury kind, 0 lamports or 1 token pool. Accounts: payer
+/// (signer), treasury (signer) or pool, treasury state or vault, treasury
+/// token mint, withdraw queue, escrow, rent sysvar, system program, token
+/// program
+pub fn process_init_withdraw_queue(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let payer_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let state_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter)?;
+    let queue_account = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    assert_signer(payer_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    assert_key(token_program, &spl_token::ID)?;
+    match Backing::load(program_id, kind, treasury_account, state_account)? {
+        // The lamport treasury vouches for its mint, a pool records its own
+        Backing::Lamports(_) => assert_signer(treasury_account)?,
+        Backing::TokenPool(pool) => assert_key(mint_account, &pool.lp_mint)?,
+    }
+    Mint::unpack(&mint_account.data.borrow())?;
+
+    let (queue_address, bump_seed) = WithdrawQueue::find_address(program_id, treasury_account.key);
+    let (escrow_address, escrow_bump_seed) =
+        WithdrawQueue::find_escrow_address(program_id, treasury_account.key);
+    assert_key(queue_account, &queue_address)?;
+    assert_key(escrow_account, &escrow_address)?;
+    let rent = Rent::from_account_info(rent_account)?;
+
+    info!("Creating withdraw queue");
+    create_pda_account(
+        payer_account,
+        queue_account,
+        system_program_account,
+        program_id,
+        WithdrawQueue::LEN,
+        &rent,
+        &[WITHDRAW_QUEUE_SEED, treasury_account.key.as_ref(), &[bump_seed]],
+    )?;
+    create_pda_account(
+        payer_account,
+        escrow_account,
+        system_program_account,
+        &spl_token::ID,
+        TokenAccount::LEN,
+        &rent,
+        &[WITHDRAW_ESCROW_SEED, treasury_account.key.as_ref(), &[escrow_bump_seed]],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_account(
+            &spl_token::ID,
+            escrow_account.key,
+            mint_account.key,
+            queue_account.key,
+        )?,
+        &[
+            escrow_account.clone(),
+            mint_account.clone(),
+            queue_account.clone(),
+            rent_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    let queue = WithdrawQueue {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury_account.key,
+        mint: *mint_account.key,
+        escrow_bump_seed,
+        ..WithdrawQueue::default()
+    };
+    queue.pack(&mut queue_account.data.borrow_mut())
+}
+
+/// 3, 8 - request a withdrawal, locking treasury tokens in the queue
+///
+/// Command 3 withdraws from the lamport treasury and command 8 from a token
+/// pool. Requesting again adds to the pending request, which then waits for
+/// the next epoch again. Requests within the owner's deposit lockup pay the
+/// early exit fee on execution. Data: [1..9] treasury token amount.
+/// Accounts: owner (signer), owner's treasury token account, treasury or
+/// pool, treasury state or vault, treasury token mint, withdraw queue,
+/// escrow, withdraw request, clock sysvar, rent sysvar, system program,
+/// token program, owner's deposit record
+pub fn process_request_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+    kind: TreasuryKind,
+) -> ProgramResult {
+    if data.len() < 9 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let tokens = LittleEndian::read_u64(&data[1..9]);
+    if tokens == 0 {
+        info!("Withdraw amount must be > 0");
+        return Err(RollError::InvalidAmount.into());
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?;
+    let owner_token_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let state_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter)?;
+    let queue_account = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let request_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let rent_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let deposit_account = next_account_info(accounts_iter)?;
+
+    assert_signer(owner_account)?;
+    assert_sysvar(rent_account, &rent::id())?;
+    assert_key(system_program_account, &system_program::id())?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_distinct(&[owner_token_account, escrow_account])?;
+    let mut backing = Backing::load(program_id, kind, treasury_account, state_account)?;
+    pause::assert_not_paused(backing.paused(), PAUSE_WITHDRAWALS)?;
+    let mut queue = load_queue(program_id, treasury_account, queue_account, mint_account)?;
+    let (escrow_address, _) = WithdrawQueue::find_escrow_address(program_id, treasury_account.key);
+    assert_key(escrow_account, &escrow_address)?;
+    let (request_address, request_bump) =
+        WithdrawRequest::find_address(program_id, treasury_account.key, owner_account.key);
+    assert_key(request_account, &request_address)?;
+
+    roll_epoch(&mut queue, &mut backing, clock_account, treasury_account, state_account, mint_account)?;
+    let clock = Clock::from_account_info(clock_account)?;
+    let (lockup_slots, early_exit_fee_bps) = backing.lockup();
+    let fee_bps = lockup::owed_fee_bps(
+        program_id,
+        treasury_account.key,
+        owner_account.key,
+        lockup_slots,
+        early_exit_fee_bps,
+        clock.slot,
+        deposit_account,
+    )?;
+
+    info!("Queueing treasury tokens for withdrawal:");
+    info!(&tokens.to_string());
+    invoke(
+        &spl_token::instruction::transfer(
+            &spl_token::ID,
+            owner_token_account.key,
+            escrow_account.key,
+            owner_account.key,
+            &[],
+            tokens,
+        )?,
+        &[
+            owner_token_account.clone(),
+            escrow_account.clone(),
+            owner_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    let mut request = if request_account.owner == program_id {
+        WithdrawRequest::unpack(&request_account.data.borrow())?
+    } else {
+        create_pda_account(
+            owner_account,
+            request_account,
+            system_program_account,
+            program_id,
+            WithdrawRequest::LEN,
+            &Rent::from_account_info(rent_account)?,
+            &[
+                WITHDRAW_REQUEST_SEED,
+                treasury_account.key.as_ref(),
+                owner_account.key.as_ref(),
+                &[request_bump],
+            ],
+        )?;
+        WithdrawRequest {
+            is_initialized: true,
+            bump_seed: request_bump,
+            treasury: *treasury_account.key,
+            owner: *owner_account.key,
+            ..WithdrawRequest::default()
+        }
+    };
+    request.tokens = request.tokens.saturating_add(tokens);
+    request.epoch = clock.epoch;
+    request.early_exit_fee_bps = request.early_exit_fee_bps.max(fee_bps);
+    request.pack(&mut request_account.data.borrow_mut())?;
+
+    queue.queued = queue.queued.saturating_add(tokens);
+    queue.pack(&mut queue_account.data.borrow_mut())
+}
+
+/// 25 - pay out a due withdrawal request
+///
+/// Burns the escrowed tokens at the snapshot price, less any early exit fee,
+/// and closes the request. Anyone may execute. Data: [1] treasury kind, 0
+/// lamports or 1 token pool. Accounts: owner, treasury or pool, treasury
+/// state or vault, treasury token mint, withdraw queue, escrow, withdraw
+/// request, clock sysvar, token program, price oracle, then for token pools
+/// the owner's token account of the pool mint
+pub fn process_execute_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let kind = data
+        .get(1)
+        .and_then(|kind| TreasuryKind::from_u8(*kind))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let accounts_iter = &mut accounts.iter();
+    let owner_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let state_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter)?;
+    let queue_account = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let request_account = next_account_info(accounts_iter)?;
+    let clock_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let oracle_account = next_account_info(accounts_iter)?;
+
+    assert_writable(owner_account)?;
+    assert_key(token_program, &spl_token::ID)?;
+    assert_distinct(&[owner_account, treasury_account, request_account])?;
+    let mut backing = Backing::load(program_id, kind, treasury_account, state_account)?;
+    pause::assert_not_paused(backing.paused(), PAUSE_WITHDRAWALS)?;
+    let mut queue = load_queue(program_id, treasury_account, queue_account, mint_account)?;
+    let (escrow_address, _) = WithdrawQueue::find_escrow_address(program_id, treasury_account.key);
+    assert_key(escrow_account, &escrow_address)?;
+    let (request_address, _) =
+        WithdrawRequest::find_address(program_id, treasury_account.key, owner_account.key);
+    assert_key(request_account, &request_address)?;
+    assert_program_account(request_account, program_id)?;
+    let request = WithdrawRequest::unpack(&request_account.data.borrow())?;
+    if !request.is_initialized || request.tokens == 0 {
+        info!("SolanaRoll no withdrawal requested");
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    roll_epoch(&mut queue, &mut backing, clock_account, treasury_account, state_account, mint_account)?;
+    if !is_due(&queue, request.epoch) {
+        info!("Withdrawal is paid at the next epoch's price");
+        return Err(RollError::WithdrawNotDue.into());
+    }
+
+    let value = shares::withdraw_amount_out(request.tokens, queue.liquidity, queue.supply);
+    // The early exit fee is not paid out, staying with the remaining LPs
+    let fee = lockup::early_exit_fee(value, request.early_exit_fee_bps);
+    let amount = value - fee;
+    if amount == 0 {
+        info!("Withdraw amount must be > 0");
+        return Err(RollError::InvalidAmount.into());
+    }
+    let treasury_liquidity = backing.liquidity(treasury_account, state_account)?;
+    settle::assert_unreserved(backing.reserved(), treasury_liquidity, amount)?;
+    info!("Burning queued treasury tokens, withdrawing:");
+    info!(&request.tokens.to_string());
+    info!(&amount.to_string());
+    if fee > 0 {
+        info!("Early exit fee:");
+        info!(&fee.to_string());
+    }
+
+    invoke_signed(
+        &spl_token::instruction::burn(
+            &spl_token::ID,
+            escrow_account.key,
+            mint_account.key,
+            queue_account.key,
+            &[],
+            request.tokens,
+        )?,
+        &[
+            escrow_account.clone(),
+            mint_account.clone(),
+            queue_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[WITHDRAW_QUEUE_SEED, treasury_account.key.as_ref(), &[queue.bump_seed]]],
+    )?;
+
+    match &backing {
+        Backing::Lamports(_) => {
+            **treasury_account.lamports.borrow_mut() -= amount;
+            **owner_account.lamports.borrow_mut() += amount;
+        }
+        Backing::TokenPool(pool) => {
+            let owner_token_account = next_account_info(accounts_iter)?;
+            let owner_tokens = load_token_account(owner_token_account, owner_account.key)?;
+            if owner_tokens.mint != pool.mint {
+                info!("Owner token account is not for the pool mint");
+                return Err(RollError::IncorrectAddress.into());
+            }
+            assert_distinct(&[state_account, owner_token_account])?;
+            transfer_from_vault(pool, treasury_account, state_account, owner_token_account, token_program, amount)?;
+        }
+    }
+
+    queue.queued = queue.queued.saturating_sub(request.tokens);
+    queue.pack(&mut queue_account.data.borrow_mut())?;
+
+    // Close the request, returning its rent to the owner
+    let rent_lamports = request_account.lamports();
+    **request_account.lamports.borrow_mut() = 0;
+    **owner_account.lamports.borrow_mut() += rent_lamports;
+    request_account.data.borrow_mut().iter_mut().for_each(|byte| *byte = 0);
+
+    oracle::update(
+        program_id,
+        treasury_account.key,
+        backing.liquidity(treasury_account, state_account)?,
+        Some(Mint::unpack(&mint_account.data.borrow())?.supply),
+        clock_account,
+        oracle_account,
+    )?;
+
+    info!("Withdraw successful");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_once_per_epoch() {
+        let mut queue = WithdrawQueue::default();
+        assert!(snapshot(&mut queue, 3, 1_000, 500));
+        assert!(!snapshot(&mut queue, 3, 2_000, 500));
+        assert_eq!((queue.epoch, queue.liquidity, queue.supply), (3, 1_000, 500));
+        assert!(snapshot(&mut queue, 5, 2_000, 400));
+        assert_eq!((queue.epoch, queue.liquidity, queue.supply), (5, 2_000, 400));
+    }
+
+    #[test]
+    fn test_open_epoch_once() {
+        let mut start = EpochStart::default();
+        // A commit opens the epoch before the supply is known
+        open_epoch(&mut start, 4, 1_000, None);
+        open_epoch(&mut start, 4, 900, None);
+        assert_eq!((start.liquidity, start.has_supply), (1_000, false));
+        // The first instruction reading the mint fills it in, later ones
+        // leave the record alone
+        open_epoch(&mut start, 4, 800, Some(500));
+        open_epoch(&mut start, 4, 700, Some(600));
+        assert_eq!((start.epoch, start.liquidity, start.supply), (4, 1_000, 500));
+        open_epoch(&mut start, 5, 700, Some(600));
+        assert_eq!(
+            start,
+            EpochStart {
+                epoch: 5,
+                liquidity: 700,
+                supply: 600,
+                has_supply: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_due_after_request_epoch() {
+        let mut queue = WithdrawQueue::default();
+        snapshot(&mut queue, 7, 1_000, 500);
+        assert!(!is_due(&queue, 7));
+        assert!(is_due(&queue, 6));
+        snapshot(&mut queue, 8, 1_000, 500);
+        assert!(is_due(&queue, 7));
+    }
+}
@@ -0,0 +1,310 @@
+// Bets placed and settled by another program through the `cpi` interface
+#![cfg(feature = "program")]
+
+use solana_bpf_helloworld::{
+    cpi,
+    instruction::Commit,
+    odds::GameKind,
+    process_instruction,
+    state::{Game, GameStatus, Referral, Treasury},
+};
+use solana_sdk::{
+    account_info::AccountInfo,
+    clock::{Clock, Epoch},
+    entrypoint::ProcessInstruction,
+    entrypoint_deprecated::ProgramResult,
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    program_stubs::{self, SyscallStubs},
+    pubkey::Pubkey,
+    slot_hashes::SlotHashes,
+    sysvar::{clock, slot_hashes, Sysvar},
+};
+use std::sync::Mutex;
+
+const FUND: u64 = 100_000;
+const TREASURY: u64 = 1_000_000;
+const COMMIT_SLOT: u64 = 10;
+const WAGER: u64 = 5_000;
+const REVEAL: u8 = 42;
+
+/// Example tournament program whose entrant is its PDA `["entrant"]`,
+/// which bets the entrant's fund against the lamport treasury through
+/// SolanaRoll and collects winnings in the tournament's prize account
+mod tournament {
+    use byteorder::{ByteOrder, LittleEndian};
+    use solana_bpf_helloworld::{
+        cpi::{LamportCommitAccounts, LamportSettleAccounts},
+        instruction::Commit,
+        odds::GameKind,
+    };
+    use solana_sdk::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint_deprecated::ProgramResult,
+        program_error::ProgramError,
+        pubkey::Pubkey,
+    };
+
+    pub const ENTRANT_SEED: &[u8] = b"entrant";
+
+    /// 0 - bet [2..10] on rolling under 50.00 with reveal number [1].
+    /// Accounts: entrant, game, clock sysvar, slot hashes sysvar, fund,
+    /// treasury, treasury state, entrant's referral, SolanaRoll
+    ///
+    /// 1 - settle with reveal number [1]. Accounts: entrant, game, clock
+    /// sysvar, slot hashes sysvar, fund, treasury, prize account, treasury
+    /// state, player stats, roll history, oracle, fee vault, SolanaRoll
+    pub fn process_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        data: &[u8],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let entrant = next_account_info(accounts_iter)?;
+        let (address, bump_seed) = Pubkey::find_program_address(&[ENTRANT_SEED], program_id);
+        if *entrant.key != address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        match data.first() {
+            Some(0) => {
+                let bet = Commit {
+                    reveal_number: data[1] as u32,
+                    kind: GameKind::DiceUnder,
+                    target: 5_000,
+                    roll_count: 1,
+                    wager: LittleEndian::read_u64(&data[2..10]),
+                    roulette: None,
+                    secret_hash: None,
+                };
+                LamportCommitAccounts {
+                    player: entrant,
+                    game: next_account_info(accounts_iter)?,
+                    clock: next_account_info(accounts_iter)?,
+                    slot_hashes: next_account_info(accounts_iter)?,
+                    fund: next_account_info(accounts_iter)?,
+                    treasury: next_account_info(accounts_iter)?,
+                    treasury_state: next_account_info(accounts_iter)?,
+                    referral: Some(next_account_info(accounts_iter)?),
+                    program: next_account_info(accounts_iter)?,
+                }
+                .invoke_signed(&bet, &[&[ENTRANT_SEED, &[bump_seed]]])
+            }
+            Some(1) => LamportSettleAccounts {
+                player: entrant,
+                game: next_account_info(accounts_iter)?,
+                clock: next_account_info(accounts_iter)?,
+                slot_hashes: next_account_info(accounts_iter)?,
+                fund: next_account_info(accounts_iter)?,
+                treasury: next_account_info(accounts_iter)?,
+                recipient: next_account_info(accounts_iter)?,
+                treasury_state: next_account_info(accounts_iter)?,
+                player_stats: next_account_info(accounts_iter)?,
+                roll_history: next_account_info(accounts_iter)?,
+                oracle: next_account_info(accounts_iter)?,
+                fee_vault: next_account_info(accounts_iter)?,
+                program: next_account_info(accounts_iter)?,
+                referrer: None,
+            }
+            .invoke_signed(data[1], &[&[ENTRANT_SEED, &[bump_seed]]]),
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+}
+
+/// Cross-program invokes run natively: the callee's processor gets the
+/// caller's account infos in instruction order, with the PDAs the caller
+/// signed for marked as signers.
+struct CpiStubs {
+    programs: Vec<(Pubkey, ProcessInstruction)>,
+    /// Programs currently running, the innermost last
+    callers: Mutex<Vec<Pubkey>>,
+}
+
+impl SyscallStubs for CpiStubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = *self.callers.lock().unwrap().last().unwrap();
+        let signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller).unwrap())
+            .collect();
+        let accounts: Vec<AccountInfo> = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let mut account = account_infos
+                    .iter()
+                    .find(|account| *account.key == meta.pubkey)
+                    .expect("account missing from the invoke")
+                    .clone();
+                assert!(!meta.is_writable || account.is_writable, "writable mismatch");
+                if meta.is_signer && !account.is_signer {
+                    assert!(signers.contains(&meta.pubkey), "signer mismatch");
+                    account.is_signer = true;
+                }
+                account
+            })
+            .collect();
+        let (_, process) = self
+            .programs
+            .iter()
+            .find(|(program_id, _)| *program_id == instruction.program_id)
+            .expect("unknown program");
+        self.callers.lock().unwrap().push(instruction.program_id);
+        let result = process(&instruction.program_id, &accounts, &instruction.data);
+        self.callers.lock().unwrap().pop();
+        result
+    }
+}
+
+/// The tournament's instruction `tag` with the accounts of the SolanaRoll
+/// instruction it wraps, SolanaRoll itself appended
+fn wrap(caller_id: Pubkey, program_id: Pubkey, tag: u8, inner: Instruction, data: Vec<u8>) -> Instruction {
+    let mut accounts: Vec<AccountMeta> = inner
+        .accounts
+        .into_iter()
+        .map(|meta| AccountMeta {
+            is_signer: false,
+            ..meta
+        })
+        .collect();
+    accounts.push(AccountMeta::new_readonly(program_id, false));
+    let mut instruction_data = vec![tag];
+    instruction_data.extend(data);
+    Instruction {
+        program_id: caller_id,
+        accounts,
+        data: instruction_data,
+    }
+}
+
+/// Accounts the runtime holds: key, lamports, data and owner
+type Ledger = Vec<(Pubkey, u64, Vec<u8>, Pubkey)>;
+
+/// Run the tournament's `instruction` against `ledger` at `clock`, as the
+/// runtime would with `program_id` deployed
+fn run(
+    program_id: &Pubkey,
+    instruction: &Instruction,
+    ledger: &mut Ledger,
+    clock: &Clock,
+    slot_hashes: &SlotHashes,
+) -> ProgramResult {
+    let mut infos: Vec<AccountInfo> = ledger
+        .iter_mut()
+        .map(|(key, lamports, data, owner)| {
+            let executable = key == program_id;
+            AccountInfo::new(key, false, true, lamports, data, owner, executable, Epoch::default())
+        })
+        .collect();
+    for info in infos.iter_mut() {
+        if *info.key == clock::id() {
+            clock.to_account_info(info).unwrap();
+        } else if *info.key == slot_hashes::id() {
+            slot_hashes.to_account_info(info).unwrap();
+        }
+    }
+    let accounts: Vec<AccountInfo> = instruction
+        .accounts
+        .iter()
+        .map(|meta| {
+            let mut account = infos.iter().find(|info| *info.key == meta.pubkey).unwrap().clone();
+            account.is_writable = meta.is_writable;
+            account
+        })
+        .collect();
+    tournament::process_instruction(&instruction.program_id, &accounts, &instruction.data)
+}
+
+// Program-test's CPI stubs hand the lamport settle its accounts in message
+// order, so the tournament runs against accounts held here
+#[test]
+fn test_pda_player_commits_and_settles() {
+    let program_id = Pubkey::new_unique();
+    let caller_id = Pubkey::new_unique();
+    program_stubs::set_syscall_stubs(Box::new(CpiStubs {
+        programs: vec![(program_id, process_instruction)],
+        callers: Mutex::new(vec![caller_id]),
+    }));
+
+    let treasury = Pubkey::new_unique();
+    let (treasury_state, bump_seed) = Treasury::find_address(&program_id, &treasury);
+    let mut state_data = vec![0; Treasury::LEN];
+    Treasury {
+        is_initialized: true,
+        bump_seed,
+        treasury,
+        kelly_fraction_bps: 10_000,
+        ..Treasury::default()
+    }
+    .pack(&mut state_data)
+    .unwrap();
+    let game = Pubkey::new_unique();
+    let fund = Pubkey::new_unique();
+    let prize = Pubkey::new_unique();
+
+    // The entrant is a PDA of the tournament, which signs for it
+    let (entrant, _) = Pubkey::find_program_address(&[tournament::ENTRANT_SEED], &caller_id);
+
+    let system = Pubkey::default();
+    let sysvar = solana_sdk::sysvar::id();
+    let settle = cpi::settle(&program_id, &entrant, &game, &fund, &treasury, &prize, REVEAL, None);
+    let mut ledger: Ledger = vec![
+        (entrant, 0, vec![], system),
+        (game, 1, vec![0; Game::LEN], program_id),
+        (clock::id(), 1, vec![0; Clock::size_of()], sysvar),
+        (slot_hashes::id(), 1, vec![0; SlotHashes::size_of()], sysvar),
+        (fund, FUND, vec![0; 32], program_id),
+        (treasury, TREASURY, vec![], program_id),
+        (treasury_state, 1, state_data, program_id),
+        (Referral::find_address(&program_id, &entrant).0, 0, vec![], system),
+        (prize, 0, vec![], caller_id),
+        (program_id, 1, vec![], system),
+    ];
+    // Player stats, roll history, oracle and fee vault are not created yet
+    for meta in &settle.accounts[8..] {
+        ledger.push((meta.pubkey, 0, vec![], system));
+    }
+    let slot_hashes = SlotHashes::new(&[(COMMIT_SLOT, Hash::new_unique())]);
+
+    let bet = Commit {
+        reveal_number: REVEAL as u32,
+        kind: GameKind::DiceUnder,
+        target: 5_000,
+        roll_count: 1,
+        wager: WAGER,
+        roulette: None,
+        secret_hash: None,
+    };
+    let commit = cpi::commit(&program_id, &entrant, &game, &fund, &treasury, &bet);
+    let mut data = vec![REVEAL];
+    data.extend_from_slice(&WAGER.to_le_bytes());
+    let at_commit = Clock {
+        slot: COMMIT_SLOT,
+        ..Clock::default()
+    };
+    let commit = wrap(caller_id, program_id, 0, commit, data);
+    run(&program_id, &commit, &mut ledger, &at_commit, &slot_hashes).unwrap();
+
+    let state = Game::unpack(&ledger[1].2).unwrap();
+    assert_eq!(state.status, GameStatus::Committed);
+    assert_eq!(state.player, entrant);
+
+    let at_settle = Clock {
+        slot: COMMIT_SLOT + 1,
+        ..Clock::default()
+    };
+    let settle = wrap(caller_id, program_id, 1, settle, vec![REVEAL]);
+    run(&program_id, &settle, &mut ledger, &at_settle, &slot_hashes).unwrap();
+
+    assert_eq!(Game::unpack(&ledger[1].2).unwrap().status, GameStatus::Settled);
+    assert_eq!(ledger[4].1, 0);
+    // The game rolled rather than refunding, and whatever the roll the fund
+    // is now with the prize account or the treasury
+    assert_ne!(ledger[8].1, FUND);
+    assert_eq!(ledger[8].1 + ledger[5].1, FUND + TREASURY);
+}
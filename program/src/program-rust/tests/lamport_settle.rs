@@ -0,0 +1,114 @@
+// Settling lamport games through the `cpi` builders
+#![cfg(feature = "program")]
+
+use solana_bpf_helloworld::{
+    cpi,
+    instruction::Commit,
+    odds::GameKind,
+    process_instruction,
+    state::{Game, GameStatus, Treasury},
+};
+use solana_program_test::{processor, BanksClientExt, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+const WAGER: u64 = 5_000;
+const REVEAL: u8 = 42;
+
+fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_lamport_settle_pays_player() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test =
+        ProgramTest::new("solana_bpf_helloworld", program_id, processor!(process_instruction));
+
+    let treasury = Pubkey::new_unique();
+    let (treasury_state, bump_seed) = Treasury::find_address(&program_id, &treasury);
+    let mut state_data = vec![0; Treasury::LEN];
+    Treasury {
+        is_initialized: true,
+        bump_seed,
+        treasury,
+        kelly_fraction_bps: 10_000,
+        ..Treasury::default()
+    }
+    .pack(&mut state_data)
+    .unwrap();
+    program_test.add_account(treasury, account(program_id, vec![]));
+    program_test.add_account(treasury_state, account(program_id, state_data));
+
+    let game = Pubkey::new_unique();
+    let fund = Pubkey::new_unique();
+    program_test.add_account(game, account(program_id, vec![0; Game::LEN]));
+    program_test.add_account(fund, account(program_id, vec![0; 32]));
+
+    let player = Keypair::new();
+    let stranger = Keypair::new();
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let bet = Commit {
+        reveal_number: REVEAL as u32,
+        kind: GameKind::DiceUnder,
+        target: 5_000,
+        roll_count: 1,
+        wager: WAGER,
+        roulette: None,
+        secret_hash: None,
+    };
+    let mut transaction = Transaction::new_with_payer(
+        &[cpi::commit(&program_id, &player.pubkey(), &game, &fund, &treasury, &bet)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &player], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // A stranger cannot settle the player's game, even with the right reveal
+    let settle = |settler: &Pubkey, reveal_number: u8| {
+        cpi::settle(
+            &program_id,
+            settler,
+            &game,
+            &fund,
+            &treasury,
+            &stranger.pubkey(),
+            reveal_number,
+            None,
+        )
+    };
+    let mut transaction =
+        Transaction::new_with_payer(&[settle(&stranger.pubkey(), REVEAL)], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &stranger], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+
+    // A wrong reveal fails rather than refunding
+    let mut transaction =
+        Transaction::new_with_payer(&[settle(&player.pubkey(), REVEAL + 1)], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &player], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+
+    // The player can direct their payout there by signing
+    let mut transaction =
+        Transaction::new_with_payer(&[settle(&player.pubkey(), REVEAL)], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &player], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let state = banks_client.get_account(game).await.unwrap().unwrap();
+    assert_eq!(Game::unpack(&state.data).unwrap().status, GameStatus::Settled);
+    let fund = banks_client.get_account(fund).await.unwrap();
+    assert!(fund.map_or(true, |fund| fund.lamports == 0));
+    let stranger = banks_client.get_account(stranger.pubkey()).await.unwrap().unwrap();
+    assert!(stranger.lamports > 0);
+}
@@ -0,0 +1,283 @@
+// Batched cranks of abandoned lamport games
+#![cfg(feature = "program")]
+
+use solana_bpf_helloworld::{
+    cpi,
+    crank::{CRANK_DELAY_SLOTS, MAX_BATCH_GAMES},
+    instruction::Commit,
+    odds::GameKind,
+    process_instruction,
+    state::{Game, GameStatus, Treasury},
+};
+use solana_program_test::{processor, BanksClientExt, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    account_info::AccountInfo,
+    clock::{Clock, Epoch},
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    sysvar::{clock, Sysvar},
+    transaction::Transaction,
+};
+
+const WAGER: u64 = 5_000;
+const FUND: u64 = 10_000;
+/// Compute units a BPF instruction may use
+#[cfg(feature = "test-bpf")]
+const COMPUTE_BUDGET: u64 = 200_000;
+
+fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Wire size of a transaction signed by its required signers
+fn transaction_size(transaction: &Transaction) -> usize {
+    let signatures = transaction.message.header.num_required_signatures as usize;
+    1 + signatures * 64 + transaction.message.serialize().len()
+}
+
+/// State of a treasury paying a 50 bps keeper fee with `reserved` exposure
+fn treasury_state(program_id: &Pubkey, treasury: &Pubkey, reserved: u64) -> (Pubkey, Vec<u8>) {
+    let (address, bump_seed) = Treasury::find_address(program_id, treasury);
+    let mut data = vec![0; Treasury::LEN];
+    Treasury {
+        is_initialized: true,
+        bump_seed,
+        treasury: *treasury,
+        kelly_fraction_bps: 10_000,
+        keeper_fee_bps: 50,
+        reserved,
+        ..Treasury::default()
+    }
+    .pack(&mut data)
+    .unwrap();
+    (address, data)
+}
+
+/// A one roll lamport game committed at `commit_slot`, reserving 100
+fn committed_game(treasury: &Pubkey, player: &Pubkey, fund: &Pubkey, commit_slot: u64) -> Vec<u8> {
+    let mut game = Game::unpack(&[0; Game::LEN]).unwrap();
+    game.status = GameStatus::Committed;
+    game.player = *player;
+    game.fund = *fund;
+    game.treasury = *treasury;
+    game.commit_slot = commit_slot;
+    game.wager = WAGER;
+    game.roll_count = 1;
+    game.reserved = 100;
+    let mut data = vec![0; Game::LEN];
+    game.pack(&mut data).unwrap();
+    data
+}
+
+/// A fund account linked to `game`
+fn linked_fund(game: &Pubkey) -> Vec<u8> {
+    let mut data = vec![0; Game::LEN];
+    data[..32].copy_from_slice(game.as_ref());
+    data
+}
+
+fn batch(program_id: &Pubkey, keeper: &Pubkey, treasury: &Pubkey, games: usize) -> Transaction {
+    let games: Vec<_> = (0..games)
+        .map(|_| (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()))
+        .collect();
+    Transaction::new_with_payer(&[cpi::settle_batch(program_id, keeper, treasury, &games)], Some(keeper))
+}
+
+// Each game adds three account keys, so the packet runs out before the
+// compute budget, which `test_full_batch_fits_compute_budget` meters
+#[test]
+fn test_batch_fits_transaction() {
+    let program_id = Pubkey::new_unique();
+    let keeper = Pubkey::new_unique();
+    let treasury = Pubkey::new_unique();
+
+    let full = batch(&program_id, &keeper, &treasury, MAX_BATCH_GAMES);
+    assert!(transaction_size(&full) <= PACKET_DATA_SIZE);
+    let over = batch(&program_id, &keeper, &treasury, MAX_BATCH_GAMES + 1);
+    assert!(transaction_size(&over) > PACKET_DATA_SIZE);
+}
+
+// Program-test keeps its bank at slot 1, short of every game's crank delay,
+// so the batch runs straight through the processor with a later clock
+#[test]
+fn test_settle_batch_settles_expired_games() {
+    let program_id = Pubkey::new_unique();
+    let keeper = Pubkey::new_unique();
+    let treasury = Pubkey::new_unique();
+    let (state_address, state_data) = treasury_state(&program_id, &treasury, 1_000);
+    let clock = Clock {
+        slot: CRANK_DELAY_SLOTS + 10,
+        ..Clock::default()
+    };
+
+    // Two games past their crank delay, then one committed too recently
+    let games: Vec<_> = (0..3)
+        .map(|_| (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()))
+        .collect();
+    let instruction = cpi::settle_batch(&program_id, &keeper, &treasury, &games);
+    let system = Pubkey::default();
+    let mut accounts: Vec<(u64, Vec<u8>, Pubkey)> = vec![
+        (0, vec![], system),
+        (1, vec![0; Clock::size_of()], system),
+        (0, vec![], program_id),
+        (1, state_data, program_id),
+    ];
+    for (index, (game, player, fund)) in games.iter().enumerate() {
+        let commit_slot = if index < 2 { 0 } else { 10 };
+        accounts.push((1, committed_game(&treasury, player, fund, commit_slot), program_id));
+        accounts.push((0, vec![], system));
+        accounts.push((FUND, linked_fund(game), program_id));
+    }
+    let mut infos: Vec<AccountInfo> = instruction
+        .accounts
+        .iter()
+        .zip(accounts.iter_mut())
+        .map(|(meta, (lamports, data, owner))| {
+            AccountInfo::new(&meta.pubkey, meta.is_signer, meta.is_writable, lamports, data, owner, false, Epoch::default())
+        })
+        .collect();
+    assert_eq!(infos[1].key, &clock::id());
+    assert_eq!(infos[3].key, &state_address);
+    clock.to_account_info(&mut infos[1]).unwrap();
+
+    process_instruction(&program_id, &infos, &instruction.data).unwrap();
+
+    // Each expired stake is forfeited less the keeper's fee, the rest of
+    // its fund returned to the player
+    let fee = WAGER * 50 / 10_000;
+    assert_eq!(infos[0].lamports(), 2 * fee);
+    assert_eq!(infos[2].lamports(), 2 * (WAGER - fee));
+    for (index, triple) in infos[4..].chunks(3).enumerate() {
+        let status = Game::unpack(&triple[0].data.borrow()).unwrap().status;
+        if index < 2 {
+            assert_eq!(status, GameStatus::Settled);
+            assert_eq!(triple[1].lamports(), FUND - WAGER);
+            assert_eq!(triple[2].lamports(), 0);
+        } else {
+            assert_eq!(status, GameStatus::Committed);
+            assert_eq!(triple[1].lamports(), 0);
+            assert_eq!(triple[2].lamports(), FUND);
+        }
+    }
+    let state = Treasury::unpack(&infos[3].data.borrow()).unwrap();
+    assert_eq!(state.reserved, 800);
+}
+
+#[tokio::test]
+async fn test_settle_batch_skips_games_it_cannot_settle() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test =
+        ProgramTest::new("solana_bpf_helloworld", program_id, processor!(process_instruction));
+
+    let treasury = Pubkey::new_unique();
+    let (treasury_state, state_data) = treasury_state(&program_id, &treasury, 0);
+    program_test.add_account(treasury, account(program_id, vec![]));
+    program_test.add_account(treasury_state, account(program_id, state_data));
+
+    // A committed game, an empty game and a committed game whose fund is not
+    // linked to it
+    let games: Vec<_> = (0..3).map(|_| (Pubkey::new_unique(), Pubkey::new_unique())).collect();
+    for (game, fund) in &games {
+        program_test.add_account(*game, account(program_id, vec![0; Game::LEN]));
+        program_test.add_account(*fund, account(program_id, vec![0; Game::LEN]));
+    }
+    let player = Keypair::new();
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let bet = Commit {
+        reveal_number: 42,
+        kind: GameKind::DiceUnder,
+        target: 5_000,
+        roll_count: 1,
+        wager: WAGER,
+        roulette: None,
+        secret_hash: None,
+    };
+    let (committed, committed_fund) = games[0];
+    let (unlinked, _) = games[2];
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            cpi::commit(&program_id, &player.pubkey(), &committed, &committed_fund, &treasury, &bet),
+            cpi::commit(&program_id, &player.pubkey(), &unlinked, &games[1].1, &treasury, &bet),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &player], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // Commits link blank funds to their game
+    let fund = banks_client.get_account(committed_fund).await.unwrap().unwrap();
+    assert_eq!(&fund.data[..32], committed.as_ref());
+
+    // Triples are (game, player, fund); the last game was committed with the
+    // empty game's fund, so its own is not linked
+    let triples = vec![
+        (committed, player.pubkey(), committed_fund),
+        (games[1].0, player.pubkey(), games[1].1),
+        (unlinked, player.pubkey(), games[2].1),
+    ];
+    let mut transaction = Transaction::new_with_payer(
+        &[cpi::settle_batch(&program_id, &payer.pubkey(), &treasury, &triples)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // None can be cranked this soon after the commit, and a skipped game is
+    // left as it was
+    for (game, _, fund) in &triples {
+        let fund = banks_client.get_account(*fund).await.unwrap().unwrap();
+        assert_eq!(fund.lamports, 1_000_000_000);
+        let game = banks_client.get_account(*game).await.unwrap().unwrap();
+        assert_ne!(Game::unpack(&game.data).unwrap().status, GameStatus::Settled);
+    }
+    let state = banks_client.get_account(treasury_state).await.unwrap().unwrap();
+    assert!(Treasury::unpack(&state.data).unwrap().reserved > 0);
+
+    // A batch with a partial triple is rejected outright
+    let mut instruction = cpi::settle_batch(&program_id, &payer.pubkey(), &treasury, &triples[..1]);
+    instruction.accounts.pop();
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+}
+
+// A full batch on the BPF build, metered against the compute budget. At the
+// bank's slot every game is read and checked up to its crank delay, the
+// bulk of a game's work next to the few lamport moves of settling it.
+// Build the program into `target/deploy` before running this.
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_full_batch_fits_compute_budget() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("solana_bpf_helloworld", program_id, None);
+    program_test.set_bpf_compute_max_units(COMPUTE_BUDGET);
+
+    let treasury = Pubkey::new_unique();
+    let (treasury_state, state_data) = treasury_state(&program_id, &treasury, 100 * MAX_BATCH_GAMES as u64);
+    program_test.add_account(treasury, account(program_id, vec![]));
+    program_test.add_account(treasury_state, account(program_id, state_data));
+    let games: Vec<_> = (0..MAX_BATCH_GAMES)
+        .map(|_| (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()))
+        .collect();
+    for (game, player, fund) in &games {
+        program_test.add_account(*game, account(program_id, committed_game(&treasury, player, fund, 0)));
+        program_test.add_account(*fund, account(program_id, linked_fund(game)));
+    }
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[cpi::settle_batch(&program_id, &payer.pubkey(), &treasury, &games)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+}
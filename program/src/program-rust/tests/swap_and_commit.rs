@@ -0,0 +1,370 @@
+// Swap-then-bet against the SPL token-swap program loaded into program-test
+//
+// Both SolanaRoll and token-swap run as BPF: the native program-test stubs
+// hand CPI accounts over in message order, which breaks every token
+// transfer. Build both programs into `target/deploy` before running these.
+#![cfg(all(feature = "program", feature = "test-bpf"))]
+
+use byteorder::{ByteOrder, LittleEndian};
+use solana_bpf_helloworld::state::{Game, GameStatus, Pool, Randomness, VAULT_SEED};
+use solana_program_test::{processor, BanksClientExt, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    sysvar::{clock, slot_hashes},
+    transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+const RESERVE: u64 = 1_000_000;
+const AMOUNT_IN: u64 = 10_000;
+/// Constant product output for `AMOUNT_IN` against equal reserves, no fees
+const AMOUNT_OUT: u64 = RESERVE * AMOUNT_IN / (RESERVE + AMOUNT_IN);
+const WAGER: u64 = 5_000;
+
+fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn mint_account(authority: Pubkey) -> Account {
+    let mut data = vec![0; Mint::LEN];
+    let mint = Mint {
+        mint_authority: Some(authority).into(),
+        decimals: 6,
+        is_initialized: true,
+        ..Mint::default()
+    };
+    Mint::pack(mint, &mut data).unwrap();
+    account(spl_token::id(), data)
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; TokenAccount::LEN];
+    let token = TokenAccount {
+        mint,
+        owner,
+        amount,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    };
+    TokenAccount::pack(token, &mut data).unwrap();
+    account(spl_token::id(), data)
+}
+
+fn token_amount(account: &Account) -> u64 {
+    TokenAccount::unpack(&account.data).unwrap().amount
+}
+
+/// Token-swap 2.0 state of a fee-less constant product swap: version,
+/// `SwapV1` fields in order, eight fee fields and the curve type with its
+/// empty calculator
+#[allow(clippy::too_many_arguments)]
+fn swap_state(
+    nonce: u8,
+    token_a: Pubkey,
+    token_b: Pubkey,
+    pool_mint: Pubkey,
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    pool_fee_account: Pubkey,
+) -> Vec<u8> {
+    let mut data = vec![1, 1, nonce];
+    for key in &[
+        spl_token::id(),
+        token_a,
+        token_b,
+        pool_mint,
+        token_a_mint,
+        token_b_mint,
+        pool_fee_account,
+    ] {
+        data.extend_from_slice(key.as_ref());
+    }
+    for _ in 0..4 {
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&10_000u64.to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 33]);
+    data
+}
+
+struct Setup {
+    program_id: Pubkey,
+    swap_program_id: Pubkey,
+    player: Keypair,
+    game: Pubkey,
+    pool: Pubkey,
+    vault: Pubkey,
+    source: Pubkey,
+    wager: Pubkey,
+    swap: Pubkey,
+    swap_authority: Pubkey,
+    reserve_in: Pubkey,
+    reserve_out: Pubkey,
+    swap_pool_mint: Pubkey,
+    swap_fee: Pubkey,
+}
+
+/// A SolanaRoll token pool for `wager_mint` and a constant product swap
+/// between `source_mint` and `wager_mint`, all written straight into the bank
+fn setup() -> (ProgramTest, Setup) {
+    let program_id = Pubkey::new_unique();
+    let swap_program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("solana_bpf_helloworld", program_id, None);
+    program_test.add_program("spl_token_swap", swap_program_id, None);
+    program_test.add_program(
+        "spl_token",
+        spl_token::id(),
+        processor!(spl_token::processor::Processor::process),
+    );
+
+    let player = Keypair::new();
+    let source_mint = Pubkey::new_unique();
+    let wager_mint = Pubkey::new_unique();
+    let lp_mint = Pubkey::new_unique();
+    program_test.add_account(source_mint, mint_account(player.pubkey()));
+    program_test.add_account(wager_mint, mint_account(player.pubkey()));
+
+    // SolanaRoll pool
+    let (pool, bump_seed) = Pool::find_address(&program_id, &wager_mint);
+    let (vault, _) = Pubkey::find_program_address(&[VAULT_SEED, wager_mint.as_ref()], &program_id);
+    let mut pool_data = vec![0; Pool::LEN];
+    Pool {
+        is_initialized: true,
+        bump_seed,
+        mint: wager_mint,
+        vault,
+        lp_mint,
+        authority: player.pubkey(),
+        kelly_fraction_bps: 10_000,
+        randomness: Randomness::SlotHashes,
+        ..Pool::default()
+    }
+    .pack(&mut pool_data)
+    .unwrap();
+    program_test.add_account(pool, account(program_id, pool_data));
+    program_test.add_account(vault, token_account(wager_mint, pool, RESERVE));
+
+    let game = Pubkey::new_unique();
+    program_test.add_account(game, account(program_id, vec![0; Game::LEN]));
+
+    // Player token accounts
+    let source = Pubkey::new_unique();
+    let wager = Pubkey::new_unique();
+    program_test.add_account(source, token_account(source_mint, player.pubkey(), RESERVE));
+    program_test.add_account(wager, token_account(wager_mint, player.pubkey(), 0));
+
+    // Token swap between the two mints
+    let swap = Pubkey::new_unique();
+    let (swap_authority, nonce) =
+        Pubkey::find_program_address(&[swap.as_ref()], &swap_program_id);
+    let reserve_in = Pubkey::new_unique();
+    let reserve_out = Pubkey::new_unique();
+    let swap_pool_mint = Pubkey::new_unique();
+    let swap_fee = Pubkey::new_unique();
+    program_test.add_account(reserve_in, token_account(source_mint, swap_authority, RESERVE));
+    program_test.add_account(reserve_out, token_account(wager_mint, swap_authority, RESERVE));
+    program_test.add_account(swap_pool_mint, mint_account(swap_authority));
+    program_test.add_account(swap_fee, token_account(swap_pool_mint, player.pubkey(), 0));
+
+    let swap_data = swap_state(
+        nonce,
+        reserve_in,
+        reserve_out,
+        swap_pool_mint,
+        source_mint,
+        wager_mint,
+        swap_fee,
+    );
+    program_test.add_account(swap, account(swap_program_id, swap_data));
+
+    let setup = Setup {
+        program_id,
+        swap_program_id,
+        player,
+        game,
+        pool,
+        vault,
+        source,
+        wager,
+        swap,
+        swap_authority,
+        reserve_in,
+        reserve_out,
+        swap_pool_mint,
+        swap_fee,
+    };
+    (program_test, setup)
+}
+
+/// Swap `AMOUNT_IN` and bet `WAGER` on rolling under 50.00, paid back in the
+/// source token
+fn swap_and_commit(setup: &Setup, minimum_amount_out: u64) -> Instruction {
+    let mut data = vec![0u8; 31];
+    data[0] = 9;
+    LittleEndian::write_u64(&mut data[1..9], AMOUNT_IN);
+    LittleEndian::write_u64(&mut data[9..17], minimum_amount_out);
+    data[17] = 1;
+    data[18] = 7;
+    data[19] = 0;
+    LittleEndian::write_u16(&mut data[20..22], 5_000);
+    data[22] = 1;
+    LittleEndian::write_u64(&mut data[23..31], WAGER);
+
+    Instruction {
+        program_id: setup.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(setup.player.pubkey(), true),
+            AccountMeta::new(setup.game, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new_readonly(slot_hashes::id(), false),
+            AccountMeta::new(setup.pool, false),
+            AccountMeta::new(setup.vault, false),
+            AccountMeta::new(setup.source, false),
+            AccountMeta::new(setup.wager, false),
+            AccountMeta::new_readonly(setup.swap_program_id, false),
+            AccountMeta::new_readonly(setup.swap, false),
+            AccountMeta::new_readonly(setup.swap_authority, false),
+            AccountMeta::new(setup.reserve_in, false),
+            AccountMeta::new(setup.reserve_out, false),
+            AccountMeta::new(setup.swap_pool_mint, false),
+            AccountMeta::new(setup.swap_fee, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data,
+    }
+}
+
+#[tokio::test]
+async fn test_swap_and_commit() {
+    let (program_test, setup) = setup();
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[swap_and_commit(&setup, AMOUNT_OUT)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &setup.player], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let source = banks_client.get_account(setup.source).await.unwrap().unwrap();
+    let wager = banks_client.get_account(setup.wager).await.unwrap().unwrap();
+    let vault = banks_client.get_account(setup.vault).await.unwrap().unwrap();
+    assert_eq!(token_amount(&source), RESERVE - AMOUNT_IN);
+    assert_eq!(token_amount(&wager), AMOUNT_OUT - WAGER);
+    assert_eq!(token_amount(&vault), RESERVE + WAGER);
+
+    let pool = banks_client.get_account(setup.pool).await.unwrap().unwrap();
+    let pool = Pool::unpack(&pool.data).unwrap();
+    assert_eq!(pool.escrowed, WAGER);
+    // An even roll pays 1.98x, so 0.98x of the wager is reserved
+    assert_eq!(pool.reserved, WAGER * 98 / 100);
+
+    let game = banks_client.get_account(setup.game).await.unwrap().unwrap();
+    let game = Game::unpack(&game.data).unwrap();
+    assert_eq!(game.status, GameStatus::Committed);
+    assert_eq!(game.wager, WAGER);
+    assert_eq!(game.pool, setup.pool);
+    assert_eq!(game.player, setup.source);
+}
+
+#[tokio::test]
+async fn test_swap_and_commit_slippage() {
+    let (program_test, setup) = setup();
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[swap_and_commit(&setup, AMOUNT_OUT + 1)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &setup.player], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+
+    let source = banks_client.get_account(setup.source).await.unwrap().unwrap();
+    let game = banks_client.get_account(setup.game).await.unwrap().unwrap();
+    assert_eq!(token_amount(&source), RESERVE);
+    assert_eq!(Game::unpack(&game.data).unwrap().status, GameStatus::Uninitialized);
+}
+
+/// Settle the game swapping its payout from `payout` back into the source
+/// token, signed by `owner`
+fn settle_and_swap(setup: &Setup, owner: &Pubkey, payout: &Pubkey) -> Instruction {
+    let mut data = vec![0u8; 10];
+    data[0] = 10;
+    data[1] = 7;
+    LittleEndian::write_u64(&mut data[2..10], 0);
+
+    Instruction {
+        program_id: setup.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(setup.game, false),
+            AccountMeta::new_readonly(clock::id(), false),
+            AccountMeta::new_readonly(slot_hashes::id(), false),
+            AccountMeta::new(setup.pool, false),
+            AccountMeta::new(setup.vault, false),
+            AccountMeta::new(setup.source, false),
+            AccountMeta::new(*payout, false),
+            AccountMeta::new_readonly(setup.swap_program_id, false),
+            AccountMeta::new_readonly(setup.swap, false),
+            AccountMeta::new_readonly(setup.swap_authority, false),
+            AccountMeta::new(setup.reserve_out, false),
+            AccountMeta::new(setup.reserve_in, false),
+            AccountMeta::new(setup.swap_pool_mint, false),
+            AccountMeta::new(setup.swap_fee, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ],
+        data,
+    }
+}
+
+// The payout is swapped from the settler's own pool mint account, never from
+// the vault, so settling through another account or signer fails
+#[tokio::test]
+async fn test_settle_and_swap_pays_the_owner() {
+    let (program_test, setup) = setup();
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[swap_and_commit(&setup, AMOUNT_OUT)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &setup.player], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // The vault is the pool's, not the player's
+    let mut transaction = Transaction::new_with_payer(
+        &[settle_and_swap(&setup, &setup.player.pubkey(), &setup.vault)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &setup.player], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+
+    // A stranger holds neither token account
+    let stranger = Keypair::new();
+    let mut transaction = Transaction::new_with_payer(
+        &[settle_and_swap(&setup, &stranger.pubkey(), &setup.wager)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &stranger], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_err());
+
+    let vault = banks_client.get_account(setup.vault).await.unwrap().unwrap();
+    assert_eq!(token_amount(&vault), RESERVE + WAGER);
+    let game = banks_client.get_account(setup.game).await.unwrap().unwrap();
+    assert_eq!(Game::unpack(&game.data).unwrap().status, GameStatus::Committed);
+}
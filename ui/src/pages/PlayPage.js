@@ -10,7 +10,7 @@ import {
   LAMPORTS_PER_SOL,
 } from '@solana/web3.js';
 
-import {useConnection, sendTransactionSequence, sendDepositSequence, sendWithdrawSequence, getTokenAccounts, getMintInfo} from "../util/connection"
+import {useConnection, sendTransactionSequence, sendDepositSequence, sendWithdrawSequence, getTokenAccounts, getMintInfo, getQuote} from "../util/connection"
 
 
 import { useWallet } from "../util/wallet";
@@ -49,7 +49,7 @@ export function PlayPage() {
 
     const [fundBalance, setFundBalance] = React.useState(0);
     const [fundBalanceDollar, setFundBalanceDollar] = React.useState(0);
-    const [maxProfitAllowed, setMaxProfitAllowed] = React.useState(0);
+    const [maxWager, setMaxWager] = React.useState(0);
 
     const refreshTreasuryBalance = React.useCallback(() => {
         (async () => {
@@ -60,7 +60,6 @@ export function PlayPage() {
             );
             setFundBalance(balance/LAMPORTS_PER_SOL);
             setFundBalanceDollar((balance/LAMPORTS_PER_SOL)*2.23); // TODO
-            setMaxProfitAllowed((balance/LAMPORTS_PER_SOL)*0.01); // TODO
           } catch (err) {
               console.log(err);
           }
@@ -68,14 +67,25 @@ export function PlayPage() {
     }, []);
 
     const setNewProfit = (newValue, newWager) => {
-        // let x = (( (   ((100-(newValue-1))) / (newValue-1)+1))*990/1000)-1;
-        let sub_under_number_64 = newValue - 1;
-        let num = (100 - sub_under_number_64);
-        let tmp = (num / sub_under_number_64) + 1;
-        let winning_ratio = (tmp * 990 / 1000) - 1;
-        let newProfit = winning_ratio*newWager;
-        setProfit(newProfit.toFixed(9));
-        setInvalid(newProfit > maxProfitAllowed);
+        (async () => {
+            const wagerLamports = Math.round(newWager * LAMPORTS_PER_SOL);
+            const quote = await getQuote(
+                connection,
+                programId,
+                payerAccount,
+                treasuryAccount.publicKey,
+                treasuryTokenAccount,
+                (newValue - 1) * 100,
+                wagerLamports
+            );
+            if (quote === null) {
+                setInvalid(true);
+                return;
+            }
+            setProfit(((quote.payout - wagerLamports) / LAMPORTS_PER_SOL).toFixed(9));
+            setMaxWager(quote.max_wager / LAMPORTS_PER_SOL);
+            setInvalid(wagerLamports > quote.max_wager);
+        })();
     };
 
     const handleChange = (event, newValue) => {
@@ -259,7 +269,7 @@ export function PlayPage() {
                               Potential Profit: +{profit} SOL
                             </Typography>
                             <Typography id="max-wager-text">
-                              Max Profit: {maxProfitAllowed.toFixed(9)} SOL
+                              Max Wager: {maxWager.toFixed(9)} SOL
                             </Typography>
                             <Slider
                                 defaultValue={51}
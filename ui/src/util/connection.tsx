@@ -9,6 +9,8 @@ import {
   Connection,
   Transaction,
   SystemProgram,
+  SYSVAR_CLOCK_PUBKEY,
+  SYSVAR_RENT_PUBKEY,
   TransactionInstruction,
 } from "@solana/web3.js";
 import {AccountLayout, u64, MintInfo, MintLayout, Token} from "@solana/spl-token";
@@ -338,7 +340,14 @@ export const sendDepositSequence = async (
             {pubkey: treasuryMint, isSigner: false, isWritable: true},
             {pubkey: userTokenAccountPubkey, isSigner: false, isWritable: true},
             {pubkey: splTokenProgram, isSigner: false, isWritable: false},
-            {pubkey: treasuryAccount.publicKey, isSigner: false, isWritable: true}],
+            {pubkey: treasuryAccount.publicKey, isSigner: false, isWritable: true},
+            {pubkey: await findTreasuryState(treasuryAccount.publicKey, programId), isSigner: false, isWritable: true},
+            {pubkey: await findLpDeposit(treasuryAccount.publicKey, wallet.publicKey, programId), isSigner: false, isWritable: true},
+            {pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false},
+            {pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false},
+            {pubkey: SystemProgram.programId, isSigner: false, isWritable: false},
+            {pubkey: await findOracle(treasuryAccount.publicKey, programId), isSigner: false, isWritable: true},
+            {pubkey: await findAllowlistEntry(treasuryAccount.publicKey, wallet.publicKey, programId), isSigner: false, isWritable: false}],
         programId,
         data: Buffer.from([2]),
     });
@@ -362,6 +371,171 @@ const longToByteArray = function(long: any) {
     return byteArray;
 };
 
+// Exposure state PDA of the lamport treasury, ["treasury", treasury]
+const findTreasuryState = async (treasury: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("treasury"), treasury.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Fee vault PDA of a treasury or token pool, ["fee_vault", treasury]
+const findFeeVault = async (treasury: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("fee_vault"), treasury.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Stats PDA of a player, ["player_stats", player]
+const findPlayerStats = async (player: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("player_stats"), player.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Roll history PDA of a treasury or token pool, ["roll_history", treasury]
+const findRollHistory = async (treasury: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("roll_history"), treasury.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Withdrawal queue PDA of the lamport treasury and its token escrow, and an
+// LP's pending withdrawal request
+const findWithdrawQueue = async (treasury: PublicKey, programId: PublicKey) => {
+    const [queue] = await PublicKey.findProgramAddress(
+        [Buffer.from("withdraw_queue"), treasury.toBuffer()],
+        programId
+    );
+    const [escrow] = await PublicKey.findProgramAddress(
+        [Buffer.from("withdraw_escrow"), treasury.toBuffer()],
+        programId
+    );
+    return [queue, escrow];
+};
+
+const findWithdrawRequest = async (treasury: PublicKey, owner: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("withdraw_request"), treasury.toBuffer(), owner.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Deposit record PDA of an LP, ["deposit", treasury, owner], starting their
+// lockup on every deposit
+const findLpDeposit = async (treasury: PublicKey, owner: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("deposit"), treasury.toBuffer(), owner.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Allowlist entry PDA of a depositor, ["allowlist", treasury, depositor],
+// only read when the treasury is allowlisted
+const findAllowlistEntry = async (treasury: PublicKey, depositor: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("allowlist"), treasury.toBuffer(), depositor.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Price oracle PDA of a treasury or token pool, ["oracle", treasury]
+const findOracle = async (treasury: PublicKey, programId: PublicKey) => {
+    const [address] = await PublicKey.findProgramAddress(
+        [Buffer.from("oracle"), treasury.toBuffer()],
+        programId
+    );
+    return address;
+};
+
+// Most recent settled bets of a treasury, newest first, read from its roll
+// history: a 48 byte header then 128 records of 72 bytes, little endian
+const ROLL_HISTORY_CAPACITY = 128;
+const ROLL_RECORD_LEN = 72;
+export const getRecentRolls = async (
+  connection: Connection,
+  programId: PublicKey,
+  treasury: PublicKey
+) => {
+    const info = await connection.getAccountInfo(await findRollHistory(treasury, programId));
+    if (info === null || info.data[0] === 0) {
+        return [];
+    }
+    const data = Buffer.from(info.data);
+    const total = u64.fromBuffer(data.slice(40, 48)).toNumber();
+    const rolls = [];
+    for (let i = 1; i <= Math.min(total, ROLL_HISTORY_CAPACITY); i++) {
+        const offset = 48 + ((total - i) % ROLL_HISTORY_CAPACITY) * ROLL_RECORD_LEN;
+        const record = data.slice(offset, offset + ROLL_RECORD_LEN);
+        rolls.push({
+            player: new PublicKey(record.slice(0, 32)),
+            wager: u64.fromBuffer(record.slice(32, 40)).toNumber(),
+            payout: u64.fromBuffer(record.slice(40, 48)).toNumber(),
+            result: u64.fromBuffer(record.slice(48, 56)).toNumber(),
+            slot: u64.fromBuffer(record.slice(56, 64)).toNumber(),
+            target: record.readUInt16LE(64),
+            kind: record[66],
+        });
+    }
+    return rolls;
+};
+
+// Quote a roll under `target` through the program's own math, by simulating
+// its read-only Quote instruction and parsing the "QUOTE key=value" log line
+export const getQuote = async (
+  connection: any,
+  programId: PublicKey,
+  payerAccount: Account,
+  treasury: PublicKey,
+  treasuryMint: PublicKey,
+  target: number,
+  wagerLamports: number,
+  depositLamports: number = 0
+) => {
+    const treasuryState = await findTreasuryState(treasury, programId);
+    const [withdrawQueue] = await findWithdrawQueue(treasury, programId);
+    const kind_dice_under = 0;
+    const data = [12, 0].concat(
+        longToByteArray(depositLamports),
+        [0, 0, kind_dice_under, target & 0xff, target >> 8, 1],
+        longToByteArray(wagerLamports)
+    );
+    const transaction = new Transaction().add(new TransactionInstruction({
+        keys: [{pubkey: treasury, isSigner: false, isWritable: false},
+            {pubkey: treasuryState, isSigner: false, isWritable: false},
+            {pubkey: treasuryMint, isSigner: false, isWritable: false},
+            {pubkey: withdrawQueue, isSigner: false, isWritable: false}],
+        programId,
+        data: Buffer.from(data),
+    }));
+    try {
+        const { value } = await connection.simulateTransaction(transaction, [payerAccount]);
+        const line = (value.logs || []).find((log: string) => log.includes("QUOTE "));
+        if (!line) {
+            return null;
+        }
+        const quote: any = {};
+        line.slice(line.indexOf("QUOTE ") + 6).split(" ").forEach((pair: string) => {
+            const [key, value] = pair.split("=");
+            quote[key] = Number(value);
+        });
+        return quote;
+    } catch (e) {
+        console.log(e);
+        return null;
+    }
+};
+
 export const sendWithdrawSequence = async (
   amount: any,
   wallet: any,
@@ -381,15 +555,26 @@ export const sendWithdrawSequence = async (
     // Get amount in byte array for instruction data
     const lamports = amount * LAMPORTS_PER_SOL;
     const lamports_ba = longToByteArray(lamports);
+    const treasuryState = await findTreasuryState(treasuryAccount.publicKey, programId);
+    const [withdrawQueue, withdrawEscrow] = await findWithdrawQueue(treasuryAccount.publicKey, programId);
+    const withdrawRequest = await findWithdrawRequest(treasuryAccount.publicKey, wallet.publicKey, programId);
 
-    console.log('Sending withdraw');
+    // Queued tokens are paid out by sendExecuteWithdrawSequence from the next epoch
+    console.log('Sending withdraw request');
     const instruction = new TransactionInstruction({
         keys: [{pubkey: wallet.publicKey, isSigner: true, isWritable: true},
-            {pubkey: payerAccount.publicKey, isSigner: false, isWritable: false},
-            {pubkey: treasuryMint, isSigner: false, isWritable: true},
             {pubkey: userTokenAccountPubkey, isSigner: false, isWritable: true},
+            {pubkey: treasuryAccount.publicKey, isSigner: false, isWritable: false},
+            {pubkey: treasuryState, isSigner: false, isWritable: true},
+            {pubkey: treasuryMint, isSigner: false, isWritable: false},
+            {pubkey: withdrawQueue, isSigner: false, isWritable: true},
+            {pubkey: withdrawEscrow, isSigner: false, isWritable: true},
+            {pubkey: withdrawRequest, isSigner: false, isWritable: true},
+            {pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false},
+            {pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false},
+            {pubkey: SystemProgram.programId, isSigner: false, isWritable: false},
             {pubkey: splTokenProgram, isSigner: false, isWritable: false},
-            {pubkey: treasuryAccount.publicKey, isSigner: false, isWritable: true}],
+            {pubkey: await findLpDeposit(treasuryAccount.publicKey, wallet.publicKey, programId), isSigner: false, isWritable: false}],
         programId,
         data: Buffer.from([3].concat(lamports_ba)),
     });
@@ -401,6 +586,41 @@ export const sendWithdrawSequence = async (
     setRefresh(0);
 };
 
+export const sendExecuteWithdrawSequence = async (
+  wallet: any,
+  connection: any,
+  programId: PublicKey,
+  splTokenProgram: PublicKey,
+  treasury: PublicKey,
+  treasuryMint: PublicKey,
+  setRefresh: any
+) => {
+    const treasuryState = await findTreasuryState(treasury, programId);
+    const [withdrawQueue, withdrawEscrow] = await findWithdrawQueue(treasury, programId);
+    const withdrawRequest = await findWithdrawRequest(treasury, wallet.publicKey, programId);
+
+    console.log('Sending withdraw');
+    const instruction = new TransactionInstruction({
+        keys: [{pubkey: wallet.publicKey, isSigner: false, isWritable: true},
+            {pubkey: treasury, isSigner: false, isWritable: true},
+            {pubkey: treasuryState, isSigner: false, isWritable: true},
+            {pubkey: treasuryMint, isSigner: false, isWritable: true},
+            {pubkey: withdrawQueue, isSigner: false, isWritable: true},
+            {pubkey: withdrawEscrow, isSigner: false, isWritable: true},
+            {pubkey: withdrawRequest, isSigner: false, isWritable: true},
+            {pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false},
+            {pubkey: splTokenProgram, isSigner: false, isWritable: false},
+            {pubkey: await findOracle(treasury, programId), isSigner: false, isWritable: true}],
+        programId,
+        data: Buffer.from([25, 0]),
+    });
+    let transaction = new Transaction();
+    transaction.add(instruction);
+    await sendTransaction(connection, null, wallet, transaction, null, [], [], true, true);
+    await sleep(1500);
+    setRefresh(0);
+};
+
 export const getTokenAccounts = async (
   connection: any,
   owner: any,
@@ -466,10 +686,22 @@ export const sendTransactionSequence = async (
 ) => {
 
     let treasuryPubkey = treasuryAccount.publicKey;
+    const treasuryState = await findTreasuryState(treasuryPubkey, programId);
+    const feeVault = await findFeeVault(treasuryPubkey, programId);
+    const playerStats = await findPlayerStats(payerAccount.publicKey, programId);
+    const rollHistory = await findRollHistory(treasuryPubkey, programId);
+    const oracle = await findOracle(treasuryPubkey, programId);
+
+    // The wager is known at commit so its worst case profit can be reserved
+    const wager_ba = longToByteArray(wager * LAMPORTS_PER_SOL);
+
+    // Results are 0-9999, a roll under N on a 1-100 die is a target of (N - 1) * 100
+    const kind_dice_under = 0;
+    const target = (roll_value - 1) * 100;
 
     // Send roll seed
     const gameAccount = new Account();
-    let space = 44;
+    let space = 226;
     console.log('space ', space.toString(), ' ****');
     let lamports = 4400;
     console.log('lamports ', lamports.toString(), ' ****');
@@ -504,6 +736,24 @@ export const sendTransactionSequence = async (
     sendTransaction(connection, payerAccount, payerAccount, gameAccount, gameFundAccount, transaction, [], true);
     console.log('sent tx1');
 
+    await sleep(500);
+    // Fund the game before the commit, which checks the fund holds the wager
+    let transactions3 = new Transaction();
+    console.log("Sending to game fund: ");
+    console.log(gameFundAccount.publicKey.toBase58());
+    transactions3.add(SystemProgram.transfer({
+        fromPubkey: wallet.publicKey,
+        toPubkey: gameFundAccount.publicKey,
+        lamports: wager * LAMPORTS_PER_SOL,
+    }));
+
+    setStatus(history, setHistory, wager_count, "funding");
+
+    console.log('user sending funds');
+    let id = await sendTransaction(connection, null, wallet, transactions3, null, [], [], false, true);
+    console.log('user sent funds');
+    console.log(id);
+
     await sleep(500);
     // Send command 0
     console.log('Sending command 0 to ', gameAccount.publicKey.toBase58());
@@ -513,9 +763,10 @@ export const sendTransactionSequence = async (
             {pubkey: sysvarClockPubKey, isSigner: false, isWritable: false},
             {pubkey: sysvarSlotHashesPubKey, isSigner: false, isWritable: false},
             {pubkey: gameFundAccount.publicKey, isSigner: false, isWritable: true},
-            {pubkey: treasuryPubkey, isSigner: true, isWritable: true}],
+            {pubkey: treasuryPubkey, isSigner: true, isWritable: true},
+            {pubkey: treasuryState, isSigner: false, isWritable: true}],
         programId,
-        data: Buffer.from([0, 5453445, roll_value]),
+        data: Buffer.from([0, 5453445, kind_dice_under, target & 0xff, target >> 8, 1].concat(wager_ba)),
     });
 
     setStatus(history, setHistory, wager_count, "setting reveal number");
@@ -529,26 +780,6 @@ export const sendTransactionSequence = async (
 
     await sleep(500);
 
-     // Send command 1
-    console.log('Sending command 1 to', gameAccount.publicKey.toBase58());
-    let transactions3 = new Transaction();
-
-    // Send game funds
-    console.log("Sending to game fund: ");
-    console.log(gameFundAccount.publicKey.toBase58());
-    transactions3.add(SystemProgram.transfer({
-        fromPubkey: wallet.publicKey,
-        toPubkey: gameFundAccount.publicKey,
-        lamports: wager * LAMPORTS_PER_SOL,
-    }));
-
-    setStatus(history, setHistory, wager_count, "funding");
-
-    console.log('user sending funds');
-    let id = await sendTransaction(connection, null, wallet, transactions3, null, [], [], false, true);
-    console.log('user sent funds');
-    console.log(id);
-
     // THIS TAKES TOO LONG (~13 seconds)
     // let options = {
     //   skipPreflight: true,
@@ -573,9 +804,14 @@ export const sendTransactionSequence = async (
             {pubkey: sysvarSlotHashesPubKey, isSigner: false, isWritable: false},
             {pubkey: gameFundAccount.publicKey, isSigner: true, isWritable: true},
             {pubkey: treasuryPubkey, isSigner: true, isWritable: true},
-            {pubkey: wallet.publicKey, isSigner: false, isWritable: true}],
+            {pubkey: wallet.publicKey, isSigner: false, isWritable: true},
+            {pubkey: treasuryState, isSigner: false, isWritable: true},
+            {pubkey: playerStats, isSigner: false, isWritable: true},
+            {pubkey: rollHistory, isSigner: false, isWritable: true},
+            {pubkey: oracle, isSigner: false, isWritable: true},
+            {pubkey: feeVault, isSigner: false, isWritable: true}],
         programId,
-        data: Buffer.from([1, 5453445, roll_value]),
+        data: Buffer.from([1, 5453445]),
     });
     console.log('sending command 1');
 
@@ -618,7 +854,7 @@ export const sendTransactionSequence = async (
       const rolled = info.data.slice(20, 28);
       const rolled_result = Int64ToString(rolled, false);
       let msg = "";
-      if (rolled_result < roll_value) {
+      if (parseInt(rolled_result) < target) {
           msg = " YOU WIN";
       } else {
           msg = " YOU LOSE";